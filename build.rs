@@ -0,0 +1,30 @@
+use std::env;
+use std::process::Command;
+
+#[path = "src/buildenv.rs"]
+mod buildenv;
+
+fn main() {
+    for var in ["TARGET", "OPT_LEVEL", "PROFILE"] {
+        let val = env::var(var).unwrap_or_else(|_| "unknown".to_owned());
+        println!("cargo:rustc-env=BUGSNAG_BUILD_{var}={val}");
+    }
+    println!("cargo:rustc-env=BUGSNAG_BUILD_LTO={}", buildenv::lto());
+    println!("cargo:rustc-env=BUGSNAG_BUILD_RUSTC={}", rustc_version());
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    println!("cargo:rerun-if-changed=src/buildenv.rs");
+    buildenv::rerun_if_lto_changed();
+}
+
+/// Returns the version of the compiler, e.g. `1.80.0` of `rustc 1.80.0 (051478957 2024-07-21)`.
+fn rustc_version() -> String {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|version| version.split_whitespace().nth(1).map(str::to_owned))
+        .unwrap_or_else(|| "unknown".to_owned())
+}
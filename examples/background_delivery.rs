@@ -0,0 +1,47 @@
+use bugsnag::{Bugsnag, Outcome};
+use std::time::Duration;
+
+fn handle_request(bugsnag: &mut Bugsnag, id: u32) {
+    // with background delivery, send only enqueues the serialized notification, so the
+    // request handler does not wait for the network
+    let res = bugsnag
+        .notify("Request::Failed", format!("request {id} failed"))
+        .send();
+
+    match res {
+        Ok(Outcome::Queued) => println!("request {id}: bug report queued"),
+        Ok(outcome) => println!("request {id}: bug report outcome: {outcome:?}"),
+        Err(e) => println!("request {id}: error queueing bug report: {e:?}"),
+    }
+}
+
+fn main() {
+    let mut bugsnag = Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+
+    bugsnag.set_app_info(
+        Some(env!("CARGO_PKG_VERSION")),
+        Some("development"),
+        Some("rust"),
+    );
+
+    // reports that can not be delivered by the worker thread, or that do not fit into
+    // the queue, are stored here and retransmitted by the worker thread while it is idle
+    bugsnag.use_offline_storage("./");
+
+    // up to 64 notifications wait for delivery, the offline storage is checked every minute
+    bugsnag.use_background_delivery(64, Duration::from_secs(60));
+
+    // deliver the queued reports before the process is terminated by SIGTERM or SIGINT
+    #[cfg(unix)]
+    bugsnag::signal::flush_on_termination(&bugsnag, Duration::from_secs(5))
+        .expect("failed to install signal handlers");
+
+    for id in 0..3 {
+        handle_request(&mut bugsnag, id);
+    }
+
+    // wait for the worker thread to deliver the reports before the process exits
+    if !bugsnag.flush(Duration::from_secs(5)) {
+        println!("not all bug reports were delivered");
+    }
+}
@@ -1,4 +1,4 @@
-use bugsnag::{Bugsnag, Severity};
+use bugsnag::{Bugsnag, Outcome, Severity};
 use std::num::ParseIntError;
 
 fn test() -> Result<i32, ParseIntError> {
@@ -33,8 +33,12 @@ fn main() {
             .severity(Severity::Error)
             .send();
 
-        if let Err(e) = res {
-            println!("error transmitting bug report: {e:?}");
+        match res {
+            Ok(Outcome::StoredOffline(path)) => {
+                println!("bug report stored for retransmission in {}", path.display())
+            }
+            Ok(outcome) => println!("bug report outcome: {outcome:?}"),
+            Err(e) => println!("error transmitting bug report: {e:?}"),
         }
     }
 
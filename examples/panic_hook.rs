@@ -21,6 +21,8 @@ fn init_bugsnag() {
         let res = bugsnag
             .notify("Panic", &message)
             .severity(Severity::Error)
+            // reports the panic payload in the "rust" tab
+            .panic(info)
             .send();
 
         if let Err(e) = res {
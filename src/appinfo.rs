@@ -1,8 +1,27 @@
+//! Module for the information about the app that is reported with every event.
+//!
+//! # Example
+//!
+//! ```
+//! use bugsnag::appinfo::AppInfo;
+//!
+//! let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! api.set_app(
+//!     AppInfo::builder()
+//!         .version(env!("CARGO_PKG_VERSION"))
+//!         .release_stage("production")
+//!         .build_uuid("5b0d7c8e-2f4a-4c3e-9a61-3d2f8e1b7a90")
+//!         .build(),
+//! );
+//! ```
+
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AppInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     version: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -10,16 +29,139 @@ pub struct AppInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "type")]
     atype: Option<String>,
+    /// Correlates the event with the debug symbols uploaded for the build.
+    #[serde(rename = "buildUUID", skip_serializing_if = "Option::is_none")]
+    build_uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_bundle_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binary_arch: Option<String>,
+    /// Milliseconds since the launch of the process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
 }
 
 impl AppInfo {
     pub fn new(version: Option<&str>, release_stage: Option<&str>, atype: Option<&str>) -> AppInfo {
         AppInfo {
+            id: None,
             version: version.map_or_else(|| None, |v| Some(v.to_owned())),
             release_stage: release_stage.map_or_else(|| None, |v| Some(v.to_owned())),
             atype: atype.map_or_else(|| None, |v| Some(v.to_owned())),
+            build_uuid: None,
+            code_bundle_id: None,
+            binary_arch: None,
+            duration: None,
         }
     }
+
+    /// Returns a builder for all fields of the app info. The binary architecture is
+    /// preset to the one of this build.
+    pub fn builder() -> AppInfoBuilder {
+        let mut info = AppInfo::new(None, None, None);
+        info.binary_arch = Some(binary_arch().to_owned());
+        AppInfoBuilder(info)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn set_version(&mut self, version: &str) {
+        self.version = Some(version.to_owned());
+    }
+
+    pub fn release_stage(&self) -> Option<&str> {
+        self.release_stage.as_deref()
+    }
+
+    pub fn set_release_stage(&mut self, release_stage: &str) {
+        self.release_stage = Some(release_stage.to_owned());
+    }
+
+    pub fn build_uuid(&self) -> Option<&str> {
+        self.build_uuid.as_deref()
+    }
+
+    pub fn code_bundle_id(&self) -> Option<&str> {
+        self.code_bundle_id.as_deref()
+    }
+
+    pub fn binary_arch(&self) -> Option<&str> {
+        self.binary_arch.as_deref()
+    }
+
+    pub fn duration(&self) -> Option<u64> {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: u64) {
+        self.duration = Some(duration);
+    }
+}
+
+/// Builder for an `AppInfo`, see `AppInfo::builder`.
+#[derive(Debug, Clone)]
+pub struct AppInfoBuilder(AppInfo);
+
+impl AppInfoBuilder {
+    /// Sets the unique identifier of the app, e.g. its package name.
+    pub fn id(mut self, id: &str) -> Self {
+        self.0.id = Some(id.to_owned());
+        self
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.0.version = Some(version.to_owned());
+        self
+    }
+
+    pub fn release_stage(mut self, release_stage: &str) -> Self {
+        self.0.release_stage = Some(release_stage.to_owned());
+        self
+    }
+
+    pub fn app_type(mut self, atype: &str) -> Self {
+        self.0.atype = Some(atype.to_owned());
+        self
+    }
+
+    /// Sets the unique identifier of the build, which Bugsnag uses to find the debug
+    /// symbols uploaded for it.
+    pub fn build_uuid(mut self, build_uuid: &str) -> Self {
+        self.0.build_uuid = Some(build_uuid.to_owned());
+        self
+    }
+
+    /// Sets the identifier of a bundle of code that is updated independently of the
+    /// binary, e.g. of scripts loaded at runtime.
+    pub fn code_bundle_id(mut self, code_bundle_id: &str) -> Self {
+        self.0.code_bundle_id = Some(code_bundle_id.to_owned());
+        self
+    }
+
+    /// Overrides the binary architecture, e.g. `arm64`.
+    pub fn binary_arch(mut self, binary_arch: &str) -> Self {
+        self.0.binary_arch = Some(binary_arch.to_owned());
+        self
+    }
+
+    pub fn build(self) -> AppInfo {
+        self.0
+    }
+}
+
+/// Returns the architecture of this build, named like by the other Bugsnag notifiers.
+fn binary_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        "arm" => "arm32",
+        arch => arch,
+    }
 }
 
 #[cfg(test)]
@@ -65,6 +207,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_appinfo_builder_to_json() {
+        let info = AppInfo::builder()
+            .id("com.example.shop")
+            .version("1.0.0")
+            .release_stage("test")
+            .app_type("rust")
+            .build_uuid("5b0d7c8e")
+            .code_bundle_id("bundle-7")
+            .binary_arch("arm64")
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            json!({
+                "id": "com.example.shop",
+                "version": "1.0.0",
+                "releaseStage": "test",
+                "type": "rust",
+                "buildUUID": "5b0d7c8e",
+                "codeBundleId": "bundle-7",
+                "binaryArch": "arm64"
+            })
+        );
+        assert!(AppInfo::builder().build().binary_arch().is_some());
+    }
+
+    #[test]
+    fn test_appinfo_with_duration_to_json() {
+        let mut info = AppInfo::new(None, None, Some("rust"));
+        info.set_duration(1500);
+
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            json!({
+                "type": "rust",
+                "duration": 1500
+            })
+        );
+    }
+
     #[test]
     fn test_appinfo_with_type_to_json() {
         let info = AppInfo::new(None, None, Some("rust"));
@@ -0,0 +1,615 @@
+//! Conversion of metadata to json with limits for the depth and the width.
+//!
+//! Subtrees that are nested deeper than the depth limit are replaced by a marker string
+//! without being serialized, so huge or recursive structures can neither create huge
+//! payloads nor overflow the stack. Sequences and maps keep the elements up to the width
+//! limit, followed by a marker with the number of omitted elements.
+
+use serde::ser::{self, Serialize, Serializer};
+use serde_json::Value;
+
+/// Key of the marker entry of maps and structs with omitted entries.
+const OMITTED_KEY: &str = "…";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub max_depth: usize,
+    pub max_width: usize,
+}
+
+/// Converts `value` to json like `serde_json::to_value`, but within `limits`.
+pub(crate) fn to_value<T: Serialize + ?Sized>(
+    value: &T,
+    limits: Limits,
+) -> Result<Value, serde_json::Error> {
+    Limited {
+        value,
+        depth: 0,
+        limits,
+    }
+    .serialize(serde_json::value::Serializer)
+}
+
+/// A value that is serialized at `depth`.
+struct Limited<'a, T: ?Sized> {
+    value: &'a T,
+    depth: usize,
+    limits: Limits,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Limited<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(LimitedSerializer {
+            inner: serializer,
+            depth: self.depth,
+            limits: self.limits,
+        })
+    }
+}
+
+/// Serializer that passes scalars to `inner` and limits compound values.
+struct LimitedSerializer<S> {
+    inner: S,
+    depth: usize,
+    limits: Limits,
+}
+
+impl<S> LimitedSerializer<S> {
+    fn wrap<'a, T: ?Sized>(&self, value: &'a T, depth: usize) -> Limited<'a, T> {
+        Limited {
+            value,
+            depth,
+            limits: self.limits,
+        }
+    }
+
+    fn len_hint(&self, len: Option<usize>) -> Option<usize> {
+        len.map(|len| len.min(self.limits.max_width + 1))
+    }
+}
+
+/// A compound value, which is either serialized or replaced by a marker because it is
+/// nested too deep.
+enum Compound<S, C> {
+    Open {
+        inner: C,
+        depth: usize,
+        limits: Limits,
+        len: usize,
+        omitted: usize,
+        /// Set if the key of an omitted map entry was passed to `serialize_key`.
+        omitting_value: bool,
+    },
+    Truncated {
+        serializer: S,
+        kind: &'static str,
+        len: Option<usize>,
+    },
+}
+
+impl<S: Serializer, C> Compound<S, C> {
+    fn open(
+        serializer: LimitedSerializer<S>,
+        kind: &'static str,
+        len: Option<usize>,
+        open: impl FnOnce(S) -> Result<C, S::Error>,
+    ) -> Result<Self, S::Error> {
+        if serializer.depth >= serializer.limits.max_depth {
+            return Ok(Compound::Truncated {
+                serializer: serializer.inner,
+                kind,
+                len,
+            });
+        }
+
+        Ok(Compound::Open {
+            inner: open(serializer.inner)?,
+            depth: serializer.depth,
+            limits: serializer.limits,
+            len: 0,
+            omitted: 0,
+            omitting_value: false,
+        })
+    }
+
+    /// Returns the element limited to the depth of the children, or `None` if the width
+    /// limit is reached or the compound is truncated.
+    fn child<'a, T: ?Sized>(&mut self, value: &'a T) -> Option<(&mut C, Limited<'a, T>)> {
+        if let Compound::Open {
+            len,
+            omitted,
+            limits,
+            ..
+        } = self
+        {
+            if *len >= limits.max_width {
+                *omitted += 1;
+                return None;
+            }
+            *len += 1;
+        }
+        self.value(value)
+    }
+
+    /// Returns the value of a map entry whose key was passed to `serialize_key`, limited
+    /// to the depth of the children, or `None` if the entry is omitted.
+    fn value<'a, T: ?Sized>(&mut self, value: &'a T) -> Option<(&mut C, Limited<'a, T>)> {
+        match self {
+            Compound::Open {
+                inner,
+                depth,
+                limits,
+                omitting_value: false,
+                ..
+            } => {
+                let child = Limited {
+                    value,
+                    depth: *depth + 1,
+                    limits: *limits,
+                };
+                Some((inner, child))
+            }
+            Compound::Open { omitting_value, .. } => {
+                *omitting_value = false;
+                None
+            }
+            Compound::Truncated { .. } => None,
+        }
+    }
+
+    /// Ends the compound, with `end` for a serialized one and a marker for a truncated one.
+    fn end(self, end: impl FnOnce(C, usize) -> Result<S::Ok, S::Error>) -> Result<S::Ok, S::Error> {
+        match self {
+            Compound::Open { inner, omitted, .. } => end(inner, omitted),
+            Compound::Truncated {
+                serializer,
+                kind,
+                len,
+            } => match len {
+                Some(len) => {
+                    serializer.serialize_str(&format!("[{kind} of {len} nested too deep]"))
+                }
+                None => serializer.serialize_str(&format!("[{kind} nested too deep]")),
+            },
+        }
+    }
+}
+
+fn omitted_marker(omitted: usize) -> String {
+    format!("[{omitted} more omitted]")
+}
+
+macro_rules! forward_scalars {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for LimitedSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S, S::SerializeSeq>;
+    type SerializeTuple = Compound<S, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<S, S::SerializeMap>;
+    type SerializeStruct = Compound<S, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S, S::SerializeStructVariant>;
+
+    forward_scalars! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value, self.depth);
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value, self.depth);
+        self.inner.serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        // serialized as a map with a single entry
+        if self.depth >= self.limits.max_depth {
+            return self
+                .inner
+                .serialize_str(&format!("[{variant} nested too deep]"));
+        }
+        let value = self.wrap(value, self.depth + 1);
+        self.inner
+            .serialize_newtype_variant(name, index, variant, &value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let hint = self.len_hint(len);
+        Compound::open(self, "sequence", len, |s| s.serialize_seq(hint))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Compound::open(self, "tuple", Some(len), |s| s.serialize_tuple(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Compound::open(self, name, Some(len), |s| {
+            s.serialize_tuple_struct(name, len)
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Compound::open(self, variant, Some(len), |s| {
+            s.serialize_tuple_variant(name, index, variant, len)
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let hint = self.len_hint(len);
+        Compound::open(self, "map", len, |s| s.serialize_map(hint))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Compound::open(self, name, None, |s| s.serialize_struct(name, len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Compound::open(self, variant, None, |s| {
+            s.serialize_struct_variant(name, index, variant, len)
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<S: Serializer> ser::SerializeSeq for Compound<S, S::SerializeSeq> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_element(&child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_element(&omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+impl<S: Serializer> ser::SerializeTuple for Compound<S, S::SerializeTuple> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_element(&child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_element(&omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+impl<S: Serializer> ser::SerializeTupleStruct for Compound<S, S::SerializeTupleStruct> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_field(&child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_field(&omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+impl<S: Serializer> ser::SerializeTupleVariant for Compound<S, S::SerializeTupleVariant> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_field(&child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_field(&omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+impl<S: Serializer> ser::SerializeMap for Compound<S, S::SerializeMap> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+        match self.child(key) {
+            Some((inner, _)) => inner.serialize_key(key),
+            None => {
+                if let Compound::Open { omitting_value, .. } = self {
+                    *omitting_value = true;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        match self.value(value) {
+            Some((inner, child)) => inner.serialize_value(&child),
+            None => Ok(()),
+        }
+    }
+
+    fn serialize_entry<K, V>(&mut self, key: &K, value: &V) -> Result<(), S::Error>
+    where
+        K: Serialize + ?Sized,
+        V: Serialize + ?Sized,
+    {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_entry(key, &child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_entry(OMITTED_KEY, &omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+impl<S: Serializer> ser::SerializeStruct for Compound<S, S::SerializeStruct> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_field(key, &child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_field(OMITTED_KEY, &omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+impl<S: Serializer> ser::SerializeStructVariant for Compound<S, S::SerializeStructVariant> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        match self.child(value) {
+            Some((inner, child)) => inner.serialize_field(key, &child),
+            None => Ok(()),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        Compound::end(self, |mut inner, omitted| {
+            if omitted > 0 {
+                inner.serialize_field(OMITTED_KEY, &omitted_marker(omitted))?;
+            }
+            inner.end()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_value, Limits};
+    use serde::Serialize;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    const LIMITS: Limits = Limits {
+        max_depth: 2,
+        max_width: 3,
+    };
+
+    #[derive(Serialize)]
+    struct Node {
+        name: &'static str,
+        children: Vec<Node>,
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let tree = Node {
+            name: "root",
+            children: vec![Node {
+                name: "child",
+                children: vec![Node {
+                    name: "grandchild",
+                    children: Vec::new(),
+                }],
+            }],
+        };
+
+        assert_eq!(
+            to_value(&tree, LIMITS).unwrap(),
+            json!({
+                "name": "root",
+                "children": [
+                    "[Node nested too deep]"
+                ]
+            })
+        );
+        assert_eq!(
+            to_value(&json!({"a": {"b": [1, 2]}}), LIMITS).unwrap(),
+            json!({"a": {"b": "[sequence of 2 nested too deep]"}})
+        );
+    }
+
+    #[test]
+    fn test_width_limit() {
+        let map: BTreeMap<u32, u32> = (0..5).map(|i| (i, i)).collect();
+
+        assert_eq!(
+            to_value(&(0..10).collect::<Vec<_>>(), LIMITS).unwrap(),
+            json!([0, 1, 2, "[7 more omitted]"])
+        );
+        assert_eq!(
+            to_value(&map, LIMITS).unwrap(),
+            json!({"0": 0, "1": 1, "2": 2, "…": "[2 more omitted]"})
+        );
+    }
+
+    #[test]
+    fn test_map_key_value() {
+        use serde::ser::{SerializeMap, Serializer};
+
+        struct Pairs;
+
+        impl Serialize for Pairs {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(None)?;
+                for key in ["a", "b", "c", "d"] {
+                    map.serialize_key(key)?;
+                    map.serialize_value(&[[key]])?;
+                }
+                map.end()
+            }
+        }
+
+        assert_eq!(
+            to_value(&Pairs, LIMITS).unwrap(),
+            json!({
+                "a": ["[tuple of 1 nested too deep]"],
+                "b": ["[tuple of 1 nested too deep]"],
+                "c": ["[tuple of 1 nested too deep]"],
+                "…": "[1 more omitted]"
+            })
+        );
+    }
+
+    #[test]
+    fn test_within_limits() {
+        let value = json!({"user": {"id": 1, "tags": ["a", "b"]}, "flag": null});
+
+        assert_eq!(
+            to_value(
+                &value,
+                Limits {
+                    max_depth: 3,
+                    max_width: 3
+                }
+            )
+            .unwrap(),
+            value
+        );
+    }
+}
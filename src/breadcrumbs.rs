@@ -0,0 +1,148 @@
+//! Module for breadcrumbs, the events that led up to an error.
+
+use super::redact;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Default number of breadcrumbs that are kept.
+pub const DEFAULT_MAX_BREADCRUMBS: usize = 50;
+
+/// The type of a breadcrumb, which determines how it is displayed by Bugsnag.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreadcrumbType {
+    Navigation,
+    Request,
+    Process,
+    Log,
+    User,
+    State,
+    Error,
+    Manual,
+}
+
+/// An event that happened before an error.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Breadcrumb {
+    timestamp: DateTime<Utc>,
+    name: String,
+    #[serde(rename = "type")]
+    btype: BreadcrumbType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta_data: Option<serde_json::Value>,
+}
+
+impl Breadcrumb {
+    pub fn new(
+        name: &str,
+        btype: BreadcrumbType,
+        meta_data: Option<serde_json::Value>,
+    ) -> Breadcrumb {
+        Breadcrumb {
+            timestamp: Utc::now(),
+            name: name.to_owned(),
+            btype,
+            meta_data,
+        }
+    }
+
+    /// Replaces the values of the sensitive keys in the metadata, see `redact::redact`.
+    pub fn redact(&mut self, keys: &[String]) {
+        if let Some(serde_json::Value::Object(map)) = &mut self.meta_data {
+            redact::redact(map, keys);
+        }
+    }
+}
+
+/// Ring buffer of the most recent breadcrumbs.
+#[derive(Debug)]
+pub struct Breadcrumbs {
+    max: usize,
+    crumbs: VecDeque<Breadcrumb>,
+}
+
+impl Default for Breadcrumbs {
+    fn default() -> Breadcrumbs {
+        Breadcrumbs::new(DEFAULT_MAX_BREADCRUMBS)
+    }
+}
+
+impl Breadcrumbs {
+    pub fn new(max: usize) -> Breadcrumbs {
+        Breadcrumbs {
+            max,
+            crumbs: VecDeque::with_capacity(max),
+        }
+    }
+
+    /// Adds a breadcrumb, dropping the oldest one if the buffer is full.
+    pub fn push(&mut self, crumb: Breadcrumb) {
+        if self.max == 0 {
+            return;
+        }
+        while self.crumbs.len() >= self.max {
+            self.crumbs.pop_front();
+        }
+        self.crumbs.push_back(crumb);
+    }
+
+    pub fn set_max(&mut self, max: usize) {
+        self.max = max;
+        while self.crumbs.len() > max {
+            self.crumbs.pop_front();
+        }
+    }
+
+    /// Returns the breadcrumbs, oldest first.
+    pub fn to_vec(&self) -> Vec<Breadcrumb> {
+        self.crumbs.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Breadcrumb, BreadcrumbType, Breadcrumbs};
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    #[test]
+    fn test_breadcrumb_to_json() {
+        let mut crumb = Breadcrumb::new(
+            "config loaded",
+            BreadcrumbType::State,
+            Some(json!({"file": "app.toml"})),
+        );
+        crumb.timestamp = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&crumb).unwrap(),
+            json!({
+                "timestamp": "2024-05-01T12:30:00Z",
+                "name": "config loaded",
+                "type": "state",
+                "metaData": {"file": "app.toml"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_breadcrumbs_ring_buffer() {
+        let mut crumbs = Breadcrumbs::new(2);
+        crumbs.push(Breadcrumb::new("1", BreadcrumbType::Manual, None));
+        crumbs.push(Breadcrumb::new("2", BreadcrumbType::Manual, None));
+        crumbs.push(Breadcrumb::new("3", BreadcrumbType::Manual, None));
+
+        let names = crumbs
+            .to_vec()
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["2", "3"]);
+
+        crumbs.set_max(1);
+        assert_eq!(crumbs.to_vec().len(), 1);
+        assert_eq!(crumbs.to_vec()[0].name, "3");
+    }
+}
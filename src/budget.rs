@@ -0,0 +1,151 @@
+//! Module for limiting the number of notifications per error class and app version.
+
+use log::info;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A key of `Bugsnag::notify_once`.
+#[derive(Debug, Default)]
+struct OnceKey {
+    hits: u64,
+    reported: bool,
+}
+
+/// The keys of `Bugsnag::notify_once` in this process.
+static ONCE_KEYS: OnceLock<Mutex<HashMap<String, OnceKey>>> = OnceLock::new();
+
+fn once_keys() -> std::sync::MutexGuard<'static, HashMap<String, OnceKey>> {
+    ONCE_KEYS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Counts a hit of `key`. Returns the number of hits including this one, or `None` if a
+/// notification with the key was already reported.
+pub fn hit_once_key(key: &str) -> Option<u64> {
+    let mut keys = once_keys();
+    let once = keys.entry(key.to_owned()).or_default();
+    once.hits += 1;
+    (!once.reported).then_some(once.hits)
+}
+
+/// Marks `key` as reported. Returns false if a notification with the key was reported
+/// in the meantime.
+pub fn report_once_key(key: &str) -> bool {
+    let mut keys = once_keys();
+    let once = keys.entry(key.to_owned()).or_default();
+    !std::mem::replace(&mut once.reported, true)
+}
+
+/// Returns the number of hits of `key`.
+pub fn once_key_hits(key: &str) -> u64 {
+    once_keys().get(key).map_or(0, |once| once.hits)
+}
+
+/// Counts the notifications of each error class for the current app version.
+#[derive(Debug, Default)]
+pub struct ClassBudgets {
+    version: Option<String>,
+    counts: HashMap<String, u32>,
+}
+
+impl ClassBudgets {
+    /// Takes one notification from the budget of `class`. Returns false if the budget
+    /// of `max` notifications is used up. The counts start over when the app version
+    /// changes.
+    pub fn take(&mut self, version: Option<&str>, class: &str, max: u32) -> bool {
+        if self.version.as_deref() != version {
+            self.version = version.map(|v| v.to_owned());
+            self.counts.clear();
+        }
+
+        let count = self.counts.entry(class.to_owned()).or_insert(0);
+        if *count >= max {
+            return false;
+        }
+
+        *count += 1;
+        if *count == max {
+            info!("Budget of error class {class} used up, further notifications are muted.");
+        }
+        true
+    }
+}
+
+/// Limits the number of stored reports that are retransmitted per time window.
+#[derive(Debug)]
+pub struct RetryBudget {
+    max: u32,
+    window: Duration,
+    start: Instant,
+    used: u32,
+}
+
+impl RetryBudget {
+    pub fn new(max: u32, window: Duration) -> RetryBudget {
+        RetryBudget {
+            max,
+            window,
+            start: Instant::now(),
+            used: 0,
+        }
+    }
+
+    /// Takes one report from the budget. Returns false if `max` reports were taken in the
+    /// current window.
+    pub fn take(&mut self) -> bool {
+        if self.start.elapsed() >= self.window {
+            self.start = Instant::now();
+            self.used = 0;
+        }
+        if self.used >= self.max {
+            return false;
+        }
+        self.used += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hit_once_key, once_key_hits, report_once_key, ClassBudgets, RetryBudget};
+    use std::time::Duration;
+
+    #[test]
+    fn test_class_budgets() {
+        let mut budgets = ClassBudgets::default();
+
+        assert!(budgets.take(Some("1.0"), "IoError", 2));
+        assert!(budgets.take(Some("1.0"), "IoError", 2));
+        assert!(!budgets.take(Some("1.0"), "IoError", 2));
+        assert!(budgets.take(Some("1.0"), "ParseError", 2));
+
+        assert!(budgets.take(Some("1.1"), "IoError", 2));
+    }
+
+    #[test]
+    fn test_retry_budget() {
+        let mut budget = RetryBudget::new(2, Duration::from_millis(50));
+
+        assert!(budget.take());
+        assert!(budget.take());
+        assert!(!budget.take());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(budget.take());
+    }
+
+    #[test]
+    fn test_once_keys() {
+        let key = format!("degraded-{}", uuid::Uuid::new_v4());
+        assert_eq!(once_key_hits(&key), 0);
+        assert_eq!(hit_once_key(&key), Some(1));
+        assert_eq!(hit_once_key(&key), Some(2));
+        assert!(report_once_key(&key));
+        assert!(!report_once_key(&key));
+        assert_eq!(hit_once_key(&key), None);
+        assert_eq!(once_key_hits(&key), 3);
+    }
+}
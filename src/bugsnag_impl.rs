@@ -2,18 +2,46 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use super::{appinfo, deviceinfo, event, exception, notification, stacktrace, user};
+use super::{
+    appinfo, bounded, breadcrumbs, budget, buildid, clock, coalesce, config, console, deviceinfo,
+    diagnostics, discard, envelope, event, exception, hashing, notification, panic, pending,
+    plugin, pseudonym, queue, redact, rustinfo, stacktrace, threads, trace, transport, user,
+};
+#[cfg(feature = "http")]
+use super::{instrumentation, receipts};
 
-use log::info;
+use log::{info, warn};
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::error::Error as StdError;
 use std::fmt;
-use std::fs::DirEntry;
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "http")]
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 
 const NOTIFY_URL: &str = "https://notify.bugsnag.com";
-const OFFLINE_REPORT_PREFIX: &str = "bugsnag_report";
+const SESSIONS_URL: &str = "https://sessions.bugsnag.com";
+/// Name of the internal metadata tab, see `Bugsnag::set_internal_tab`.
+pub(crate) const DEFAULT_INTERNAL_TAB: &str = "bugsnag-rs";
+/// Maximum length in bytes of messages created with `NotifyBuilder::message_with`.
+const MAX_MESSAGE_LEN: usize = 4096;
+/// Default maximum number of causes that are reported as exceptions.
+const DEFAULT_MAX_CAUSE_DEPTH: usize = 10;
+const DEFAULT_METADATA_LIMITS: bounded::Limits = bounded::Limits {
+    max_depth: 16,
+    max_width: 500,
+};
+/// Capacity of the delivery queue of `Bugsnag::start`.
+const START_QUEUE_CAPACITY: usize = 64;
+/// Interval in which `Bugsnag::start` retransmits reports from the offline storage.
+const START_RETRY_INTERVAL: Duration = Duration::from_secs(60);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     /// The conversion to json failed.
     JsonConversionFailed,
@@ -24,6 +52,12 @@ pub enum Error {
     JsonTransferAndStorageFailed,
     /// No storage has been specified or could not be read
     OfflineStorageError,
+    /// The email address of a user is malformed.
+    InvalidUserEmail,
+    /// An error class does not follow the `Namespace::Kind` convention.
+    InvalidErrorClass,
+    /// Bugsnag responded, but did not accept the json.
+    Rejected(DeliveryStatus),
 }
 
 impl fmt::Display for Error {
@@ -44,11 +78,114 @@ impl StdError for Error {
             },
             Error::OfflineStorageError => {
                 "reading from / writing to offline storage failed"
-            }
+            },
+            Error::InvalidUserEmail => "the email address of the user is malformed",
+            Error::InvalidErrorClass => "the error class does not follow the `Namespace::Kind` convention",
+            Error::Rejected(_) => "Bugsnag did not accept the json",
+        }
+    }
+}
+
+/// The response of Bugsnag to a delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// The notification was accepted.
+    Delivered,
+    /// The notification was rejected as invalid, e.g. because of a malformed payload or
+    /// an unknown API key. Sending it again does not help.
+    RejectedBadRequest,
+    /// Too many notifications were sent, the notification may be sent again after
+    /// `retry_after` if Bugsnag told it.
+    RateLimited { retry_after: Option<Duration> },
+    /// Bugsnag failed to process the notification, it may be sent again later.
+    ServerError,
+}
+
+impl DeliveryStatus {
+    /// Classifies a response by its status code and the value of its `Retry-After` header.
+    #[cfg(feature = "http")]
+    pub(crate) fn from_response(status: u16, retry_after: Option<&str>) -> DeliveryStatus {
+        match status {
+            200..=299 => DeliveryStatus::Delivered,
+            429 => DeliveryStatus::RateLimited {
+                retry_after: retry_after
+                    .and_then(|secs| secs.trim().parse().ok())
+                    .map(Duration::from_secs),
+            },
+            500..=599 => DeliveryStatus::ServerError,
+            _ => DeliveryStatus::RejectedBadRequest,
+        }
+    }
+}
+
+/// The result of a delivery attempt, independent of the HTTP client that made it.
+#[cfg(feature = "http")]
+enum AttemptResult {
+    Response {
+        status: u16,
+        retry_after: Option<String>,
+    },
+    Failed {
+        connect: bool,
+        error: String,
+    },
+}
+
+#[cfg(feature = "http")]
+impl AttemptResult {
+    fn new(status: reqwest::StatusCode, headers: &reqwest::header::HeaderMap) -> AttemptResult {
+        AttemptResult::Response {
+            status: status.as_u16(),
+            retry_after: headers
+                .get("Retry-After")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+        }
+    }
+
+    fn failed(e: &reqwest::Error) -> AttemptResult {
+        AttemptResult::Failed {
+            connect: e.is_connect(),
+            error: e.to_string(),
         }
     }
 }
 
+/// Describes what happened to a notification when it was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The notification was delivered to Bugsnag.
+    Delivered,
+    /// The notification was handed to the background delivery queue.
+    Queued,
+    /// The delivery failed or the queue was full and the notification was written to
+    /// the offline storage at the given path, to be retransmitted later.
+    StoredOffline(PathBuf),
+    /// The notification was suppressed, because the rate limit was reached.
+    SuppressedByRateLimit,
+    /// The notification was discarded, because notifications are disabled or the
+    /// notification was filtered.
+    DiscardedByFilter,
+    /// The notification was dropped by sampling.
+    Sampled,
+    /// The budget of the error class is used up.
+    SuppressedByClassBudget,
+    /// The notification was converted into a breadcrumb.
+    ConvertedToBreadcrumb,
+    /// The notification was printed to the console instead of being sent.
+    WrittenToConsole,
+    /// The notification was handed to the capture callback instead of being sent.
+    Captured,
+    /// A notification with the same key was already send by `Bugsnag::notify_once`.
+    SuppressedOnce,
+    /// An identical notification was sent at the same time, which counts this one in
+    /// its `coalesced` tab.
+    Coalesced,
+    /// The release stage is not one of the stages set with
+    /// `Bugsnag::set_notify_release_stages`.
+    SuppressedByReleaseStage,
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum Severity {
@@ -88,10 +225,17 @@ impl RateLimitNotificationOptions {
 pub struct RateLimit {
     persistence_file: PathBuf,
     limits: Vec<SendLimit>,
+    #[serde(default)]
     sent_notifications: Vec<DateTime<Utc>>,
+    #[serde(default)]
     triggered: bool,
 
     notification_options: Option<RateLimitNotificationOptions>,
+
+    /// The sent notifications of a rate limit without persistence file, shared by its
+    /// clones.
+    #[serde(skip)]
+    memory: Option<Arc<Mutex<Vec<DateTime<Utc>>>>>,
 }
 
 impl RateLimit {
@@ -106,6 +250,7 @@ impl RateLimit {
             sent_notifications: Vec::new(),
             triggered: false,
             notification_options,
+            memory: None,
         };
 
         let from_file = res.read_from_file();
@@ -122,25 +267,124 @@ impl RateLimit {
         }
     }
 
+    /// Creates a rate limit that is kept in memory instead of a persistence file, so it
+    /// applies to this process only and is reset when the process restarts.
+    pub fn in_memory(
+        limits: Vec<SendLimit>,
+        notification_options: Option<RateLimitNotificationOptions>,
+    ) -> RateLimit {
+        RateLimit {
+            persistence_file: PathBuf::new(),
+            limits,
+            sent_notifications: Vec::new(),
+            triggered: false,
+            notification_options,
+            memory: Some(Arc::new(Mutex::new(Vec::new()))),
+        }
+    }
+
+    /// Synchronizes the rate limit with its persistence file. Rate limits kept in memory
+    /// are already up to date.
+    fn synchronized(self) -> RateLimit {
+        match self.memory {
+            Some(_) => self,
+            None => RateLimit::new(
+                self.limits,
+                self.persistence_file,
+                self.notification_options,
+            ),
+        }
+    }
+
+    /// Returns the rate limit without the notifications sent so far, i.e. only its
+    /// configuration.
+    fn without_state(mut self) -> RateLimit {
+        self.sent_notifications.clear();
+        self.triggered = false;
+        self
+    }
+
     fn register_notification(&mut self) {
+        if let Some(memory) = self.memory.clone() {
+            let mut sent = memory.lock().unwrap_or_else(PoisonError::into_inner);
+            self.sent_notifications = std::mem::take(&mut *sent);
+            self.register_in_memory();
+
+            // notifications outside of all windows are no longer needed
+            let longest = self.limits.iter().map(|l| l.duration).max();
+            let now = Utc::now();
+            sent.extend(self.sent_notifications.iter().copied().filter(|sent| {
+                longest.is_some_and(|longest| {
+                    now.signed_duration_since(*sent).num_milliseconds() < longest.as_millis() as i64
+                })
+            }));
+            return;
+        }
+
+        // the persistence file is locked while it is read and written, so processes
+        // sharing it register their notifications one after another and only one
+        // of them triggers the rate limit notification
+
+        let mut file = match self.lock_file() {
+            Ok(file) => file,
+            Err(e) => {
+                warn!(
+                    "failed to lock RateLimit file {}, registering notification in memory: {e}",
+                    self.persistence_file.display()
+                );
+                self.register_in_memory();
+                return;
+            }
+        };
+
         // load from persistence file
 
-        let from_file = self.read_from_file();
-        self.limits = from_file.limits;
-        self.sent_notifications = from_file.sent_notifications;
-        self.triggered = from_file.triggered;
+        let mut json = String::new();
+        if file.read_to_string(&mut json).is_ok() {
+            if let Ok(from_file) = serde_json::from_str::<RateLimit>(&json) {
+                self.limits = from_file.limits;
+                self.sent_notifications = from_file.sent_notifications;
+                self.triggered = from_file.triggered;
+            }
+        }
 
         // register notification
 
+        self.register_in_memory();
+
+        // save to persistence file, the lock is released when the file is closed
+
+        let json = serde_json::to_string(&self).expect("failed to serialize RateLimit");
+        let res = file
+            .set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(json.as_bytes()));
+        if let Err(e) = res {
+            warn!(
+                "failed to write RateLimit to {}: {e}",
+                self.persistence_file.display()
+            );
+        }
+    }
+
+    fn register_in_memory(&mut self) {
         let prev_reached = self.reached();
         self.sent_notifications.push(Utc::now());
         let now_reached = self.reached();
 
         self.triggered = now_reached && !prev_reached;
+    }
 
-        // save to persistence file
-
-        self.write_to_file();
+    /// Opens the persistence file and locks it exclusively.
+    fn lock_file(&self) -> std::io::Result<File> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.persistence_file)?;
+        fs2::FileExt::lock_exclusive(&file)?;
+        Ok(file)
     }
 
     fn read_from_file(&mut self) -> Self {
@@ -177,6 +421,37 @@ impl RateLimit {
         self.triggered
     }
 
+    /// Returns how many notifications were sent within the window of each limit,
+    /// according to the persistence file or the memory.
+    fn usage(&mut self) -> Vec<diagnostics::RateLimitUsage> {
+        let current = match &self.memory {
+            Some(memory) => RateLimit {
+                sent_notifications: memory
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+                ..self.clone()
+            },
+            None => self.read_from_file(),
+        };
+        current
+            .limits
+            .iter()
+            .map(|limit| diagnostics::RateLimitUsage {
+                window: limit.duration,
+                limit: limit.limit,
+                sent: current
+                    .sent_notifications
+                    .iter()
+                    .filter(|sent| {
+                        Utc::now().signed_duration_since(**sent).num_milliseconds()
+                            < limit.duration.as_millis() as i64
+                    })
+                    .count() as u32,
+            })
+            .collect()
+    }
+
     fn reached(&mut self) -> bool {
         for limit in &self.limits {
             let sent_in_duration = self
@@ -197,50 +472,244 @@ impl RateLimit {
     }
 }
 
+type FrameRewriterFn = dyn Fn(&mut Vec<stacktrace::Frame>) + Send + Sync;
+
+/// Callback of `NotifyBuilder::on_before_send`.
+type BeforeSendFn = Box<dyn FnOnce(&mut plugin::Event) -> bool + Send>;
+
+/// Callback that rewrites the frames of a notification before it is sent.
+#[derive(Clone)]
+struct FrameRewriter(Arc<FrameRewriterFn>);
+
+impl fmt::Debug for FrameRewriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FrameRewriter")
+    }
+}
+
+/// The plugins registered with `Bugsnag::register_plugin`, in order of registration.
+#[derive(Clone, Default)]
+struct Plugins(Vec<Arc<dyn plugin::Plugin>>);
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|plugin| plugin.name()))
+            .finish()
+    }
+}
+
+type OnErrorFn = dyn Fn(&mut plugin::Event) -> bool + Send + Sync;
+
+/// The callbacks added with `Bugsnag::add_on_error`, in order of addition.
+#[derive(Clone, Default)]
+struct OnErrorCallbacks(Vec<Arc<OnErrorFn>>);
+
+impl fmt::Debug for OnErrorCallbacks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OnErrorCallbacks({})", self.0.len())
+    }
+}
+
+/// Callback that receives the serialized notifications instead of the HTTP transport.
+#[derive(Clone)]
+struct CaptureCallback(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl fmt::Debug for CaptureCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CaptureCallback")
+    }
+}
+
+/// Callback that provides a value when a notification is created.
+#[derive(Clone)]
+struct Provider<T>(Arc<dyn Fn() -> Option<T> + Send + Sync>);
+
+impl<T> fmt::Debug for Provider<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Provider")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Bugsnag {
     api_key: String,
     device_info: deviceinfo::DeviceInfo,
     app_info: Option<appinfo::AppInfo>,
     user: Option<user::User>,
+    user_provider: Option<Provider<user::User>>,
+    device_user: Option<user::User>,
+    device_user_provider: Option<Provider<user::User>>,
+    app_version_provider: Option<Provider<String>>,
     project_source_dir: String,
+    project_packages: Vec<String>,
+    notify_release_stages: Option<Vec<String>>,
+    discard_classes: discard::DiscardClasses,
     offline_storage: Option<String>,
-    rate_limit: Option<RateLimit>,
+    config: Arc<RwLock<config::Config>>,
+    transport_options: transport::TransportOptions,
+    #[cfg(feature = "http")]
+    client: Arc<OnceLock<reqwest::blocking::Client>>,
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    async_client: Arc<OnceLock<reqwest::Client>>,
+    notify_endpoint: String,
+    sessions_endpoint: String,
+    context: Option<String>,
+    context_provider: Option<Provider<String>>,
+    metadata: Option<serde_json::Value>,
+    metadata_sections: serde_json::Map<String, serde_json::Value>,
+    dependencies: Option<serde_json::Value>,
+    build_info: Option<serde_json::Value>,
+    queue: Option<Arc<queue::DeliveryQueue>>,
+    breadcrumbs: Arc<Mutex<breadcrumbs::Breadcrumbs>>,
+    class_budgets: Arc<Mutex<budget::ClassBudgets>>,
+    retry_budget: Option<Arc<Mutex<budget::RetryBudget>>>,
+    console_output: bool,
+    capture: Option<CaptureCallback>,
+    stable_grouping: bool,
+    max_cause_depth: usize,
+    metadata_limits: bounded::Limits,
+    receipts: Option<PathBuf>,
+    symbolication_data: bool,
+    source_snippets: bool,
+    frame_rewriter: Option<FrameRewriter>,
+    path_prefix_rewrites: Vec<(String, String)>,
+    plugins: Plugins,
+    on_error: OnErrorCallbacks,
+    pseudonymizer: Option<pseudonym::Pseudonymizer>,
+    coalescer: Option<Arc<coalesce::Coalescer>>,
+    pending: Option<pending::PendingErrors>,
+    internal_tab: Option<String>,
+}
+
+thread_local! {
+    /// Set while the current thread sends a notification.
+    static NOTIFYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as sending a notification, to detect notifications that are
+/// triggered while sending another one, e.g. by a panic hook or a Drop implementation.
+struct NotifyGuard {
+    reentrant: bool,
+}
+
+impl NotifyGuard {
+    fn enter() -> NotifyGuard {
+        NotifyGuard {
+            reentrant: NOTIFYING.with(|n| n.replace(true)),
+        }
+    }
+}
+
+impl Drop for NotifyGuard {
+    fn drop(&mut self) {
+        if !self.reentrant {
+            NOTIFYING.with(|n| n.set(false));
+        }
+    }
 }
 
 /// Builder for creating the notification that will be send to Bugsnag.
 /// If the object is dropped, the notification is send to Bugsnag.
+///
+/// The builder is `Send`. Use `into_owned` to get a builder that can be moved to another
+/// thread or task for delivery.
 pub struct NotifyBuilder<'a, 'bugsnag> {
-    bugsnag: &'bugsnag Bugsnag,
-    error_class: &'a str,
-    message: &'a str,
-    send_executed: bool,
-    methods_to_ignore: Option<&'a [&'a str]>,
-    context: Option<&'a str>,
+    bugsnag: Cow<'bugsnag, Bugsnag>,
+    error_class: Cow<'a, str>,
+    message: Cow<'a, str>,
+    message_fn: Option<Box<dyn FnOnce() -> String + Send + 'a>>,
+    before_send: Option<BeforeSendFn>,
+    result: Option<Result<Outcome, Error>>,
+    offline_only: bool,
+    transferred: bool,
+    methods_to_ignore: Option<Vec<String>>,
+    context: Option<Cow<'a, str>>,
     metadata: Option<serde_json::Value>,
     severity: Option<Severity>,
-    grouping_hash: Option<&'a str>,
+    grouping_hash: Option<Cow<'a, str>>,
     rate_limit: Option<RateLimit>,
+    panic_payload_type: Option<&'static str>,
+    tabs: serde_json::Map<String, serde_json::Value>,
+    occurred: DateTime<Utc>,
+    occurred_monotonic: clock::Reading,
+    app_version: Option<String>,
+    user: Option<user::User>,
+    device_user: Option<user::User>,
+    provided_context: Option<String>,
+    deadline: Option<Instant>,
+    backtrace: backtrace::Backtrace,
+    std_backtrace: Option<String>,
+    frames: Option<Vec<stacktrace::Frame>>,
+    causes: Vec<(String, String)>,
+    call_site: Option<stacktrace::Frame>,
+    once_key: Option<String>,
+    /// The key of the notification if it is the first of coalesced notifications.
+    coalesce_key: Option<String>,
+    unhandled_override: Option<(bool, String)>,
+    threads: Vec<threads::RawThread>,
+    event_id: String,
 }
 
 impl<'a, 'bugsnag> NotifyBuilder<'a, 'bugsnag> {
     fn new(
         bugsnag: &'bugsnag Bugsnag,
-        error_class: &'a str,
-        message: &'a str,
+        error_class: Cow<'a, str>,
+        message: Cow<'a, str>,
         rate_limit: Option<RateLimit>,
     ) -> NotifyBuilder<'a, 'bugsnag> {
         NotifyBuilder {
-            bugsnag,
+            bugsnag: Cow::Borrowed(bugsnag),
             error_class,
             message,
-            send_executed: false,
+            message_fn: None,
+            before_send: None,
+            result: None,
+            offline_only: false,
+            transferred: false,
             methods_to_ignore: None,
             context: None,
             metadata: None,
             severity: None,
             grouping_hash: None,
             rate_limit,
+            panic_payload_type: None,
+            tabs: serde_json::Map::new(),
+            occurred: Utc::now(),
+            occurred_monotonic: clock::now(),
+            app_version: bugsnag
+                .app_version_provider
+                .as_ref()
+                .and_then(|provider| (provider.0)()),
+            user: bugsnag
+                .user_provider
+                .as_ref()
+                .and_then(|provider| (provider.0)())
+                .or_else(|| bugsnag.user.clone()),
+            device_user: bugsnag
+                .device_user_provider
+                .as_ref()
+                .and_then(|provider| (provider.0)())
+                .or_else(|| bugsnag.device_user.clone()),
+            provided_context: match &bugsnag.context {
+                Some(_) => None,
+                None => bugsnag
+                    .context_provider
+                    .as_ref()
+                    .and_then(|provider| (provider.0)()),
+            },
+            deadline: None,
+            // captured here, as the notification may be sent later by Drop or a queue
+            backtrace: stacktrace::capture(),
+            std_backtrace: None,
+            frames: None,
+            causes: Vec::new(),
+            call_site: None,
+            once_key: None,
+            coalesce_key: None,
+            unhandled_override: None,
+            threads: Vec::new(),
+            event_id: uuid::Uuid::new_v4().to_string(),
         }
     }
 
@@ -250,19 +719,21 @@ impl<'a, 'bugsnag> NotifyBuilder<'a, 'bugsnag> {
     /// To check if a method should be marked as not belonging to the
     /// project, the method name reported by the stacktrace is checked if it
     /// contains a method name in this list.
-    pub fn methods_to_ignore(mut self, val: &'a [&'a str]) -> Self {
-        self.methods_to_ignore = Some(val);
+    pub fn methods_to_ignore(mut self, val: &[&str]) -> Self {
+        self.methods_to_ignore = Some(val.iter().map(|m| (*m).to_owned()).collect());
         self
     }
 
     /// Sets a context that describes the state of the application while the error occurred.
-    pub fn context(mut self, val: &'a str) -> Self {
-        self.context = Some(val);
+    pub fn context(mut self, val: impl Into<Cow<'a, str>>) -> Self {
+        self.context = Some(val.into());
         self
     }
 
+    /// Sets the metadata of the notification, within the limits of
+    /// `Bugsnag::set_metadata_limits`.
     pub fn metadata(mut self, val: &impl Serialize) -> Result<Self, Error> {
-        let json_val = match serde_json::to_value(val) {
+        let json_val = match bounded::to_value(val, self.bugsnag.metadata_limits) {
             Ok(v) => v,
             Err(_) => return Err(Error::JsonConversionFailed),
         };
@@ -270,26 +741,435 @@ impl<'a, 'bugsnag> NotifyBuilder<'a, 'bugsnag> {
         Ok(self)
     }
 
+    /// Adds a metadata tab, replacing a tab of the same name.
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    pub(crate) fn tab(mut self, name: &str, value: serde_json::Value) -> Self {
+        self.tabs.insert(name.to_owned(), value);
+        self
+    }
+
     /// Sets the severity of the error.
     pub fn severity(mut self, val: Severity) -> Self {
         self.severity = Some(val);
         self
     }
 
+    /// Sets the point in time by which the delivery has to be finished, e.g. the timeout
+    /// of the request that failed minus a safety margin. The delivery is given up once the
+    /// deadline has passed and the notification is written to the offline storage, which
+    /// is reported as `Outcome::StoredOffline`, instead of being cut off by the caller.
+    /// If the deadline already passed when sending, no delivery is attempted. With
+    /// background delivery the deadline applies to the delivery by the worker, and the
+    /// notification is stored offline if it is still queued at the deadline.
+    pub fn deadline(mut self, at: Instant) -> Self {
+        self.deadline = Some(at);
+        self
+    }
+
+    /// Uses the frames recorded with `RawFrames::capture` as the stacktrace of the
+    /// notification, instead of the stack on which the notification is created.
+    pub fn raw_frames(mut self, frames: &stacktrace::RawFrames) -> Self {
+        self.backtrace = frames.to_backtrace();
+        self
+    }
+
+    /// Reports all threads of the process in the `threads` array of the event, with their
+    /// ids and names. The thread calling this is flagged as the error reporting thread and
+    /// gets the stacktrace of the notification, the other threads are reported without
+    /// stacktrace, see `capture_all_thread_stacks`. The threads are listed when this is
+    /// called.
+    ///
+    /// Only Linux supports listing the other threads, on other platforms only the reporting
+    /// thread is reported.
+    pub fn capture_all_threads(mut self) -> Self {
+        self.threads = threads::capture(false);
+        self
+    }
+
+    /// Like `capture_all_threads`, but also reports the stacks of the other threads, e.g.
+    /// to analyze deadlocks. The stacks are captured when this is called.
+    ///
+    /// Only Linux supports capturing the stacks of other threads. Each thread is briefly
+    /// interrupted with `SIGURG` to record its stack, threads that do not respond within
+    /// 100ms are reported without stacktrace. Capturing gives up after one second, the
+    /// remaining threads are then reported without stacktrace.
+    ///
+    /// **Deadlock risk:** the stack is unwound in the signal handler, which is not
+    /// async-signal-safe. If a thread is interrupted while it holds a lock of the unwinder
+    /// or of the allocator, its handler never finishes and the thread stays blocked
+    /// forever, even after capturing gave up. Only use this if a stuck thread is
+    /// acceptable, e.g. right before the process is aborted.
+    ///
+    /// While the stacks are captured, the `SIGURG` handler installed by the application,
+    /// e.g. by an async runtime for preemption, still gets the signals that are not meant
+    /// for capturing, and is restored afterwards.
+    pub fn capture_all_thread_stacks(mut self) -> Self {
+        self.threads = threads::capture(true);
+        self
+    }
+
+    /// Overrides whether the notification counts as unhandled. Handled errors that should
+    /// intentionally count against the stability score, e.g. tolerated data corruption,
+    /// can be promoted to unhandled, and panics can be demoted to handled. The `reason` is
+    /// reported in the severity reason of the event.
+    pub fn unhandled_override(mut self, unhandled: bool, reason: &str) -> Self {
+        self.unhandled_override = Some((unhandled, reason.to_owned()));
+        self
+    }
+
     /// Sets the grouping hash for the Bugsnag web interface.
-    pub fn grouping_hash(mut self, val: &'a str) -> Self {
-        self.grouping_hash = Some(val);
+    pub fn grouping_hash(mut self, val: impl Into<Cow<'a, str>>) -> Self {
+        self.grouping_hash = Some(val.into());
+        self
+    }
+
+    /// Fills the notification from an error: the error class is the type name of the
+    /// error, the message its `Display` output and the alternate `Debug` output is added
+    /// as `debug` entry to the `error` tab.
+    pub fn from_error<E: StdError + ?Sized>(mut self, err: &E) -> Self {
+        self.error_class = Cow::Borrowed(std::any::type_name::<E>());
+        self.message = Cow::Owned(err.to_string());
+        self.tabs
+            .insert("error".to_owned(), json!({ "debug": format!("{err:#?}") }));
+        self
+    }
+
+    /// Fills the notification from an `anyhow::Error`: the message is its `Display` output,
+    /// every cause of its chain is added as further exception and, if the error captured a
+    /// backtrace, the stacktrace is the one of the place where the error was created
+    /// instead of where it is reported. Context layers and `anyhow!` messages have no type
+    /// of their own, they get the class of the root cause, or the class of the
+    /// notification if the root cause is a message as well.
+    #[cfg(feature = "anyhow")]
+    pub fn from_anyhow(mut self, err: &anyhow::Error) -> Self {
+        let class = anyhow_error_class(err.root_cause())
+            .unwrap_or_else(|| self.error_class.clone().into_owned());
+        let mut chain = err.chain();
+        if let Some(outer) = chain.next() {
+            self.error_class = Cow::Owned(anyhow_error_class(outer).unwrap_or(class.clone()));
+        }
+        self.message = Cow::Owned(err.to_string());
+        for cause in chain {
+            let cause_class = anyhow_error_class(cause).unwrap_or(class.clone());
+            self.causes.push((cause_class, cause.to_string()));
+        }
+        self.std_backtrace(err.backtrace())
+    }
+
+    /// Uses a backtrace of the standard library as the stacktrace of the notification,
+    /// e.g. one captured when the error was created, so the report points to where the
+    /// error happened rather than where it is reported. Frames are assigned to the
+    /// project like captured frames. Nothing changes if the backtrace was not captured,
+    /// e.g. because `RUST_BACKTRACE` is not set.
+    pub fn std_backtrace(mut self, backtrace: &std::backtrace::Backtrace) -> Self {
+        if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+            self.std_backtrace = Some(backtrace.to_string());
+        }
+        self
+    }
+
+    /// Uses the given frames as the stacktrace of the notification, e.g. of a crash
+    /// reported by an embedded device or a program in another language, instead of the
+    /// stack of this process. The frames are reported as they are, only the path prefixes
+    /// and the frame rewriter of the instance are applied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bugsnag::Frame;
+    ///
+    /// let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+    /// let mut frame = Frame::new("firmware/motor.c", 120, "motor_step", true);
+    /// frame.set_column_number(7);
+    ///
+    /// api.notify("HardFault", "bus error at 0x20001000")
+    ///     .stacktrace(vec![frame, Frame::new("hal/irq.c", 40, "irq_dispatch", false)]);
+    /// ```
+    pub fn stacktrace(mut self, frames: Vec<stacktrace::Frame>) -> Self {
+        self.frames = Some(frames);
+        self
+    }
+
+    /// Fills the notification from an IO error. The error class is derived from the kind
+    /// of the error, e.g. `Io::NotFound`, so errors group the same on every platform. The
+    /// kind and the OS error code, if any, are added to the `io` tab.
+    pub fn from_io_error(mut self, err: &std::io::Error) -> Self {
+        self.error_class = Cow::Owned(io_error_class(err.kind()));
+        self.message = Cow::Owned(err.to_string());
+        let mut tab = json!({ "kind": format!("{:?}", err.kind()) });
+        if let Some(code) = err.raw_os_error() {
+            tab["osError"] = json!(code);
+        }
+        self.tabs.insert("io".to_owned(), tab);
+        self
+    }
+
+    /// Sets a function that creates the message. The function is only called when the
+    /// notification is actually send, so expensive messages are not created for
+    /// notifications that are disabled, sampled or rate limited. The message is
+    /// truncated to 4096 bytes.
+    pub fn message_with<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce() -> String + Send + 'a,
+    {
+        self.message_fn = Some(Box::new(f));
+        self
+    }
+
+    /// Sets a callback that is called with the enriched event right before it is
+    /// serialized, after the filters, sampling and limits decided to send it. It can
+    /// change the event, e.g. attach diagnostics that are too expensive to collect for
+    /// every notification, or return `false` to discard it, which is reported as
+    /// `Outcome::DiscardedByFilter`. A later call replaces the callback.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+    /// api.notify("Pool::Exhausted", "no connection available")
+    ///     .on_before_send(|event| {
+    ///         event.add_metadata("pool", "stats", "expensive dump".into());
+    ///         true
+    ///     });
+    /// ```
+    pub fn on_before_send<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut plugin::Event) -> bool + Send + 'static,
+    {
+        self.before_send = Some(Box::new(f));
+        self
+    }
+
+    /// Converts the builder into one that owns all of its data, so it can be moved to a
+    /// worker thread or an async task and send from there. A message set with
+    /// `message_with` is created now.
+    pub fn into_owned(mut self) -> NotifyBuilder<'static, 'static> {
+        self.resolve_message();
+        // the notification is sent by the returned builder
+        self.transferred = true;
+
+        NotifyBuilder {
+            bugsnag: Cow::Owned(self.bugsnag.clone().into_owned()),
+            error_class: Cow::Owned(std::mem::take(&mut self.error_class).into_owned()),
+            message: Cow::Owned(std::mem::take(&mut self.message).into_owned()),
+            message_fn: None,
+            before_send: self.before_send.take(),
+            result: self.result.take(),
+            offline_only: self.offline_only,
+            transferred: false,
+            methods_to_ignore: self.methods_to_ignore.take(),
+            context: self.context.take().map(|c| Cow::Owned(c.into_owned())),
+            metadata: self.metadata.take(),
+            severity: self.severity.take(),
+            grouping_hash: self
+                .grouping_hash
+                .take()
+                .map(|g| Cow::Owned(g.into_owned())),
+            rate_limit: self.rate_limit.take(),
+            panic_payload_type: self.panic_payload_type,
+            tabs: std::mem::take(&mut self.tabs),
+            occurred: self.occurred,
+            occurred_monotonic: self.occurred_monotonic,
+            app_version: self.app_version.take(),
+            user: self.user.take(),
+            device_user: self.device_user.take(),
+            provided_context: self.provided_context.take(),
+            deadline: self.deadline,
+            backtrace: std::mem::replace(&mut self.backtrace, Vec::new().into()),
+            std_backtrace: self.std_backtrace.take(),
+            frames: self.frames.take(),
+            causes: std::mem::take(&mut self.causes),
+            call_site: self.call_site.take(),
+            once_key: self.once_key.take(),
+            coalesce_key: None,
+            unhandled_override: self.unhandled_override.take(),
+            threads: std::mem::take(&mut self.threads),
+            event_id: std::mem::take(&mut self.event_id),
+        }
+    }
+
+    /// Marks the notification as originating from a panic. Use this when notifying
+    /// from a panic hook, so the `rust` metadata tab reports the panic payload.
+    pub fn panic(self, info: &std::panic::PanicHookInfo) -> Self {
+        self.panic_payload(info.payload())
+    }
+
+    /// Marks the notification as originating from a panic with the given payload,
+    /// e.g. as returned by `std::panic::catch_unwind`.
+    pub fn panic_payload(mut self, payload: &(dyn std::any::Any + Send)) -> Self {
+        self.panic_payload_type = Some(panic::payload_type_name_of(payload));
+        if let Some(rate_limit) = self.bugsnag.config().panic_rate_limit {
+            self.rate_limit = Some(rate_limit);
+        }
+        if let Some(err) = panic::payload_error(payload) {
+            self.add_source_chain(err);
+        }
         self
     }
 
+    /// Adds the sources of an error as further exceptions of the notification.
+    fn add_source_chain(&mut self, err: &dyn StdError) {
+        let mut source = err.source();
+        while let Some(err) = source {
+            self.causes.push((source_error_class(err), err.to_string()));
+            source = err.source();
+        }
+    }
+
     /// Call this function to explicitly send the notification to Bugsnag.
     /// This function will be called implicit if this object is dropped, but the notification will
-    /// not be send twice.
-    pub fn send(&mut self) -> Result<(), Error> {
-        if self.send_executed {
-            return Ok(());
+    /// not be send twice. Calling it again returns the result of the first call.
+    pub fn send(&mut self) -> Result<Outcome, Error> {
+        if let Some(res) = &self.result {
+            return res.clone();
+        }
+
+        // a notification triggered while sending another notification on the same thread,
+        // e.g. by a panic while sending, is only written to the offline storage, so it
+        // cannot cause another panic in the same code path
+        let _event = trace::enter(&self.event_id);
+        let guard = NotifyGuard::enter();
+        if guard.reentrant {
+            info!("Notification triggered while sending a notification. Only storing it offline.");
+            self.offline_only = true;
+        }
+
+        let res = self.send_once();
+        trace::decision!("{} {}, outcome {res:?}", self.error_class, self.message);
+        self.result = Some(res.clone());
+        res
+    }
+
+    /// Sends the notification like `send`, but gives up on the delivery once `deadline`
+    /// has passed. The notification is then written to the offline storage, which is
+    /// reported as `Outcome::StoredOffline`. With background delivery, `Outcome::Queued`
+    /// is returned and the deadline applies to the delivery by the worker. The
+    /// notification is never held back for coalescing, see `Bugsnag::set_coalescing`.
+    pub fn send_with_deadline(&mut self, deadline: Duration) -> Result<Outcome, Error> {
+        self.deadline = Some(Instant::now() + deadline);
+        self.send()
+    }
+
+    /// Sends the notification like `send`, but delivers it with the async HTTP client, so
+    /// the delivery does not block the thread of the async runtime. The future is `Send`.
+    /// Filters, limits and plugins still run synchronously. A notification held back for
+    /// coalescing is delivered by the timer thread of the coalescer.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn handler(api: &mut bugsnag::Bugsnag) {
+    /// let outcome = api.notify("Error", "message").send_async().await;
+    /// # }
+    /// ```
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    pub async fn send_async(&mut self) -> Result<Outcome, Error> {
+        if let Some(res) = &self.result {
+            return res.clone();
+        }
+
+        let prepared = {
+            let _event = trace::enter(&self.event_id);
+            let prepared = self.prepare();
+            self.defer_coalesced(prepared)
+        };
+        let res = match prepared {
+            Ok(ControlFlow::Continue(json)) => {
+                self.bugsnag.deliver_async(json, self.deadline).await
+            }
+            Ok(ControlFlow::Break(outcome)) => Ok(outcome),
+            Err(e) => Err(e),
+        };
+        {
+            let _event = trace::enter(&self.event_id);
+            trace::decision!("{} {}, outcome {res:?}", self.error_class, self.message);
+        }
+        self.result = Some(res.clone());
+        res
+    }
+
+    /// Applies the configuration, filters and limits to the notification and creates its
+    /// json. Breaks with the outcome if the notification is not delivered.
+    fn prepare(&mut self) -> Result<ControlFlow<Outcome, String>, Error> {
+        let config = self.bugsnag.config();
+
+        if !config.enabled {
+            info!("Notifications are disabled.");
+            return Ok(ControlFlow::Break(Outcome::DiscardedByFilter));
+        }
+
+        if !self.bugsnag.notifies_release_stage(&config) {
+            return Ok(ControlFlow::Break(Outcome::SuppressedByReleaseStage));
+        }
+
+        if self.bugsnag.discard_classes.matches(&self.error_class) {
+            trace::decision!("error class {} is discarded", self.error_class);
+            return Ok(ControlFlow::Break(Outcome::DiscardedByFilter));
+        }
+
+        if let Some(key) = &self.once_key {
+            let Some(hits) = budget::hit_once_key(key) else {
+                info!("Notification dropped, key {key} was already reported.");
+                return Ok(ControlFlow::Break(Outcome::SuppressedOnce));
+            };
+            // includes the hits that were dropped by the filters below
+            self.tabs
+                .insert("once".to_owned(), json!({ "key": key, "hits": hits }));
+        }
+
+        if config.info_as_breadcrumbs && self.severity == Some(Severity::Info) {
+            self.resolve_message();
+            let mut metadata = json!({"message": self.message});
+            if let Some(context) = &self.context {
+                metadata["context"] = json!(context);
+            }
+            self.bugsnag.leave_breadcrumb(
+                &self.error_class,
+                breadcrumbs::BreadcrumbType::Log,
+                Some(metadata),
+            );
+            return Ok(ControlFlow::Break(Outcome::ConvertedToBreadcrumb));
+        }
+
+        if config.sampled_out() {
+            info!("Notification dropped by sampling.");
+            return Ok(ControlFlow::Break(Outcome::Sampled));
+        }
+
+        // panics are not held back, as the process may end right after the panic hook, and
+        // neither are sends with a deadline, whose outcome is waited for
+        let coalescer = self.bugsnag.coalescer.clone();
+        if let (Some(coalescer), None, false, None) = (
+            coalescer,
+            &self.panic_payload_type,
+            self.offline_only,
+            self.deadline,
+        ) {
+            self.resolve_message();
+            let key = format!("{}\0{}", self.error_class, self.message);
+            if !coalescer.register(&key) {
+                return Ok(ControlFlow::Break(Outcome::Coalesced));
+            }
+            self.coalesce_key = Some(key);
+        }
+
+        if let Some(max) = config.class_budget {
+            let version = self
+                .app_version
+                .as_deref()
+                .or_else(|| self.bugsnag.app_info.as_ref().and_then(|a| a.version()));
+            let within_budget = self
+                .bugsnag
+                .class_budgets
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(version, &self.error_class, max);
+
+            if !within_budget {
+                info!("Notification dropped, budget of error class used up.");
+                return Ok(ControlFlow::Break(Outcome::SuppressedByClassBudget));
+            }
         }
-        self.send_executed = true;
 
         if let Some(rl) = self.rate_limit.as_mut() {
             rl.register_notification()
@@ -315,221 +1195,4168 @@ impl<'a, 'bugsnag> NotifyBuilder<'a, 'bugsnag> {
 
         if let Some(options) = &rate_limit_triggered {
             info!("Rate limit triggered. Notifications will be replaced with rate limit notification.");
+            trace::decision!("replaced by the rate limit notification");
 
-            self.error_class = "RateLimit";
-            self.message = "Rate limit reached. Notifications will be suppressed.";
+            self.error_class = Cow::Borrowed("RateLimit");
+            self.message = Cow::Borrowed("Rate limit reached. Notifications will be suppressed.");
             self.context = None;
             self.metadata.clone_from(&options.metadata);
             self.severity.clone_from(&options.severity);
-            self.grouping_hash = Some("rate_limit");
+            self.grouping_hash = Some(Cow::Borrowed("rate_limit"));
+            self.message_fn = None;
+            self.before_send = None;
+            self.causes.clear();
         }
 
         if rate_limit_reached && rate_limit_triggered.is_none() {
             info!("Rate limit reached. Notifications will be suppressed.");
-            return Ok(());
+            return Ok(ControlFlow::Break(Outcome::SuppressedByRateLimit));
         }
 
-        let json = self.create_json()?;
-        self.bugsnag.send(&json, true)
+        self.resolve_message();
+        trace::decision!("passed the filters and limits");
+        let before_send = self.before_send.take();
+        let json = self.build_json(before_send)?;
+
+        // the key is only used up by a notification that is actually send
+        if let (Some(key), ControlFlow::Continue(_), None) =
+            (&self.once_key, &json, &rate_limit_triggered)
+        {
+            if !budget::report_once_key(key) {
+                info!("Notification dropped, key {key} was reported concurrently.");
+                return Ok(ControlFlow::Break(Outcome::SuppressedOnce));
+            }
+        }
+        Ok(json)
     }
 
-    /// Prepares the json as string
-    fn create_json(&self) -> Result<String, Error> {
-        let stacktrace = self.bugsnag.create_stacktrace(self.methods_to_ignore);
-        let exceptions = vec![exception::Exception::new(
-            self.error_class,
-            self.message,
-            &stacktrace,
-        )];
-        let metadata = {
-            let ts = chrono::Utc::now().to_rfc3339();
-            let json = if let Some(md) = &self.metadata {
-                json!({
-                    "bugsnag-rs": {"occurred": ts},
-                    "metaData": md
-                })
-            } else {
-                json!({"bugsnag-rs": {"occurred": ts}})
-            };
-            Some(json)
+    /// Hands a prepared notification that is the first of coalesced notifications to the
+    /// timer of the coalescer, which delivers it with the occurrences once the window is
+    /// over. If the notification is not delivered, it is not waited for further ones.
+    fn defer_coalesced(
+        &mut self,
+        prepared: Result<ControlFlow<Outcome, String>, Error>,
+    ) -> Result<ControlFlow<Outcome, String>, Error> {
+        let (Some(key), Some(coalescer)) = (self.coalesce_key.take(), &self.bugsnag.coalescer)
+        else {
+            return prepared;
+        };
+        let json = match prepared {
+            Ok(ControlFlow::Continue(json)) => json,
+            res => {
+                coalescer.cancel(&key);
+                return res;
+            }
         };
-        let events = vec![event::Event::new(
-            &exceptions,
-            self.severity.as_ref(),
-            self.context,
-            self.grouping_hash,
-            &self.bugsnag.device_info,
-            &self.bugsnag.app_info,
-            &self.bugsnag.user,
-            &metadata,
-        )];
-        let notification = notification::Notification::new(&events);
 
-        match serde_json::to_string(&notification) {
-            Ok(json) => Ok(json),
-            Err(_) => Err(Error::JsonConversionFailed),
-        }
+        let bugsnag = self.bugsnag.clone().into_owned();
+        let deadline = self.deadline;
+        let event_id = self.event_id.clone();
+        coalescer.defer(key, move |occurrences| {
+            let _event = trace::enter(&event_id);
+            trace::decision!("coalesced {} occurrences", occurrences.count);
+            let json = coalesce::add_occurrences(json, &occurrences);
+            let res = bugsnag.deliver(json, deadline);
+            trace::decision!("coalesced delivery, outcome {res:?}");
+            if res.is_err() {
+                info!("delivery of coalesced notification failed");
+            }
+        });
+        Ok(ControlFlow::Break(Outcome::Queued))
     }
-}
 
-impl<'a, 'bugsnag> Drop for NotifyBuilder<'a, 'bugsnag> {
-    fn drop(&mut self) {
-        let _ = self.send();
+    fn send_once(&mut self) -> Result<Outcome, Error> {
+        let prepared = self.prepare();
+        let json = match self.defer_coalesced(prepared)? {
+            ControlFlow::Break(outcome) => return Ok(outcome),
+            ControlFlow::Continue(json) => json,
+        };
+        if self.offline_only {
+            trace::decision!("triggered while sending a notification, only storing it offline");
+            return self
+                .bugsnag
+                .store_offline(&json)
+                .map(Outcome::StoredOffline);
+        }
+        if self.panic_payload_type.is_some() && self.bugsnag.queue.is_some() {
+            // the process may abort after the panic hook, before the worker sends
+            trace::decision!("panic is delivered directly instead of through the queue");
+            return self.bugsnag.without_queue().deliver(json, self.deadline);
+        }
+        self.bugsnag.deliver(json, self.deadline)
     }
-}
 
-impl Bugsnag {
-    /// Creates a new instance of the Bugsnag api
-    pub fn new(api_key: &str, project_source_dir: &str) -> Bugsnag {
-        Bugsnag {
-            api_key: api_key.to_owned(),
-            device_info: deviceinfo::DeviceInfo::generate(),
-            user: None,
-            app_info: None,
-            project_source_dir: project_source_dir.to_owned(),
-            offline_storage: None,
-            rate_limit: None,
+    /// Creates the message with the function set by `message_with`.
+    fn resolve_message(&mut self) {
+        if let Some(f) = self.message_fn.take() {
+            let mut message = f();
+            if message.len() > MAX_MESSAGE_LEN {
+                let mut end = MAX_MESSAGE_LEN;
+                while !message.is_char_boundary(end) {
+                    end -= 1;
+                }
+                message.truncate(end);
+            }
+            self.message = Cow::Owned(message);
         }
     }
 
-    /// Notifies the Bugsnag web-interface about an error.
-    /// The function returns a builder to provide more information about the error.
-    pub fn notify<'a, 'bugsnag>(
-        &'bugsnag mut self,
-        error_class: &'a str,
-        message: &'a str,
-    ) -> NotifyBuilder<'a, 'bugsnag> {
-        NotifyBuilder::new(self, error_class, message, self.rate_limit.clone())
+    /// Prepares the json as string, without the callback of `on_before_send`.
+    #[cfg(test)]
+    pub(crate) fn create_json(&self) -> Result<String, Error> {
+        match self.build_json(None)? {
+            ControlFlow::Continue(json) => Ok(json),
+            ControlFlow::Break(outcome) => panic!("notification was not created: {:?}", outcome),
+        }
     }
 
-    fn create_stacktrace(&self, methods_to_ignore: Option<&[&str]>) -> Vec<stacktrace::Frame> {
-        if let Some(ignore) = methods_to_ignore {
-            let in_project_check = |file: &str, method: &str| {
-                file.starts_with(self.project_source_dir.as_str())
-                    && ignore.iter().any(|check| !method.contains(*check))
-            };
-
-            stacktrace::create_stacktrace(&in_project_check)
-        } else {
-            let in_project_check =
-                |file: &str, _: &str| file.starts_with(self.project_source_dir.as_str());
-
-            stacktrace::create_stacktrace(&in_project_check)
+    /// Prepares the json as string. Breaks with the outcome if `before_send` discards the
+    /// notification.
+    fn build_json(
+        &self,
+        before_send: Option<BeforeSendFn>,
+    ) -> Result<ControlFlow<Outcome, String>, Error> {
+        let mut stacktrace = match &self.frames {
+            Some(frames) => frames.clone(),
+            None => self.bugsnag.create_stacktrace(
+                &self.backtrace,
+                self.std_backtrace.as_deref(),
+                self.methods_to_ignore.as_deref(),
+            ),
+        };
+        if let (Some(frame), None) = (&self.call_site, &self.frames) {
+            stacktrace.insert(0, frame.clone());
+        }
+        // before the paths are rewritten, as the sources are read from the local paths
+        if self.bugsnag.source_snippets && self.frames.is_none() {
+            stacktrace::attach_source_snippets(&mut stacktrace, &self.bugsnag.project_source_dir);
+        }
+        self.bugsnag.rewrite_path_prefixes(&mut stacktrace);
+        if let Some(rewriter) = &self.bugsnag.frame_rewriter {
+            (rewriter.0)(&mut stacktrace);
+        }
+        let mut exceptions = vec![exception::Exception::new(
+            &self.error_class,
+            &self.message,
+            &stacktrace,
+        )];
+        let depth = self.bugsnag.max_cause_depth;
+        for (class, message) in self.causes.iter().take(depth) {
+            exceptions.push(exception::Exception::new(class, message, &[]));
+        }
+        let omitted_causes;
+        if let Some((root_class, root_message)) = self.causes.get(depth..).and_then(|c| c.last()) {
+            omitted_causes = format!(
+                "{} further causes omitted, root cause {root_class}: {root_message}",
+                self.causes.len() - depth
+            );
+            exceptions.push(exception::Exception::new(
+                "CausesOmitted",
+                &omitted_causes,
+                &[],
+            ));
+        }
+        let stable_grouping_hash = match &self.grouping_hash {
+            None if self.bugsnag.stable_grouping => Some(stacktrace::grouping_hash(
+                &self.error_class,
+                &stacktrace,
+                &self.bugsnag.project_source_dir,
+            )),
+            _ => None,
+        };
+        let mut metadata = self.bugsnag.metadata_sections.clone();
+        if let Some(tab) = &self.bugsnag.internal_tab {
+            metadata.insert(
+                tab.clone(),
+                json!({
+                    "occurred": self.occurred.to_rfc3339(),
+                    "eventId": self.event_id,
+                    // lets uploaders correct `occurred` of devices with a wrong clock
+                    "monotonicMs": self.occurred_monotonic.millis,
+                    "monotonicSince": self.occurred_monotonic.since,
+                    "bootId": clock::boot_id(),
+                }),
+            );
+        }
+        // before the tabs of the notification, so a supplied `rust` tab takes precedence
+        metadata.insert(
+            "rust".to_owned(),
+            json!(rustinfo::RustInfo::generate(self.panic_payload_type)),
+        );
+        if let Some(deps) = &self.bugsnag.dependencies {
+            metadata.insert("dependencies".to_owned(), deps.clone());
+        }
+        for (tab, val) in &self.tabs {
+            merge_section(&mut metadata, tab, val);
+        }
+        // the addresses of supplied frames are not known
+        if self.bugsnag.symbolication_data && self.frames.is_none() {
+            metadata.insert(
+                "symbolication".to_owned(),
+                json!({
+                    "buildId": buildid::of_current_exe(),
+                    "frames": stacktrace::raw_addresses(&self.backtrace),
+                }),
+            );
+        }
+        let md = match (&self.bugsnag.metadata, &self.metadata) {
+            (Some(base), Some(md)) => Some(merge_metadata(base, md)),
+            (base, md) => md.clone().or_else(|| base.clone()),
+        };
+        if let Some(md) = md {
+            merge_section(&mut metadata, "metaData", &md);
         }
-    }
 
-    /// Send a json string to the Bugsnag endpoint
-    fn send(&self, json: &str, store_on_error: bool) -> Result<(), Error> {
-        let client = reqwest::blocking::Client::new();
-        let request = client
-            .post(NOTIFY_URL)
-            .body(json.to_string())
-            .header("Content-Type", "application/json")
-            .header("Bugsnag-Api-Key", self.api_key.clone())
-            .header("Bugsnag-Payload-Version", notification::PAYLOAD_VERSION);
-        match request.send() {
-            Ok(_) => Ok(()),
-            Err(_) => {
-                if store_on_error {
-                    let os = match &self.offline_storage {
-                        Some(os) => os,
-                        None => return Err(Error::JsonTransferAndStorageFailed),
-                    };
-                    let name = format!("{os}/{OFFLINE_REPORT_PREFIX}_{}", uuid::Uuid::new_v4());
-                    if std::fs::write(name, json).is_err() {
-                        return Err(Error::JsonTransferAndStorageFailed);
-                    }
-                }
-                Err(Error::JsonTransferFailed)
+        let mut enriched = plugin::Event {
+            error_class: self.error_class.clone().into_owned(),
+            message: self.message.clone().into_owned(),
+            severity: self.severity.clone(),
+            occurred: self.occurred,
+            context: self
+                .context
+                .as_deref()
+                .or(self.bugsnag.context.as_deref())
+                .or(self.provided_context.as_deref())
+                .map(str::to_owned),
+            device: self.bugsnag.device_info.clone(),
+            app: self.bugsnag.app_info.clone(),
+            user: self.user.clone(),
+            device_user: self.device_user.clone(),
+            metadata,
+        };
+        let config = self.bugsnag.config();
+        let builtin: [&dyn plugin::Plugin; 4] = [
+            &plugin::DevicePlugin,
+            &plugin::AppPlugin {
+                version: self.app_version.as_deref(),
+                release_stage: config.release_stage.as_deref(),
+                duration: clock::since_launch(self.occurred_monotonic),
+            },
+            &plugin::ProcessPlugin {
+                build_info: self.bugsnag.build_info.as_ref(),
+            },
+            &plugin::CloudPlugin,
+        ];
+        for plugin in builtin {
+            plugin.enrich(&mut enriched);
+        }
+        #[cfg(feature = "tokio")]
+        plugin::Plugin::enrich(&plugin::TokioPlugin, &mut enriched);
+        for plugin in &self.bugsnag.plugins.0 {
+            plugin.enrich(&mut enriched);
+        }
+        for (i, on_error) in self.bugsnag.on_error.0.iter().enumerate() {
+            if !on_error(&mut enriched) {
+                trace::decision!("discarded by the callback {i} of add_on_error");
+                return Ok(ControlFlow::Break(Outcome::DiscardedByFilter));
             }
         }
-    }
+        if let Some(before_send) = before_send {
+            if !before_send(&mut enriched) {
+                trace::decision!("discarded by the callback of on_before_send");
+                return Ok(ControlFlow::Break(Outcome::DiscardedByFilter));
+            }
+        }
+        // last, so identifiers set by plugins are covered as well
+        if let Some(pseudonymizer) = &self.bugsnag.pseudonymizer {
+            plugin::Plugin::enrich(pseudonymizer, &mut enriched);
+        }
 
-    /// Sets information about the device. These information will be send to
-    /// Bugsnag when notify is called.
-    pub fn set_device_info(&mut self, hostname: Option<&str>, version: Option<&str>) {
-        if let Some(name) = hostname {
-            self.device_info.set_hostname(name);
+        // with a device user, both users are reported in their own tab, so they can not
+        // be confused on the dashboard
+        if let Some(device_user) = &enriched.device_user {
+            enriched
+                .metadata
+                .insert("deviceUser".to_owned(), json!(device_user));
+            if let Some(user) = &enriched.user {
+                enriched.metadata.insert("appUser".to_owned(), json!(user));
+            }
+        }
+        redact::redact(&mut enriched.metadata, &config.redacted_keys);
+        let metadata = Some(serde_json::Value::Object(enriched.metadata));
+        let mut breadcrumbs = self
+            .bugsnag
+            .breadcrumbs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .to_vec();
+        for breadcrumb in &mut breadcrumbs {
+            breadcrumb.redact(&config.redacted_keys);
         }
+        let mut event = event::Event::new(
+            &exceptions,
+            enriched.severity.as_ref(),
+            enriched.context.as_deref(),
+            self.grouping_hash
+                .as_deref()
+                .or(stable_grouping_hash.as_deref()),
+            &enriched.device,
+            &enriched.app,
+            &enriched.user,
+            &metadata,
+        )
+        .with_breadcrumbs(&breadcrumbs);
+        if let Some((unhandled, reason)) = &self.unhandled_override {
+            let originally_unhandled = self.panic_payload_type.is_some();
+            event = event.with_unhandled_override(*unhandled, originally_unhandled, reason);
+        }
+        let threads = self
+            .threads
+            .iter()
+            .map(|raw| {
+                let frames = match raw.reporting {
+                    true => stacktrace.clone(),
+                    false => self.bugsnag.create_thread_stacktrace(&raw.ips),
+                };
+                threads::Thread::new(raw, frames)
+            })
+            .collect::<Vec<_>>();
+        event = event.with_threads(&threads);
+        let events = vec![event];
+        let notification = notification::Notification::new(&events);
 
-        if let Some(ver) = version {
-            self.device_info.set_os_version(ver);
+        match serde_json::to_string(&notification) {
+            Ok(json) => Ok(ControlFlow::Continue(json)),
+            Err(_) => Err(Error::JsonConversionFailed),
         }
     }
+}
 
-    /// Sets information about the application that uses this api. These information
-    /// will be send to Bugsnag when notify is called.
-    pub fn set_app_info(
+/// Returns the error class for an IO error kind, e.g. `Io::PermissionDenied`.
+pub fn io_error_class(kind: std::io::ErrorKind) -> String {
+    format!("Io::{kind:?}")
+}
+
+/// Returns the offline storage used by `Bugsnag::start`, a directory in the temporary
+/// directory per API key and, on Unix, per user. This way programs reporting to different
+/// projects or running as different users do not share their reports.
+fn default_storage_dir(api_key: &str) -> PathBuf {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(api_key.as_bytes());
+    let key: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    // SAFETY: `getuid` takes no arguments and always succeeds.
+    #[cfg(unix)]
+    let name = format!("bugsnag-{}-{key}", unsafe { libc::getuid() });
+    #[cfg(not(unix))]
+    let name = format!("bugsnag-{key}");
+    std::env::temp_dir().join(name)
+}
+
+/// Creates a directory that only the current user can access, if it does not exist.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Returns the time left until `deadline`, which is zero once it has passed.
+fn time_left(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|d| d.saturating_duration_since(Instant::now()))
+}
+
+/// Returns a class for an error of which only the trait object is known, from the type
+/// or variant name at the start of its `Debug` output, e.g. `ParseIntError` for
+/// `ParseIntError { kind: InvalidDigit }`.
+fn source_error_class(err: &dyn StdError) -> String {
+    let debug = format!("{err:?}");
+    let name: String = debug
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == ':')
+        .collect();
+
+    if name.is_empty() {
+        "Error".to_owned()
+    } else {
+        name
+    }
+}
+
+/// Returns the class of an error of an `anyhow` chain like `source_error_class`, or `None`
+/// for context layers and messages, which print as `Error { context: .. }` and as quoted
+/// string.
+#[cfg(feature = "anyhow")]
+fn anyhow_error_class(err: &dyn StdError) -> Option<String> {
+    let debug = format!("{err:?}");
+    if debug.starts_with("Error { context:") || debug.starts_with('"') {
+        return None;
+    }
+    Some(source_error_class(err))
+}
+
+/// Collects the structured key-values of a log record as json values.
+struct KeyValues(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for KeyValues {
+    fn visit_pair(
         &mut self,
-        version: Option<&str>,
-        release_stage: Option<&str>,
-        atype: Option<&str>,
-    ) {
-        self.app_info = Some(appinfo::AppInfo::new(version, release_stage, atype));
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        let value = if let Some(v) = value.to_bool() {
+            json!(v)
+        } else if let Some(v) = value.to_i64() {
+            json!(v)
+        } else if let Some(v) = value.to_u64() {
+            json!(v)
+        } else if let Some(v) = value.to_f64() {
+            json!(v)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
     }
+}
 
-    pub fn reset_app_info(&mut self) {
-        self.app_info = None;
+/// Merges two metadata values, values of `overrides` win on conflict.
+fn merge_metadata(base: &serde_json::Value, overrides: &serde_json::Value) -> serde_json::Value {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            let mut merged = base.clone();
+            for (key, value) in overrides {
+                let value = match merged.get(key) {
+                    Some(existing) => merge_metadata(existing, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            serde_json::Value::Object(merged)
+        }
+        (_, overrides) => overrides.clone(),
     }
+}
 
-    pub fn set_user(&mut self, user: user::User) {
-        self.user = Some(user);
+/// Merges `value` into the section `name` of `metadata`, values of `value` win on
+/// conflict.
+fn merge_section(
+    metadata: &mut serde_json::Map<String, serde_json::Value>,
+    name: &str,
+    value: &serde_json::Value,
+) {
+    let merged = match metadata.get(name) {
+        Some(existing) => merge_metadata(existing, value),
+        None => value.clone(),
+    };
+    metadata.insert(name.to_owned(), merged);
+}
+
+impl<'a, 'bugsnag> Drop for NotifyBuilder<'a, 'bugsnag> {
+    fn drop(&mut self) {
+        if self.transferred {
+            return;
+        }
+        // while unwinding, a panic in the network code would abort the process
+        if std::thread::panicking() {
+            self.offline_only = true;
+        }
+        let _ = self.send();
     }
+}
 
-    pub fn use_offline_storage(&mut self, storage: &str) {
-        self.offline_storage = Some(storage.to_string())
+impl Bugsnag {
+    /// Creates an instance of the Bugsnag api with the defaults of the official Bugsnag
+    /// SDKs, for a quick start:
+    ///
+    /// - the current directory is the project source directory
+    /// - the app version and release stage are read from the `BUGSNAG_APP_VERSION` and
+    ///   `BUGSNAG_RELEASE_STAGE` environment variables, the release stage defaults to
+    ///   `production`
+    /// - panics are reported by a panic hook, see `panic::install_hook`
+    /// - notifications are delivered by a background thread
+    /// - at most 10 notifications per minute and 100 per hour are sent, counted in memory
+    /// - reports that can not be delivered are stored in a private directory of the
+    ///   temporary directory, one per API key and user, and retransmitted every minute
+    ///
+    /// Every default can still be changed on the returned instance. As the panic hook
+    /// reports with a clone of the instance, changes made afterwards do not apply to
+    /// panics.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let mut api = bugsnag::Bugsnag::start("api-key");
+    ///
+    /// api.notify("Info", "The app started").severity(bugsnag::Severity::Info);
+    /// ```
+    pub fn start(api_key: &str) -> Bugsnag {
+        let bugsnag = Bugsnag::with_defaults(api_key, |name| std::env::var(name).ok());
+        panic::install_hook(&bugsnag);
+        bugsnag
     }
 
-    pub fn rate_limit(&mut self, rate_limit: RateLimit) {
-        self.rate_limit = Some(rate_limit);
+    /// Applies the defaults of `start`, except for the panic hook.
+    fn with_defaults(api_key: &str, var: impl Fn(&str) -> Option<String>) -> Bugsnag {
+        let var = |name| var(name).filter(|value: &String| !value.is_empty());
+        let project_source_dir = std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+
+        let mut bugsnag = Bugsnag::new(api_key, &project_source_dir);
+        bugsnag.set_app_info(
+            var("BUGSNAG_APP_VERSION").as_deref(),
+            Some(
+                var("BUGSNAG_RELEASE_STAGE")
+                    .as_deref()
+                    .unwrap_or("production"),
+            ),
+            Some("rust"),
+        );
+        bugsnag.rate_limit(RateLimit::in_memory(
+            vec![
+                SendLimit::new(Duration::from_secs(60), 10),
+                SendLimit::new(Duration::from_secs(3600), 100),
+            ],
+            None,
+        ));
+
+        let storage = default_storage_dir(api_key);
+        match create_private_dir(&storage) {
+            Ok(()) => bugsnag.use_offline_storage(&storage.display().to_string()),
+            Err(e) => warn!(
+                "failed to create offline storage {}: {e}",
+                storage.display()
+            ),
+        }
+        bugsnag.use_background_delivery(START_QUEUE_CAPACITY, START_RETRY_INTERVAL);
+        bugsnag
     }
 
-    pub fn retry_from_storage(&self) -> Result<(), Error> {
-        let os = match &self.offline_storage {
-            Some(storage) => storage,
-            None => return Err(Error::OfflineStorageError),
+    /// Creates a new instance of the Bugsnag api
+    pub fn new(api_key: &str, project_source_dir: &str) -> Bugsnag {
+        // where the start of the process is unknown, this is taken as the launch
+        clock::launch();
+        Bugsnag {
+            api_key: api_key.to_owned(),
+            device_info: deviceinfo::DeviceInfo::generate(),
+            user: None,
+            user_provider: None,
+            device_user: None,
+            device_user_provider: None,
+            app_version_provider: None,
+            app_info: None,
+            project_source_dir: project_source_dir.to_owned(),
+            project_packages: Vec::new(),
+            notify_release_stages: None,
+            discard_classes: discard::DiscardClasses::default(),
+            offline_storage: None,
+            config: Arc::new(RwLock::new(config::Config::default())),
+            transport_options: transport::TransportOptions::default(),
+            #[cfg(feature = "http")]
+            client: Arc::new(OnceLock::new()),
+            #[cfg(all(feature = "http", feature = "tokio"))]
+            async_client: Arc::new(OnceLock::new()),
+            notify_endpoint: NOTIFY_URL.to_owned(),
+            sessions_endpoint: SESSIONS_URL.to_owned(),
+            context: None,
+            context_provider: None,
+            metadata: None,
+            metadata_sections: serde_json::Map::new(),
+            dependencies: None,
+            build_info: None,
+            queue: None,
+            breadcrumbs: Arc::new(Mutex::new(breadcrumbs::Breadcrumbs::default())),
+            class_budgets: Arc::new(Mutex::new(budget::ClassBudgets::default())),
+            retry_budget: None,
+            console_output: false,
+            capture: None,
+            stable_grouping: false,
+            max_cause_depth: DEFAULT_MAX_CAUSE_DEPTH,
+            metadata_limits: DEFAULT_METADATA_LIMITS,
+            receipts: None,
+            symbolication_data: false,
+            source_snippets: false,
+            frame_rewriter: None,
+            path_prefix_rewrites: Vec::new(),
+            plugins: Plugins::default(),
+            on_error: OnErrorCallbacks::default(),
+            pseudonymizer: None,
+            coalescer: None,
+            pending: None,
+            internal_tab: Some(DEFAULT_INTERNAL_TAB.to_owned()),
+        }
+    }
+
+    /// Creates a new instance of the Bugsnag api that sends notifications to `endpoint`,
+    /// e.g. of a Bugsnag On-Premise installation, instead of the Bugsnag cloud.
+    pub fn with_endpoint(api_key: &str, project_source_dir: &str, endpoint: &str) -> Bugsnag {
+        let mut bugsnag = Bugsnag::new(api_key, project_source_dir);
+        bugsnag.set_endpoint(endpoint);
+        bugsnag
+    }
+
+    /// Notifies about a log record, for loggers that forward selected records. The level
+    /// is mapped to the severity, the target is used as context and the structured
+    /// key-values are reported in the `log` metadata tab.
+    pub fn notify_record<'bugsnag>(
+        &'bugsnag mut self,
+        record: &log::Record,
+    ) -> NotifyBuilder<'static, 'bugsnag> {
+        let severity = match record.level() {
+            log::Level::Error => Severity::Error,
+            log::Level::Warn => Severity::Warning,
+            _ => Severity::Info,
+        };
+
+        let mut key_values = KeyValues(serde_json::Map::new());
+        let _ = record.key_values().visit(&mut key_values);
+        let mut tab = key_values.0;
+        tab.insert("level".to_owned(), json!(record.level().as_str()));
+        if let Some(module_path) = record.module_path() {
+            tab.insert("modulePath".to_owned(), json!(module_path));
+        }
+        if let (Some(file), Some(line)) = (record.file(), record.line()) {
+            tab.insert("location".to_owned(), json!(format!("{file}:{line}")));
+        }
+
+        let mut builder = self
+            .notify("Log", record.args().to_string())
+            .severity(severity)
+            .context(record.target().to_owned());
+        builder
+            .tabs
+            .insert("log".to_owned(), serde_json::Value::Object(tab));
+        builder
+    }
+
+    /// Notifies about a failed JSON (de)serialization. The line, column and category of
+    /// the error are reported in the `json` metadata tab, together with `path`, the path to
+    /// the offending value if known, e.g. from `serde_path_to_error::Error::path`. A frame
+    /// for the caller is added on top of the stacktrace, as the frames of serde itself
+    /// rarely tell where the failing input was parsed.
+    #[track_caller]
+    pub fn notify_json_error<'bugsnag>(
+        &'bugsnag mut self,
+        err: &serde_json::Error,
+        path: Option<&str>,
+    ) -> NotifyBuilder<'static, 'bugsnag> {
+        let location = std::panic::Location::caller();
+        let category = match err.classify() {
+            serde_json::error::Category::Io => "io",
+            serde_json::error::Category::Syntax => "syntax",
+            serde_json::error::Category::Data => "data",
+            serde_json::error::Category::Eof => "eof",
+        };
+        let mut tab = json!({
+            "line": err.line(),
+            "column": err.column(),
+            "category": category,
+            "location": format!("{}:{}:{}", location.file(), location.line(), location.column()),
+        });
+        if let Some(path) = path {
+            tab["path"] = json!(path);
+        }
+
+        let mut builder = self.notify("serde_json::Error", err.to_string());
+        builder.tabs.insert("json".to_owned(), tab);
+        builder.call_site = Some(stacktrace::Frame::new(
+            location.file(),
+            location.line(),
+            "<deserialization>",
+            true,
+        ));
+        builder
+    }
+
+    /// Notifies about a failed soft assertion, see `bugsnag_assert!`. The notification is
+    /// a warning with the condition and the location of the caller in the `assertion`
+    /// tab, and a frame for the caller on top of the stacktrace.
+    #[track_caller]
+    pub fn notify_assertion<'a, 'bugsnag>(
+        &'bugsnag mut self,
+        condition: &str,
+        message: impl Into<Cow<'a, str>>,
+    ) -> NotifyBuilder<'a, 'bugsnag> {
+        let location = std::panic::Location::caller();
+        let message = message.into();
+        let tab = json!({
+            "condition": condition,
+            "message": message,
+            "location": format!("{}:{}:{}", location.file(), location.line(), location.column()),
+        });
+
+        let full_message = if message.is_empty() {
+            format!("assertion failed: {condition}")
+        } else {
+            format!("assertion failed: {condition}: {message}")
         };
+        let mut builder = self
+            .notify("Assertion", full_message)
+            .severity(Severity::Warning);
+        builder.tabs.insert("assertion".to_owned(), tab);
+        builder.call_site = Some(stacktrace::Frame::new(
+            location.file(),
+            location.line(),
+            "<assertion>",
+            true,
+        ));
+        builder
+    }
+
+    /// Notifies about an error and its causes. The error and every entry of its `source`
+    /// chain are reported as separate exceptions, with the type name as error class and the
+    /// `Display` output as message, so the full chain is shown instead of a flattened
+    /// string. The alternate `Debug` output of the error is added to the `error` tab.
+    pub fn notify_error<'bugsnag>(
+        &'bugsnag mut self,
+        err: &dyn StdError,
+    ) -> NotifyBuilder<'static, 'bugsnag> {
+        let mut builder = self.notify(source_error_class(err), err.to_string());
+        builder
+            .tabs
+            .insert("error".to_owned(), json!({ "debug": format!("{err:#?}") }));
+        builder.add_source_chain(err);
+        builder
+    }
+
+    /// Notifies only the first time `key` is seen in this process, e.g. for warnings about
+    /// a degraded mode that would otherwise be sent on every request. The key is used up
+    /// by the first notification that passes the filters, sampling and limits, which
+    /// reports the number of hits so far in the `once` tab. Later notifications with the
+    /// same key return `Outcome::SuppressedOnce` and are only counted, see `once_count`.
+    pub fn notify_once<'a, 'bugsnag>(
+        &'bugsnag mut self,
+        key: &str,
+        error_class: impl Into<Cow<'a, str>>,
+        message: impl Into<Cow<'a, str>>,
+    ) -> NotifyBuilder<'a, 'bugsnag> {
+        let mut builder = self.notify(error_class, message);
+        builder.once_key = Some(key.to_owned());
+        builder
+    }
+
+    /// Returns how often a notification with `key` was sent by `notify_once`, including
+    /// the suppressed ones.
+    pub fn once_count(&self, key: &str) -> u64 {
+        budget::once_key_hits(key)
+    }
+
+    /// Notifies the Bugsnag web-interface about an error.
+    /// The function returns a builder to provide more information about the error.
+    /// Error class and message can be borrowed or owned strings, so they can be computed
+    /// without keeping a temporary alive for the lifetime of the builder.
+    pub fn notify<'a, 'bugsnag>(
+        &'bugsnag mut self,
+        error_class: impl Into<Cow<'a, str>>,
+        message: impl Into<Cow<'a, str>>,
+    ) -> NotifyBuilder<'a, 'bugsnag> {
+        let rate_limit = self.config().rate_limit;
+        NotifyBuilder::new(self, error_class.into(), message.into(), rate_limit)
+    }
 
-        let entries = match std::fs::read_dir(os) {
-            Ok(entries) => entries
-                .flatten()
-                .filter(|e| match e.file_name().to_str() {
-                    Some(s) => s.starts_with(OFFLINE_REPORT_PREFIX),
-                    None => false,
+    /// Creates the frames of the captured stack, or of the `Display` output of a backtrace
+    /// of the standard library if given.
+    fn create_stacktrace(
+        &self,
+        backtrace: &backtrace::Backtrace,
+        std_backtrace: Option<&str>,
+        methods_to_ignore: Option<&[String]>,
+    ) -> Vec<stacktrace::Frame> {
+        let in_project_check = |file: &str, method: &str| {
+            self.is_in_project(file, method)
+                && methods_to_ignore.map_or(true, |ignore| {
+                    ignore.iter().any(|check| !method.contains(check.as_str()))
                 })
-                .collect::<Vec<DirEntry>>(),
-            Err(_) => return Err(Error::OfflineStorageError),
         };
 
-        for entry in entries {
-            let report = match std::fs::read_to_string(entry.path()) {
-                Ok(r) => r,
-                Err(_) => return Err(Error::OfflineStorageError),
+        match std_backtrace {
+            Some(text) => stacktrace::parse_std_backtrace(text, &in_project_check),
+            None => stacktrace::create_stacktrace(backtrace, &in_project_check),
+        }
+    }
+
+    /// Creates the frames of the stack of another thread, captured with
+    /// `NotifyBuilder::capture_all_thread_stacks`.
+    fn create_thread_stacktrace(&self, ips: &[usize]) -> Vec<stacktrace::Frame> {
+        let mut frames = stacktrace::create_stacktrace_from_ips(ips, &|file, method| {
+            self.is_in_project(file, method)
+        });
+        self.rewrite_path_prefixes(&mut frames);
+        if let Some(rewriter) = &self.frame_rewriter {
+            (rewriter.0)(&mut frames);
+        }
+        frames
+    }
+
+    /// Replaces the first matching prefix added with `add_path_prefix_rewrite` in the file
+    /// of each frame.
+    fn rewrite_path_prefixes(&self, frames: &mut [stacktrace::Frame]) {
+        if self.path_prefix_rewrites.is_empty() {
+            return;
+        }
+        for frame in frames {
+            let rewritten = self
+                .path_prefix_rewrites
+                .iter()
+                .find_map(|(from, to)| Some(format!("{to}{}", frame.file().strip_prefix(from)?)));
+            if let Some(file) = rewritten {
+                frame.set_file(&file);
+            }
+        }
+    }
+
+    /// Checks if a frame belongs to the project, by the crate of its method if project
+    /// packages are set and by its file otherwise.
+    fn is_in_project(&self, file: &str, method: &str) -> bool {
+        if self.project_packages.is_empty() {
+            file.starts_with(self.project_source_dir.as_str())
+        } else {
+            stacktrace::crate_name(method)
+                .is_some_and(|name| self.project_packages.iter().any(|p| p == name))
+        }
+    }
+
+    /// Send a json string to the Bugsnag endpoint, giving up after `timeout`.
+    fn send_with_timeout(
+        &self,
+        json: &str,
+        store_on_error: bool,
+        timeout: Option<Duration>,
+    ) -> Result<Outcome, Error> {
+        self.send_with_headers(json, &self.request_headers(), store_on_error, timeout)
+    }
+
+    /// Send a json string with the given Bugsnag headers to the Bugsnag endpoint, giving up
+    /// after `timeout`. Connection errors and server errors are retried as configured by
+    /// the retry policy of the transport options, as long as the retry fits into `timeout`.
+    #[cfg(feature = "http")]
+    fn send_with_headers(
+        &self,
+        json: &str,
+        headers: &[(&str, &str)],
+        store_on_error: bool,
+        timeout: Option<Duration>,
+    ) -> Result<Outcome, Error> {
+        #[cfg(feature = "testing")]
+        if crate::testing::deliver_to_memory(json) {
+            return Ok(Outcome::Captured);
+        }
+        #[cfg(feature = "testing")]
+        if self.transport_options.injects_failure(json) {
+            info!("Delivery failed by failure injection.");
+            return self.transfer_failed(json, store_on_error);
+        }
+
+        let first_attempt = Instant::now();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut request = self
+                .client()
+                .post(&self.notify_endpoint)
+                .body(json.to_string())
+                .header("Content-Type", "application/json");
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout.saturating_sub(first_attempt.elapsed()));
+            }
+            self.attempt_started(json, attempt);
+            let start = Instant::now();
+            let res = request.send();
+            let result = match &res {
+                Ok(response) => AttemptResult::new(response.status(), response.headers()),
+                Err(e) => AttemptResult::failed(e),
             };
+            let (transient, status) =
+                match self.attempt_finished(json, store_on_error, attempt, start, result) {
+                    ControlFlow::Break(res) => return res,
+                    ControlFlow::Continue(failure) => failure,
+                };
 
-            self.send(&report, false)?;
-            std::fs::remove_file(entry.path()).ok();
+            match self.retry_delay(attempt, transient, first_attempt, timeout) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return self.attempts_failed(json, store_on_error, status),
+            }
         }
-        Ok(())
     }
 
-    pub fn get_project_source_dir(&self) -> &String {
-        &self.project_source_dir
+    /// The Bugsnag headers of a delivery request, which are also stored with offline
+    /// reports.
+    fn request_headers(&self) -> [(&str, &str); 2] {
+        [
+            ("Bugsnag-Api-Key", &self.api_key),
+            ("Bugsnag-Payload-Version", notification::PAYLOAD_VERSION),
+        ]
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{Bugsnag, RateLimit, SendLimit};
-    use std::path::PathBuf;
+    /// Traces a delivery attempt and notifies the transport hook about it.
+    #[cfg(feature = "http")]
+    fn attempt_started(&self, json: &str, attempt: u32) {
+        trace::decision!("delivery attempt {attempt} to {}", self.notify_endpoint);
+        self.transport_options
+            .request_sent(&transport::RequestInfo {
+                endpoint: &self.notify_endpoint,
+                attempt,
+                body_len: json.len(),
+            });
+    }
 
-    #[test]
-    fn test_get_project_dir() {
-        let api = Bugsnag::new("api-key", "my-dir");
-        assert_eq!(api.get_project_source_dir(), "my-dir");
+    /// Records the result of a delivery attempt started at `start`. Breaks with the
+    /// outcome of the delivery if it is finished, and continues with whether the failure
+    /// is transient and the status of the response otherwise.
+    #[cfg(feature = "http")]
+    fn attempt_finished(
+        &self,
+        json: &str,
+        store_on_error: bool,
+        attempt: u32,
+        start: Instant,
+        result: AttemptResult,
+    ) -> ControlFlow<Result<Outcome, Error>, (bool, Option<DeliveryStatus>)> {
+        let status = match &result {
+            AttemptResult::Response { status, .. } => Some(*status),
+            AttemptResult::Failed { .. } => None,
+        };
+        instrumentation::delivery_latency(start.elapsed(), status.is_some());
+        self.transport_options
+            .response_received(&transport::ResponseInfo {
+                endpoint: &self.notify_endpoint,
+                attempt,
+                status,
+                latency: start.elapsed(),
+            });
+
+        match result {
+            AttemptResult::Response {
+                status,
+                retry_after,
+            } => {
+                trace::decision!("response {status}");
+                let status = self.response_status(json, status, retry_after.as_deref());
+                if status != DeliveryStatus::ServerError {
+                    return ControlFlow::Break(self.delivery_finished(
+                        json,
+                        store_on_error,
+                        status,
+                    ));
+                }
+                ControlFlow::Continue((true, Some(status)))
+            }
+            AttemptResult::Failed { connect, error } => {
+                trace::decision!("delivery failed: {error}");
+                ControlFlow::Continue((connect, None))
+            }
+        }
+    }
+
+    /// Returns how long to wait before the next attempt, if the failed `attempt` is to be
+    /// retried and the retry fits into `timeout`.
+    #[cfg(feature = "http")]
+    fn retry_delay(
+        &self,
+        attempt: u32,
+        transient: bool,
+        first_attempt: Instant,
+        timeout: Option<Duration>,
+    ) -> Option<Duration> {
+        let delay = match self.transport_options.retry_policy() {
+            Some(policy) if transient && attempt < policy.attempts() => policy.delay(attempt),
+            _ => return None,
+        };
+        if timeout.is_some_and(|timeout| first_attempt.elapsed() + delay >= timeout) {
+            return None;
+        }
+        info!("Delivery failed, retrying in {delay:?}.");
+        Some(delay)
+    }
+
+    /// Turns the last failed attempt into the outcome of the delivery.
+    #[cfg(feature = "http")]
+    fn attempts_failed(
+        &self,
+        json: &str,
+        store_on_error: bool,
+        last_status: Option<DeliveryStatus>,
+    ) -> Result<Outcome, Error> {
+        match last_status {
+            Some(status) => self.delivery_finished(json, store_on_error, status),
+            None => self.transfer_failed(json, store_on_error),
+        }
+    }
+
+    /// Records the receipt of a response to the delivery of `json` and classifies it.
+    #[cfg(feature = "http")]
+    fn response_status(
+        &self,
+        json: &str,
+        status: u16,
+        retry_after: Option<&str>,
+    ) -> DeliveryStatus {
+        if let (Some(path), Some(tab)) = (&self.receipts, &self.internal_tab) {
+            if let Err(e) = receipts::record(path, json, tab, status) {
+                warn!("failed to record delivery receipt: {e}");
+            }
+        }
+        DeliveryStatus::from_response(status, retry_after)
+    }
+
+    /// Turns the response of Bugsnag into the outcome of the delivery. Notifications that
+    /// may be accepted later are stored offline if `store_on_error` is set, rejected ones
+    /// are dropped.
+    #[cfg(feature = "http")]
+    fn delivery_finished(
+        &self,
+        json: &str,
+        store_on_error: bool,
+        status: DeliveryStatus,
+    ) -> Result<Outcome, Error> {
+        match status {
+            DeliveryStatus::Delivered => {
+                instrumentation::event_sent();
+                Ok(Outcome::Delivered)
+            }
+            DeliveryStatus::RejectedBadRequest => {
+                warn!("Bugsnag rejected the notification.");
+                Err(Error::Rejected(status))
+            }
+            _ if store_on_error => self.store_offline(json).map(Outcome::StoredOffline),
+            _ => Err(Error::Rejected(status)),
+        }
+    }
+
+    /// Send a json string to the Bugsnag endpoint with the async HTTP client, giving up
+    /// after `timeout`. Failures are retried like in `send_with_timeout`, without blocking
+    /// the runtime while waiting for the retry. Failed deliveries are stored offline.
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    async fn send_async_with_timeout(
+        &self,
+        json: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Outcome, Error> {
+        #[cfg(feature = "testing")]
+        if self.transport_options.injects_failure(json) {
+            info!("Delivery failed by failure injection.");
+            return self.transfer_failed(json, true);
+        }
+
+        let first_attempt = Instant::now();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut request = self
+                .async_client()
+                .post(&self.notify_endpoint)
+                .body(json.to_string())
+                .header("Content-Type", "application/json");
+            for (name, value) in self.request_headers() {
+                request = request.header(name, value);
+            }
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout.saturating_sub(first_attempt.elapsed()));
+            }
+            self.attempt_started(json, attempt);
+            let start = Instant::now();
+            let res = request.send().await;
+            let result = match &res {
+                Ok(response) => AttemptResult::new(response.status(), response.headers()),
+                Err(e) => AttemptResult::failed(e),
+            };
+            let (transient, status) =
+                match self.attempt_finished(json, true, attempt, start, result) {
+                    ControlFlow::Break(res) => return res,
+                    ControlFlow::Continue(failure) => failure,
+                };
+
+            match self.retry_delay(attempt, transient, first_attempt, timeout) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return self.attempts_failed(json, true, status),
+            }
+        }
+    }
+
+    /// Without the `http` feature notifications can not be sent, so they are stored in
+    /// the offline storage for an uploader process.
+    #[cfg(not(feature = "http"))]
+    fn send_with_headers(
+        &self,
+        json: &str,
+        _headers: &[(&str, &str)],
+        store_on_error: bool,
+        _timeout: Option<Duration>,
+    ) -> Result<Outcome, Error> {
+        info!("HTTP delivery is not available, the `http` feature is disabled.");
+        self.transfer_failed(json, store_on_error)
+    }
+
+    fn transfer_failed(&self, json: &str, store_on_error: bool) -> Result<Outcome, Error> {
+        trace::decision!("delivery failed, store offline: {store_on_error}");
+        if store_on_error {
+            return self.store_offline(json).map(Outcome::StoredOffline);
+        }
+        Err(Error::JsonTransferFailed)
+    }
+
+    /// Delivers a notification, either directly or through the background queue.
+    /// If the queue is full, the notification is spilled into the offline storage and
+    /// transmitted later by the background retrier.
+    fn deliver(&self, json: String, deadline: Option<Instant>) -> Result<Outcome, Error> {
+        match self.route(json, deadline) {
+            ControlFlow::Break(res) => res,
+            ControlFlow::Continue(json) => self.send_before(&json, deadline),
+        }
+    }
+
+    /// Sends a notification to the endpoint, giving up at `deadline`. Notifications that
+    /// are not delivered in time are stored offline.
+    pub(crate) fn send_before(
+        &self,
+        json: &str,
+        deadline: Option<Instant>,
+    ) -> Result<Outcome, Error> {
+        match time_left(deadline) {
+            Some(left) if left.is_zero() => {
+                info!("Deadline passed before the delivery. Storing notification offline.");
+                self.transfer_failed(json, true)
+            }
+            timeout => self.send_with_timeout(json, true, timeout),
+        }
+    }
+
+    /// Delivers a notification like `deliver`, but with the async HTTP client.
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    async fn deliver_async(
+        &self,
+        json: String,
+        deadline: Option<Instant>,
+    ) -> Result<Outcome, Error> {
+        let json = match self.route(json, deadline) {
+            ControlFlow::Break(res) => return res,
+            ControlFlow::Continue(json) => json,
+        };
+        match time_left(deadline) {
+            Some(left) if left.is_zero() => {
+                info!("Deadline passed before the delivery. Storing notification offline.");
+                self.transfer_failed(&json, true)
+            }
+            timeout => self.send_async_with_timeout(&json, timeout).await,
+        }
+    }
+
+    /// Hands a notification to the console, the capture callback or the background
+    /// queue if they are used. Queued notifications are delivered by the worker before
+    /// `deadline`. Continues with the notification if it has to be sent to the endpoint by
+    /// the caller.
+    fn route(
+        &self,
+        json: String,
+        deadline: Option<Instant>,
+    ) -> ControlFlow<Result<Outcome, Error>, String> {
+        #[cfg(feature = "testing")]
+        if crate::testing::deliver_to_memory(&json) {
+            return ControlFlow::Break(Ok(Outcome::Captured));
+        }
+
+        if self.console_output {
+            trace::decision!("written to the console");
+            eprint!("{}", console::render(&json, console::use_color()));
+            return ControlFlow::Break(Ok(Outcome::WrittenToConsole));
+        }
+
+        if let Some(capture) = &self.capture {
+            trace::decision!("handed to the capture callback");
+            (capture.0)(&json);
+            return ControlFlow::Break(Ok(Outcome::Captured));
+        }
+
+        let Some(queue) = &self.queue else {
+            return ControlFlow::Continue(json);
+        };
+
+        let delivery = queue::Delivery {
+            bugsnag: Box::new(self.without_queue()),
+            json,
+            deadline,
+            event_id: trace::current_event(),
+        };
+
+        ControlFlow::Break(match queue.enqueue(delivery) {
+            Ok(()) => {
+                trace::decision!("queued, queue depth {}", queue.depth());
+                Ok(Outcome::Queued)
+            }
+            Err(delivery) => {
+                info!("Delivery queue is full. Notification is spilled to offline storage.");
+                self.store_offline(&delivery.json)
+                    .map(Outcome::StoredOffline)
+            }
+        })
+    }
+
+    /// Writes a notification to the offline storage and returns the path of the report.
+    /// The report is stored with the headers needed to deliver it, so it can also be
+    /// transmitted by `upload_stored_reports`.
+    fn store_offline(&self, json: &str) -> Result<PathBuf, Error> {
+        let os = match &self.offline_storage {
+            Some(os) => os,
+            None => return Err(Error::JsonTransferAndStorageFailed),
+        };
+        let name = PathBuf::from(format!(
+            "{os}/{}_{}",
+            envelope::REPORT_PREFIX,
+            uuid::Uuid::new_v4()
+        ));
+        let report = envelope::wrap(&self.request_headers(), json);
+        if let Err(e) = std::fs::write(&name, report) {
+            trace::decision!("storing offline at {} failed: {e}", name.display());
+            return Err(Error::JsonTransferAndStorageFailed);
+        }
+        trace::decision!("stored offline at {}", name.display());
+        Ok(name)
+    }
+
+    /// Returns a clone of this instance that sends directly instead of using the queue.
+    /// The delivery thread uses such a clone, so the queue is closed when the last
+    /// instance using it is dropped.
+    fn without_queue(&self) -> Bugsnag {
+        Bugsnag {
+            queue: None,
+            ..self.clone()
+        }
+    }
+
+    /// Delivers notifications from a background thread. `send` then only enqueues
+    /// the notification and returns. When more than `capacity` notifications are
+    /// waiting, further notifications are written to the offline storage. While no
+    /// notifications are waiting, reports from the offline storage are retransmitted
+    /// every `retry_interval`. Notifications of panics are still send directly, as the
+    /// process may abort before the worker thread gets to them.
+    pub fn use_background_delivery(&mut self, capacity: usize, retry_interval: Duration) {
+        let bugsnag = self.without_queue();
+        self.queue = Some(Arc::new(queue::DeliveryQueue::new(capacity, move |rx| {
+            queue::deliver(rx, bugsnag, retry_interval)
+        })));
+    }
+
+    /// Adds the raw addresses of all frames, with the load addresses of their modules, and
+    /// the build id of the executable as `symbolication` metadata tab. A symbolication
+    /// service can use them to post-process reports of stripped release builds.
+    pub fn set_symbolication_data(&mut self, enabled: bool) {
+        self.symbolication_data = enabled;
+    }
+
+    /// Attaches up to 3 lines of source code before and after the line of each frame in
+    /// project, so the dashboard shows the code without a source control integration.
+    /// The source files are read when the notification is sent, so this only works where
+    /// they are available, e.g. during development or in containers that ship the sources.
+    pub fn set_source_snippets(&mut self, enabled: bool) {
+        self.source_snippets = enabled;
+    }
+
+    /// Sets a hook that rewrites the frames of every notification before it is sent, e.g.
+    /// to symbolicate addresses of stripped binaries or to drop frames of wrappers.
+    pub fn set_frame_rewriter<F>(&mut self, rewriter: F)
+    where
+        F: Fn(&mut Vec<stacktrace::Frame>) + Send + Sync + 'static,
+    {
+        self.frame_rewriter = Some(FrameRewriter(Arc::new(rewriter)));
+    }
+
+    /// Rewrites the files of frames that start with `from` to start with `to` instead, e.g.
+    /// `/build/workspace/` to `src/` for builds in containers, so the frames link to the
+    /// source layout of the repository. Rewrites are tried in the order they are added and
+    /// the first matching one is applied. Frames are assigned to the project before their
+    /// files are rewritten, and `set_frame_rewriter` sees the rewritten files.
+    pub fn add_path_prefix_rewrite(&mut self, from: &str, to: &str) {
+        self.path_prefix_rewrites
+            .push((from.to_owned(), to.to_owned()));
+    }
+
+    /// Registers a plugin that enriches every event before it is sent. Plugins run after
+    /// the built-in enrichers, in the order of their registration.
+    pub fn register_plugin<P: plugin::Plugin + 'static>(&mut self, plugin: P) {
+        self.plugins.0.push(Arc::new(plugin));
+    }
+
+    /// Adds a callback that can change or discard every event before it is serialized,
+    /// e.g. to add the id of the tenant or to drop known noisy errors. The callbacks run
+    /// in the order they were added, after the plugins and before the callback of
+    /// `NotifyBuilder::on_before_send`. If one returns `false`, the event is discarded,
+    /// which is reported as `Outcome::DiscardedByFilter`, and later callbacks are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+    /// api.add_on_error(|event| {
+    ///     event.add_metadata("tenant", "id", "acme".into());
+    ///     event.error_class() != "Io::BrokenPipe"
+    /// });
+    /// ```
+    pub fn add_on_error<F>(&mut self, callback: F)
+    where
+        F: Fn(&mut plugin::Event) -> bool + Send + Sync + 'static,
+    {
+        self.on_error.0.push(Arc::new(callback));
+    }
+
+    /// Coalesces notifications with the same error class and message that are sent within
+    /// `window` of each other into one event, with the number of occurrences and the
+    /// names of the threads in the `coalesced` tab. The first notification is held back by
+    /// the timer thread for `window` and returns `Outcome::Queued`, the others return
+    /// `Outcome::Coalesced`. So with coalescing, `NotifyBuilder::send` returns
+    /// `Outcome::Queued` instead of the outcome of the delivery, even without background
+    /// delivery. No caller waits for the window, see `flush` to wait for the held back
+    /// notifications. Panics and notifications sent with
+    /// `NotifyBuilder::send_with_deadline` are not coalesced, they are sent on their own.
+    /// `None` sends every notification on its own.
+    pub fn set_coalescing(&mut self, window: Option<Duration>) {
+        self.coalescer = window.map(|window| Arc::new(coalesce::Coalescer::new(window)));
+    }
+
+    /// Replaces the hostname and the id, name and email of the user of every event by an
+    /// HMAC-SHA256 with `key` before it is sent, so events can be correlated by host and
+    /// user without exposing the raw identifiers. `None` sends the identifiers unchanged.
+    pub fn set_pseudonymization_key(&mut self, key: Option<&[u8]>) {
+        self.pseudonymizer = key.map(pseudonym::Pseudonymizer::new);
+    }
+
+    /// Sets the name of the metadata tab with the time the notification was created, the
+    /// event id and the monotonic clock reading, `bugsnag-rs` by default, e.g. if the
+    /// application uses a tab of that name itself. `None` removes the tab, which also stops
+    /// the delivery receipts of `use_delivery_receipts` and the correction of the time of
+    /// stored events at upload, as both read the tab.
+    pub fn set_internal_tab(&mut self, name: Option<&str>) {
+        #[cfg(feature = "testing")]
+        if let Some(name) = name {
+            crate::testing::register_internal_tab(name);
+        }
+        self.internal_tab = name.map(|name| name.to_owned());
+    }
+
+    /// Records a receipt for every delivered event in the append-only log at `path`, with
+    /// the id of the event, the time of the delivery and the HTTP status. The event id is
+    /// read from the internal metadata tab, `bugsnag-rs` by default, which also matches
+    /// receipts with events. No receipts are recorded while `set_internal_tab` removes the
+    /// tab.
+    pub fn use_delivery_receipts(&mut self, path: &str) {
+        self.receipts = Some(PathBuf::from(path));
+    }
+
+    /// Prints notifications to stderr instead of sending them, for local development
+    /// before the project is set up with a real api key.
+    pub fn use_console_output(&mut self, enabled: bool) {
+        self.console_output = enabled;
+    }
+
+    /// Hands every serialized notification to `capture` instead of sending it, e.g. to
+    /// write it to a store that a host tool uploads later with `upload_stored_reports`.
+    /// This is the only delivery besides the offline storage when the crate is built
+    /// without the `http` feature.
+    pub fn set_capture_callback<F>(&mut self, capture: F)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.capture = Some(CaptureCallback(Arc::new(capture)));
+    }
+
+    /// Creates a ring of `capacity` errors, at least two, in which errors can be recorded with the
+    /// returned handle without blocking, e.g. from the frame thread of a game. The errors
+    /// are reported by `pump`.
+    pub fn use_pending_errors(&mut self, capacity: usize) -> pending::PendingErrors {
+        let pending = pending::PendingErrors::new(capacity);
+        self.pending = Some(pending.clone());
+        pending
+    }
+
+    /// Reports the errors recorded in the ring created by `use_pending_errors` and returns
+    /// their number. Call this regularly from a thread that may block, e.g. a low-priority
+    /// tick. The `pending` tab contains how long each error waited and how many errors
+    /// were dropped because the ring was full. Errors recorded without frames are reported
+    /// without stacktrace, as the stack of this thread is unrelated to them.
+    pub fn pump(&mut self) -> usize {
+        let Some(pending) = self.pending.clone() else {
+            return 0;
+        };
+
+        let mut count = 0;
+        while let Some(error) = pending.take() {
+            let builder = self.notify(error.error_class, error.message);
+            let mut builder = match &error.frames {
+                Some(frames) => builder.raw_frames(frames),
+                None => builder.stacktrace(Vec::new()),
+            };
+            builder.tabs.insert(
+                "pending".to_owned(),
+                json!({
+                    "delayMs": error.recorded.elapsed().as_millis() as u64,
+                    "dropped": pending.take_dropped(),
+                }),
+            );
+            let _ = builder.send();
+            count += 1;
+        }
+        count
+    }
+
+    /// Waits until the notifications queued for background delivery or held back for
+    /// coalescing are delivered or stored offline, for at most `timeout`. Returns `false`
+    /// if notifications are still unfinished after the timeout.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let unfinished = || {
+            self.coalescer.as_ref().map_or(0, |c| c.deferred())
+                + self.queue.as_ref().map_or(0, |q| q.unfinished())
+        };
+
+        let deadline = Instant::now() + timeout;
+        while unfinished() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    /// Creates a report of the effective configuration and of the delivery state, see the
+    /// `diagnostics` module. Tries to connect to the notify endpoint, which blocks for up
+    /// to a few seconds.
+    pub fn diagnostics(&self) -> diagnostics::Diagnostics {
+        let config = self.config();
+        let rate_limits = config
+            .rate_limit
+            .clone()
+            .map(|mut rate_limit| rate_limit.usage())
+            .unwrap_or_default();
+
+        diagnostics::Diagnostics {
+            version: env!("CARGO_PKG_VERSION"),
+            features: diagnostics::features(),
+            api_key: diagnostics::mask_api_key(&self.api_key),
+            config,
+            notify_endpoint: self.notify_endpoint.clone(),
+            sessions_endpoint: self.sessions_endpoint.clone(),
+            endpoint_error: diagnostics::check_endpoint(&self.notify_endpoint),
+            offline_storage: self.offline_storage.clone(),
+            offline_backlog: self
+                .offline_storage
+                .as_ref()
+                .and_then(|dir| envelope::stored_reports(Path::new(dir)).ok())
+                .map(|reports| reports.len()),
+            queue_depth: self.queue.as_ref().map(|queue| queue.depth()),
+            rate_limits,
+            console_output: self.console_output,
+            capture_callback: self.capture.is_some(),
+            plugins: self
+                .plugins
+                .0
+                .iter()
+                .map(|plugin| plugin.name().to_owned())
+                .collect(),
+            transport: format!("{:?}", self.transport_options),
+        }
+    }
+
+    /// Returns the number of notifications waiting for background delivery.
+    pub fn queue_depth(&self) -> usize {
+        self.queue.as_ref().map(|q| q.depth()).unwrap_or(0)
+    }
+
+    pub(crate) fn has_offline_storage(&self) -> bool {
+        self.offline_storage.is_some()
+    }
+
+    /// Returns the HTTP client, creating it on first use.
+    #[cfg(feature = "http")]
+    fn client(&self) -> &reqwest::blocking::Client {
+        self.client
+            .get_or_init(|| self.transport_options.build_client())
+    }
+
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    fn async_client(&self) -> &reqwest::Client {
+        self.async_client
+            .get_or_init(|| self.transport_options.build_async_client())
+    }
+
+    /// Sets the options of the HTTP client used to deliver notifications.
+    /// The client is recreated with the new options on the next notification.
+    pub fn set_transport_options(&mut self, options: transport::TransportOptions) {
+        self.transport_options = options;
+        #[cfg(feature = "http")]
+        {
+            self.client = Arc::new(OnceLock::new());
+        }
+        #[cfg(all(feature = "http", feature = "tokio"))]
+        {
+            self.async_client = Arc::new(OnceLock::new());
+        }
+    }
+
+    /// Sets how long connecting to the endpoint and a whole delivery may take, so a slow
+    /// network can not hang e.g. a panic hook during shutdown. Deliveries that time out
+    /// are stored offline. Shorthand for the corresponding transport options.
+    pub fn set_timeouts(&mut self, connect: Duration, total: Duration) {
+        let options = self
+            .transport_options
+            .clone()
+            .connect_timeout(Some(connect))
+            .timeout(Some(total));
+        self.set_transport_options(options);
+    }
+
+    /// Retries deliveries that failed with a connection error or a server error as
+    /// configured by `policy`, before they are stored offline. Shorthand for the
+    /// corresponding transport option.
+    pub fn set_retry_policy(&mut self, policy: transport::RetryPolicy) {
+        let options = self.transport_options.clone().retry(Some(policy));
+        self.set_transport_options(options);
+    }
+
+    /// Sets the endpoint notifications are sent to, e.g. the ingestion host of a Bugsnag
+    /// On-Premise installation. The sessions endpoint is not changed.
+    pub fn set_endpoint(&mut self, notify: &str) {
+        self.set_endpoints(notify, &self.sessions_endpoint.clone());
+    }
+
+    /// Sets the endpoints notifications and sessions are sent to, e.g. for
+    /// Bugsnag On-Premise installations. Sessions are sent by `start_session`.
+    pub fn set_endpoints(&mut self, notify: &str, sessions: &str) {
+        self.notify_endpoint = notify.to_owned();
+        self.sessions_endpoint = sessions.to_owned();
+    }
+
+    pub fn get_notify_endpoint(&self) -> &String {
+        &self.notify_endpoint
+    }
+
+    pub fn get_sessions_endpoint(&self) -> &String {
+        &self.sessions_endpoint
+    }
+
+    /// Reports the start of a session, e.g. of a request or a job, to the sessions
+    /// endpoint, from which Bugsnag computes the stability of releases. Unlike
+    /// notifications, sessions are neither queued, retried nor stored offline.
+    pub fn start_session(&self) -> Result<(), Error> {
+        let config = self.config();
+        if !config.enabled || !self.notifies_release_stage(&config) {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let json = serde_json::to_string(&notification::Sessions::new(
+            &self.app_info,
+            &self.device_info,
+            now,
+        ))
+        .map_err(|_| Error::JsonConversionFailed)?;
+        self.send_session(json, now)
+    }
+
+    #[cfg(feature = "http")]
+    fn send_session(&self, json: String, sent_at: DateTime<Utc>) -> Result<(), Error> {
+        let response = self
+            .client()
+            .post(&self.sessions_endpoint)
+            .body(json)
+            .header("Content-Type", "application/json")
+            .header("Bugsnag-Api-Key", &self.api_key)
+            .header(
+                "Bugsnag-Payload-Version",
+                notification::SESSION_PAYLOAD_VERSION,
+            )
+            .header("Bugsnag-Sent-At", sent_at.to_rfc3339())
+            .send()
+            .map_err(|_| Error::JsonTransferFailed)?;
+        let retry_after = response.headers().get("Retry-After");
+        match DeliveryStatus::from_response(
+            response.status().as_u16(),
+            retry_after.and_then(|v| v.to_str().ok()),
+        ) {
+            DeliveryStatus::Delivered => Ok(()),
+            status => Err(Error::Rejected(status)),
+        }
+    }
+
+    #[cfg(not(feature = "http"))]
+    fn send_session(&self, _json: String, _sent_at: DateTime<Utc>) -> Result<(), Error> {
+        info!("HTTP delivery is not available, the `http` feature is disabled.");
+        Err(Error::JsonTransferFailed)
+    }
+
+    /// Sets information about the device. These information will be send to
+    /// Bugsnag when notify is called.
+    pub fn set_device_info(&mut self, hostname: Option<&str>, version: Option<&str>) {
+        if let Some(name) = hostname {
+            self.device_info.set_hostname(name);
+        }
+
+        if let Some(ver) = version {
+            self.device_info.set_os_version(ver);
+        }
+    }
+
+    /// Adds the version of a runtime the app uses to the `runtimeVersions` of the device,
+    /// e.g. of an embedded interpreter. The version of `rustc` the binary was built with
+    /// is always reported.
+    pub fn add_runtime_version(&mut self, runtime: &str, version: &str) {
+        self.device_info.add_runtime_version(runtime, version);
+    }
+
+    /// Sets information about the application that uses this api. These information
+    /// will be send to Bugsnag when notify is called.
+    pub fn set_app_info(
+        &mut self,
+        version: Option<&str>,
+        release_stage: Option<&str>,
+        atype: Option<&str>,
+    ) {
+        self.app_info = Some(appinfo::AppInfo::new(version, release_stage, atype));
+    }
+
+    /// Sets all information about the application that uses this api, e.g. the build
+    /// UUID, see `appinfo::AppInfo::builder`.
+    pub fn set_app(&mut self, app: appinfo::AppInfo) {
+        self.app_info = Some(app);
+    }
+
+    /// Sets a callback that provides the app version when a notification is created,
+    /// e.g. the version of a hot-loaded bundle that handles the current request. If it
+    /// returns `None`, the version set with `set_app_info` is reported.
+    pub fn set_app_version_provider<F>(&mut self, provider: F)
+    where
+        F: Fn() -> Option<String> + Send + Sync + 'static,
+    {
+        self.app_version_provider = Some(Provider(Arc::new(provider)));
+    }
+
+    pub fn reset_app_info(&mut self) {
+        self.app_info = None;
+    }
+
+    pub fn set_user(&mut self, user: user::User) {
+        self.user = Some(user);
+    }
+
+    /// Sets a callback that provides the user when a notification is created, e.g. from a
+    /// thread or task local of the current request. If it returns `None`, the user set with
+    /// `set_user` is reported.
+    pub fn set_user_provider<F>(&mut self, provider: F)
+    where
+        F: Fn() -> Option<user::User> + Send + Sync + 'static,
+    {
+        self.user_provider = Some(Provider(Arc::new(provider)));
+    }
+
+    /// Sets the user of the device, e.g. the operator or the machine account of a kiosk,
+    /// as opposed to the user of the application set with `set_user`, e.g. the customer.
+    /// If a device user is set, events report it in the `deviceUser` tab and the user of
+    /// the application in the `appUser` tab, while the user of the event stays the user
+    /// of the application.
+    pub fn set_device_user(&mut self, user: user::User) {
+        self.device_user = Some(user);
+    }
+
+    /// Sets a callback that provides the user of the device when a notification is
+    /// created, e.g. the operator logged in at a kiosk. If it returns `None`, the user set
+    /// with `set_device_user` is reported.
+    pub fn set_device_user_provider<F>(&mut self, provider: F)
+    where
+        F: Fn() -> Option<user::User> + Send + Sync + 'static,
+    {
+        self.device_user_provider = Some(Provider(Arc::new(provider)));
+    }
+
+    /// Creates a child instance for a subsystem, e.g. a plugin or a tenant.
+    /// The child shares the transport and configuration with this instance, but context,
+    /// metadata, user and app info can be overridden without affecting this instance.
+    pub fn child(&self) -> Bugsnag {
+        self.clone()
+    }
+
+    /// Sets the API key the notifications are sent with.
+    pub(crate) fn set_api_key(&mut self, api_key: &str) {
+        api_key.clone_into(&mut self.api_key);
+    }
+
+    /// Sets the context that is used when a notification does not set its own context.
+    pub fn set_context(&mut self, context: &str) {
+        self.context = Some(context.to_owned());
+    }
+
+    /// Sets a callback that provides the context when a notification is created and
+    /// neither the notification nor this instance has a context set, e.g. to derive
+    /// `worker:email-digest` from the job the current thread runs.
+    pub fn set_context_provider<F>(&mut self, provider: F)
+    where
+        F: Fn() -> Option<String> + Send + Sync + 'static,
+    {
+        self.context_provider = Some(Provider(Arc::new(provider)));
+    }
+
+    /// Sets metadata that is sent with every notification. Metadata set on a
+    /// notification is merged with this metadata and wins on conflict.
+    pub fn set_metadata(&mut self, val: &impl Serialize) -> Result<(), Error> {
+        let json_val = match bounded::to_value(val, self.metadata_limits) {
+            Ok(v) => v,
+            Err(_) => return Err(Error::JsonConversionFailed),
+        };
+        self.metadata = Some(json_val);
+        Ok(())
+    }
+
+    /// Adds a value to a section of the metadata that is sent with every notification,
+    /// e.g. the build hash or the name of the Kubernetes pod. The section becomes a tab of
+    /// the event. Values set on a notification, e.g. with `NotifyBuilder::metadata` for
+    /// the `metaData` section, are merged with these values and win on conflict.
+    pub fn add_metadata(&mut self, section: &str, key: &str, value: serde_json::Value) {
+        let value = bounded::to_value(&value, self.metadata_limits).unwrap_or(value);
+        let section = self
+            .metadata_sections
+            .entry(section.to_owned())
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+
+        if !section.is_object() {
+            *section = serde_json::Value::Object(serde_json::Map::new());
+        }
+        section[key] = value;
+    }
+
+    /// Removes a section added with `add_metadata`.
+    pub fn clear_metadata(&mut self, section: &str) {
+        self.metadata_sections.remove(section);
+    }
+
+    /// Records a breadcrumb that is sent with the following notifications of this
+    /// instance and all of its clones.
+    pub fn leave_breadcrumb(
+        &self,
+        name: &str,
+        btype: breadcrumbs::BreadcrumbType,
+        metadata: Option<serde_json::Value>,
+    ) {
+        self.breadcrumbs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(breadcrumbs::Breadcrumb::new(name, btype, metadata));
+    }
+
+    /// Sets the number of breadcrumbs that are kept, the oldest are dropped first.
+    pub fn set_max_breadcrumbs(&self, max: usize) {
+        self.breadcrumbs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_max(max);
+    }
+
+    /// Sets the dependencies the application was built with, which are sent in the
+    /// `dependencies` tab. The dependencies are a json object of crate name to version,
+    /// as created by `bugsnag::dependencies!()` with the `dependency-manifest` feature.
+    pub fn set_dependencies(&mut self, manifest: &str) -> Result<(), Error> {
+        match serde_json::from_str(manifest) {
+            Ok(deps) => {
+                self.dependencies = Some(deps);
+                Ok(())
+            }
+            Err(_) => Err(Error::JsonConversionFailed),
+        }
+    }
+
+    /// Sets the build settings of the application, which are sent in the `app` tab instead
+    /// of the ones of the build of this crate. The settings are a json object as created
+    /// by `bugsnag::build_info!()` with the `dependency-manifest` feature.
+    pub fn set_build_info(&mut self, build_info: &str) -> Result<(), Error> {
+        match serde_json::from_str(build_info) {
+            Ok(serde_json::Value::Object(build_info)) => {
+                self.build_info = Some(serde_json::Value::Object(build_info));
+                Ok(())
+            }
+            _ => Err(Error::JsonConversionFailed),
+        }
+    }
+
+    pub fn use_offline_storage(&mut self, storage: &str) {
+        self.offline_storage = Some(storage.to_string())
+    }
+
+    pub fn rate_limit(&mut self, rate_limit: RateLimit) {
+        self.config
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .rate_limit = Some(rate_limit);
+    }
+
+    /// Sends an info event announcing that the app started, with its version, release stage
+    /// and a hash of the configuration. This helps to confirm which instances run a new
+    /// release during a rollout.
+    pub fn report_startup(&mut self) -> Result<Outcome, Error> {
+        let config = self.config();
+        let version = self
+            .app_info
+            .as_ref()
+            .and_then(|a| a.version())
+            .map(|v| v.to_owned());
+        let release_stage = config.release_stage.clone().or_else(|| {
+            self.app_info
+                .as_ref()
+                .and_then(|a| a.release_stage())
+                .map(|s| s.to_owned())
+        });
+
+        // the sent notifications of the rate limits change with the traffic
+        let mut hashed = config.clone();
+        hashed.rate_limit = hashed.rate_limit.map(RateLimit::without_state);
+        hashed.panic_rate_limit = hashed.panic_rate_limit.map(RateLimit::without_state);
+        let mut hasher = hashing::StableHasher::default();
+        hasher.write(
+            serde_json::to_string(&hashed)
+                .map_err(|_| Error::JsonConversionFailed)?
+                .as_bytes(),
+        );
+        hasher.write(self.notify_endpoint.as_bytes());
+        hasher.write(self.sessions_endpoint.as_bytes());
+        let config_hash = hasher.finish_hex();
+
+        let message = format!(
+            "{} started",
+            version.as_deref().unwrap_or("unknown version")
+        );
+        self.notify("Startup", message)
+            .severity(Severity::Info)
+            .context("startup")
+            .grouping_hash("startup")
+            .metadata(&json!({"startup": {
+                "version": version,
+                "releaseStage": release_stage,
+                "configHash": config_hash,
+            }}))?
+            .send()
+    }
+
+    /// Returns a copy of the current configuration.
+    pub fn config(&self) -> config::Config {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Atomically replaces the configuration of this instance and all of its clones.
+    /// Notifications that are already being sent keep using the previous configuration.
+    pub fn reload_config(&self, mut config: config::Config) {
+        config.rate_limit = config.rate_limit.map(RateLimit::synchronized);
+        config.panic_rate_limit = config.panic_rate_limit.map(RateLimit::synchronized);
+        // keys read from a file may not be lowercase
+        for key in &mut config.redacted_keys {
+            *key = key.to_lowercase();
+        }
+
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+
+    /// Limits how many stored reports `retry_from_storage` and the background retrier
+    /// transmit per `window`, e.g. 100 per minute, so a device that comes back online
+    /// after a long time does not send its whole backlog at once. The remaining reports
+    /// stay stored for later retries. The budget is shared with the clones and children
+    /// of this instance.
+    pub fn set_offline_retry_budget(&mut self, max_reports: u32, window: Duration) {
+        self.retry_budget = Some(Arc::new(Mutex::new(budget::RetryBudget::new(
+            max_reports,
+            window,
+        ))));
+    }
+
+    /// Retransmits the reports of the offline storage and removes them. The reports are
+    /// send with their stored headers, so reports of other API keys go to their project.
+    /// Reports stored several times are sent once, with the number of copies in the
+    /// `offline` tab. Stops early if the budget set with `set_offline_retry_budget` is
+    /// used up.
+    pub fn retry_from_storage(&self) -> Result<(), Error> {
+        let os = match &self.offline_storage {
+            Some(storage) => storage,
+            None => return Err(Error::OfflineStorageError),
+        };
+
+        let reports = match envelope::read_stored_reports(Path::new(os)) {
+            Ok(reports) => reports,
+            Err(_) => return Err(Error::OfflineStorageError),
+        };
+
+        let total = reports.len();
+        for (i, report) in reports.into_iter().enumerate() {
+            let within_budget = self.retry_budget.as_ref().map_or(true, |budget| {
+                budget.lock().unwrap_or_else(PoisonError::into_inner).take()
+            });
+            if !within_budget {
+                info!(
+                    "Offline retry budget used up, {} stored reports are retried later.",
+                    total - i
+                );
+                break;
+            }
+
+            let (headers, json) = report.split();
+            let headers = envelope::complete_headers(headers, &self.request_headers());
+            match self.send_with_headers(&json, &headers, false, None) {
+                Ok(_) => {}
+                Err(Error::Rejected(DeliveryStatus::RejectedBadRequest)) => {
+                    warn!("Removing stored report that was rejected by Bugsnag.");
+                }
+                Err(e) => return Err(e),
+            }
+            for path in &report.paths {
+                std::fs::remove_file(path).ok();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_project_source_dir(&self) -> &String {
+        &self.project_source_dir
+    }
+
+    /// Sets the maximum number of causes of an error that are reported as exceptions,
+    /// 10 by default. Further causes are summarized in a last `CausesOmitted` exception
+    /// with their number and the root cause, so towers of wrapped errors do not create
+    /// huge events.
+    pub fn set_max_cause_depth(&mut self, depth: usize) {
+        self.max_cause_depth = depth;
+    }
+
+    /// Sets how deep metadata may be nested, 16 levels by default, and how many entries a
+    /// sequence or map of the metadata may have, 500 by default. Deeper values are
+    /// replaced by a marker without being converted, further entries by a marker with
+    /// their number, so huge or recursive structures do not create huge events.
+    pub fn set_metadata_limits(&mut self, max_depth: usize, max_width: usize) {
+        self.metadata_limits = bounded::Limits {
+            max_depth,
+            max_width,
+        };
+    }
+
+    /// Enables a grouping hash that is computed from the symbol names and relative file
+    /// paths of the frames in project, so errors of builds with different addresses or
+    /// inlining decisions are grouped together. An explicit grouping hash takes precedence.
+    pub fn set_stable_grouping(&mut self, enabled: bool) {
+        self.stable_grouping = enabled;
+    }
+
+    /// Sets the crates that belong to the project. If set, frames are marked as in project
+    /// by the crate of their method instead of by their file, which also works for builds
+    /// in changing directories. Dashes in package names are treated as underscores.
+    pub fn set_project_packages(&mut self, packages: &[&str]) {
+        self.project_packages = packages.iter().map(|p| p.replace('-', "_")).collect();
+    }
+
+    /// Sends notifications only in the given release stages, e.g. `["production",
+    /// "staging"]`. In other stages, and if no release stage is set with `set_app_info`
+    /// or the config, `NotifyBuilder::send` returns `Outcome::SuppressedByReleaseStage`
+    /// without creating the notification.
+    pub fn set_notify_release_stages(&mut self, stages: &[&str]) {
+        self.notify_release_stages = Some(stages.iter().map(|s| (*s).to_owned()).collect());
+    }
+
+    /// Drops notifications of the given error classes in `NotifyBuilder::send`, which
+    /// returns `Outcome::DiscardedByFilter` for them. A pattern enclosed in slashes, e.g.
+    /// `/^Hyper::/`, is a regular expression, any other pattern matches the error class
+    /// exactly. Replaces the classes set before. Fails if a regular expression is invalid.
+    pub fn set_discard_classes(&mut self, patterns: &[&str]) -> Result<(), regex::Error> {
+        self.discard_classes = discard::DiscardClasses::parse(patterns)?;
+        Ok(())
+    }
+
+    /// Sets the keys whose values are replaced by `[REDACTED]` in the metadata of events,
+    /// at any depth, after plugins and callbacks ran. A key is redacted if it contains one
+    /// of `keys`, ignoring case, e.g. `password` redacts `db_password`. Replaces the
+    /// default keys `password`, `authorization` and `secret`, an empty list disables the
+    /// redaction. The keys are part of the configuration, see `Config::redacted_keys`.
+    pub fn set_redacted_keys(&mut self, keys: &[&str]) {
+        let mut config = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        *config = std::mem::take(&mut *config).redacted_keys(keys);
+    }
+
+    /// Returns whether notifications are sent in the current release stage.
+    fn notifies_release_stage(&self, config: &config::Config) -> bool {
+        let Some(stages) = &self.notify_release_stages else {
+            return true;
+        };
+        let release_stage = config
+            .release_stage
+            .as_deref()
+            .or_else(|| self.app_info.as_ref().and_then(|a| a.release_stage()));
+        release_stage.is_some_and(|stage| stages.iter().any(|s| s == stage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        io_error_class, Bugsnag, NotifyBuilder, NotifyGuard, Outcome, RateLimit, SendLimit,
+        Severity, MAX_MESSAGE_LEN,
+    };
+    use crate::breadcrumbs::BreadcrumbType;
+    use crate::config::Config;
+    use crate::queue::DeliveryQueue;
+    use crate::user::User;
+    use serde_json::json;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_get_project_dir() {
+        let api = Bugsnag::new("api-key", "my-dir");
+        assert_eq!(api.get_project_source_dir(), "my-dir");
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        assert_eq!(api.get_notify_endpoint(), "https://notify.bugsnag.com");
+        assert_eq!(api.get_sessions_endpoint(), "https://sessions.bugsnag.com");
+
+        api.set_endpoints(
+            "https://bugsnag.example.com:49000",
+            "https://bugsnag.example.com:49001",
+        );
+        assert_eq!(
+            api.get_notify_endpoint(),
+            "https://bugsnag.example.com:49000"
+        );
+        assert_eq!(
+            api.get_sessions_endpoint(),
+            "https://bugsnag.example.com:49001"
+        );
+
+        api.set_endpoint("https://notify.example.com");
+        assert_eq!(api.get_notify_endpoint(), "https://notify.example.com");
+        assert_eq!(
+            api.get_sessions_endpoint(),
+            "https://bugsnag.example.com:49001"
+        );
+
+        let api = Bugsnag::with_endpoint("api-key", "my-dir", "https://notify.example.com");
+        assert_eq!(api.get_notify_endpoint(), "https://notify.example.com");
+        assert_eq!(api.get_sessions_endpoint(), "https://sessions.bugsnag.com");
+    }
+
+    #[test]
+    fn test_reload_config() {
+        let api = Bugsnag::new("api-key", "my-dir");
+        let clone = api.clone();
+        assert!(api.config().enabled);
+
+        api.reload_config(Config::new().enabled(false).release_stage(Some("staging")));
+        assert!(!clone.config().enabled);
+        assert_eq!(clone.config().release_stage.as_deref(), Some("staging"));
+    }
+
+    #[test]
+    fn test_child() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_context("main");
+
+        let mut child = api.child();
+        child.set_context("plugin");
+        child
+            .set_metadata(&json!({"plugin": {"name": "foo", "version": 1}}))
+            .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(
+            &child
+                .notify("Error", "message")
+                .metadata(&json!({"plugin": {"version": 2}}))
+                .unwrap()
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["events"][0]["context"], "plugin");
+        assert_eq!(
+            json["events"][0]["metaData"]["metaData"],
+            json!({"plugin": {"name": "foo", "version": 2}})
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        assert_eq!(json["events"][0]["context"], "main");
+        assert_eq!(json["events"][0]["metaData"].get("metaData"), None);
+    }
+
+    #[test]
+    fn test_set_dependencies() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        assert!(api.set_dependencies("not json").is_err());
+        api.set_dependencies(r#"{"serde": "1.0.198"}"#).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        assert_eq!(
+            json["events"][0]["metaData"]["dependencies"],
+            json!({"serde": "1.0.198"})
+        );
+    }
+
+    #[test]
+    fn test_set_build_info() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        assert!(api.set_build_info("[]").is_err());
+        api.set_build_info(r#"{"buildProfile": "release", "optLevel": "3"}"#)
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        let app = &json["events"][0]["metaData"]["app"];
+        assert_eq!(app["buildProfile"], "release");
+        assert_eq!(app["optLevel"], "3");
+    }
+
+    #[test]
+    fn test_from_error() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let err = "test".parse::<i32>().unwrap_err();
+
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .from_error(&err)
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let event = &json["events"][0];
+        assert_eq!(
+            event["exceptions"][0]["errorClass"],
+            std::any::type_name::<std::num::ParseIntError>()
+        );
+        assert_eq!(event["exceptions"][0]["message"], err.to_string());
+        assert_eq!(
+            event["metaData"]["error"]["debug"],
+            format!("{err:#?}").as_str()
+        );
+    }
+
+    #[test]
+    fn test_occurred_at_notify() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let builder = api.notify("Error", "message");
+        let occurred = builder.occurred;
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        assert_eq!(
+            json["events"][0]["device"]["time"],
+            serde_json::to_value(occurred).unwrap()
+        );
+        assert_eq!(
+            json["events"][0]["metaData"]["bugsnag-rs"]["occurred"],
+            occurred.to_rfc3339()
+        );
+        assert_eq!(
+            json["events"][0]["metaData"]["bugsnag-rs"]["monotonicMs"],
+            builder.occurred_monotonic.millis
+        );
+    }
+
+    #[test]
+    fn test_queue_overflow_spills_to_storage() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_spill_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_offline_storage(dir.to_str().unwrap());
+        // a worker that never delivers keeps the queue full
+        api.queue = Some(Arc::new(DeliveryQueue::new(1, |rx| {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            drop(rx);
+        })));
+
+        assert_eq!(
+            api.notify("Error", "first").send().unwrap(),
+            Outcome::Queued
+        );
+        let Outcome::StoredOffline(path) = api.notify("Error", "second").send().unwrap() else {
+            panic!("notification was not stored offline");
+        };
+        assert!(path.starts_with(&dir));
+
+        let stored = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(stored, 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_queued_delivery_keeps_deadline() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_queued_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints("http://127.0.0.1:1", "http://127.0.0.1:1");
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.use_background_delivery(4, Duration::from_secs(3600));
+
+        let outcome = api
+            .notify("Error", "message")
+            .deadline(std::time::Instant::now())
+            .send()
+            .unwrap();
+        assert_eq!(outcome, Outcome::Queued);
+        assert!(api.flush(Duration::from_secs(5)));
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_panic_bypasses_queue() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_panic_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints("http://127.0.0.1:1", "http://127.0.0.1:1");
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.queue = Some(Arc::new(DeliveryQueue::new(1, |rx| {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            drop(rx);
+        })));
+
+        assert_eq!(
+            api.notify("Error", "handled").send().unwrap(),
+            Outcome::Queued
+        );
+        let outcome = api
+            .notify("Panic", "crash")
+            .panic_payload(&"crash")
+            .send()
+            .unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_send_outcome() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let mut builder = api.notify("Error", "message");
+        assert_eq!(builder.send().unwrap(), Outcome::DiscardedByFilter);
+        assert_eq!(builder.send().unwrap(), Outcome::DiscardedByFilter);
+        drop(builder);
+
+        api.reload_config(Config::new().sample_rate(0.0));
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::Sampled
+        );
+    }
+
+    #[test]
+    fn test_send_with_deadline() {
+        // accepts connections, but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let dir = std::env::temp_dir().join(format!("bugsnag_deadline_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.use_offline_storage(dir.to_str().unwrap());
+
+        let start = std::time::Instant::now();
+        let outcome = api
+            .notify("Error", "message")
+            .send_with_deadline(Duration::from_millis(200))
+            .unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_passed_deadline() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_deadline_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints("http://127.0.0.1:1", "http://127.0.0.1:1");
+        api.use_offline_storage(dir.to_str().unwrap());
+
+        let outcome = api
+            .notify("Error", "message")
+            .deadline(std::time::Instant::now())
+            .send()
+            .unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_timeouts() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_timeouts_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        // accepts connections, but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&endpoint, &endpoint);
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.set_timeouts(Duration::from_secs(1), Duration::from_millis(200));
+
+        let start = std::time::Instant::now();
+        let outcome = api.notify("Error", "message").send().unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        drop(listener);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_bugsnag_assert() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        let retries = 3;
+        crate::bugsnag_assert!(api, retries < 5, "too many retries");
+        crate::bugsnag_assert!(api, retries > 5, "too few retries: {}", retries);
+        assert_eq!(captured.lock().unwrap().len(), 1);
+
+        let json: serde_json::Value = serde_json::from_str(&captured.lock().unwrap()[0]).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["severity"], "warning");
+        assert_eq!(
+            event["exceptions"][0]["message"],
+            "assertion failed: retries > 5: too few retries: 3"
+        );
+        assert_eq!(event["metaData"]["assertion"]["condition"], "retries > 5");
+        assert_eq!(event["exceptions"][0]["stacktrace"][0]["file"], file!());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "assertion failed: 1 > 2")]
+    fn test_bugsnag_debug_assert() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        crate::bugsnag_debug_assert!(api, 2 > 1);
+        crate::bugsnag_debug_assert!(api, 1 > 2);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_retry_policy() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let responses: [&[u8]; 2] = [
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 65536];
+                let _ = stream.read(&mut buf).unwrap();
+                stream.write_all(response).unwrap();
+            }
+        });
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.set_retry_policy(
+            crate::transport::RetryPolicy::new(3, Duration::from_millis(10)).jitter(0.5),
+        );
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::Delivered
+        );
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_transport_hooks() {
+        use crate::transport::{RetryPolicy, TransportOptions};
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let responses: [&[u8]; 2] = [
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 65536];
+                let _ = stream.read(&mut buf).unwrap();
+                stream.write_all(response).unwrap();
+            }
+        });
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let (req_sink, resp_sink) = (requests.clone(), responses.clone());
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.set_transport_options(
+            TransportOptions::new()
+                .retry(Some(RetryPolicy::new(3, Duration::from_millis(10))))
+                .on_request(move |info| req_sink.lock().unwrap().push(info.attempt))
+                .on_response(move |info| {
+                    assert!(info.latency > Duration::ZERO);
+                    resp_sink.lock().unwrap().push((info.attempt, info.status))
+                }),
+        );
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::Delivered
+        );
+        server.join().unwrap();
+
+        assert_eq!(*requests.lock().unwrap(), vec![1, 2]);
+        assert_eq!(
+            *responses.lock().unwrap(),
+            vec![(1, Some(503)), (2, Some(200))]
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_delivery_status() {
+        use super::DeliveryStatus;
+
+        assert_eq!(
+            DeliveryStatus::from_response(202, None),
+            DeliveryStatus::Delivered
+        );
+        assert_eq!(
+            DeliveryStatus::from_response(400, None),
+            DeliveryStatus::RejectedBadRequest
+        );
+        assert_eq!(
+            DeliveryStatus::from_response(429, Some("30")),
+            DeliveryStatus::RateLimited {
+                retry_after: Some(Duration::from_secs(30))
+            }
+        );
+        assert_eq!(
+            DeliveryStatus::from_response(429, Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            DeliveryStatus::RateLimited { retry_after: None }
+        );
+        assert_eq!(
+            DeliveryStatus::from_response(503, None),
+            DeliveryStatus::ServerError
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_rate_limited_retry_keeps_report() {
+        use super::{DeliveryStatus, Error};
+        use std::io::{Read, Write};
+
+        let dir = std::env::temp_dir().join(format!("bugsnag_429_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 65536];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+        });
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.use_offline_storage(dir.to_str().unwrap());
+        let outcome = api
+            .notify("Error", "message")
+            .deadline(std::time::Instant::now())
+            .send()
+            .unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+
+        assert_eq!(
+            api.retry_from_storage(),
+            Err(Error::Rejected(DeliveryStatus::RateLimited {
+                retry_after: Some(Duration::from_secs(30))
+            }))
+        );
+        server.join().unwrap();
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_retry_from_storage_uses_stored_headers() {
+        use crate::envelope;
+        use std::io::{Read, Write};
+
+        let dir = std::env::temp_dir().join(format!("bugsnag_headers_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join(format!("{}_other", envelope::REPORT_PREFIX)),
+            envelope::wrap(&[("Bugsnag-Api-Key", "other-key")], r#"{"events":[]}"#),
+        )
+        .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 65536];
+            let len = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_lowercase()
+        });
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.use_offline_storage(dir.to_str().unwrap());
+        assert_eq!(api.retry_from_storage(), Ok(()));
+
+        let request = server.join().unwrap();
+        assert!(request.contains("bugsnag-api-key: other-key"));
+        assert!(request.contains("bugsnag-payload-version: 5"));
+        assert!(envelope::stored_reports(&dir).unwrap().is_empty());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_offline_retry_budget() {
+        use std::io::{Read, Write};
+
+        let dir = std::env::temp_dir().join(format!("bugsnag_budget_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.set_offline_retry_budget(2, Duration::from_secs(60));
+        for message in ["first", "second", "third"] {
+            let outcome = api
+                .notify("Error", message)
+                .deadline(std::time::Instant::now())
+                .send()
+                .unwrap();
+            assert!(matches!(outcome, Outcome::StoredOffline(_)));
+        }
+
+        let server = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 65536];
+                let _ = stream.read(&mut buf).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+            }
+        });
+
+        assert_eq!(api.retry_from_storage(), Ok(()));
+        server.join().unwrap();
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        // the budget is used up, so the last report is not send
+        assert_eq!(api.child().retry_from_storage(), Ok(()));
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_discard_classes() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        api.set_discard_classes(&["Io::BrokenPipe", "/^Hyper::/"])
+            .unwrap();
+
+        assert_eq!(
+            api.notify("Io::BrokenPipe", "client went away").send(),
+            Ok(Outcome::DiscardedByFilter)
+        );
+        assert_eq!(
+            api.notify("Hyper::IncompleteMessage", "connection closed")
+                .send(),
+            Ok(Outcome::DiscardedByFilter)
+        );
+        assert_eq!(
+            api.notify("Io::NotFound", "missing").send(),
+            Ok(Outcome::WrittenToConsole)
+        );
+        assert!(api.set_discard_classes(&["/(/"]).is_err());
+    }
+
+    #[test]
+    fn test_add_on_error() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        api.add_on_error(|event| {
+            event.add_metadata("tenant", "id", json!("acme"));
+            event.set_severity(Severity::Warning);
+            true
+        });
+        api.add_on_error(|event| event.error_class() != "Io::BrokenPipe");
+        api.add_on_error(|event| {
+            // runs after the previous callbacks
+            assert_eq!(event.severity(), Some(&Severity::Warning));
+            event.set_user(User::new_id("user-1"));
+            true
+        });
+
+        assert_eq!(
+            api.notify("Io::BrokenPipe", "client went away").send(),
+            Ok(Outcome::DiscardedByFilter)
+        );
+        assert_eq!(
+            api.notify("Error", "message")
+                .on_before_send(|event| {
+                    assert!(event.user().is_some());
+                    true
+                })
+                .send(),
+            Ok(Outcome::Captured)
+        );
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(&captured[0]).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["severity"], "warning");
+        assert_eq!(event["metaData"]["tenant"]["id"], "acme");
+        assert_eq!(event["user"]["id"], "user-1");
+    }
+
+    #[test]
+    fn test_on_before_send() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        let called = Arc::new(Mutex::new(0));
+        let count = called.clone();
+        assert_eq!(
+            api.notify("Error", "kept")
+                .on_before_send(move |event| {
+                    *count.lock().unwrap() += 1;
+                    event.add_metadata("diagnostics", "pool", json!("exhausted"));
+                    event.set_context("checkout");
+                    true
+                })
+                .send(),
+            Ok(Outcome::Captured)
+        );
+        assert_eq!(
+            api.notify("Error", "dropped")
+                .on_before_send(|event| event.message() != "dropped")
+                .send(),
+            Ok(Outcome::DiscardedByFilter)
+        );
+
+        api.reload_config(Config::new().enabled(false));
+        let count = called.clone();
+        assert_eq!(
+            api.notify("Error", "disabled")
+                .on_before_send(move |_| {
+                    *count.lock().unwrap() += 1;
+                    true
+                })
+                .send(),
+            Ok(Outcome::DiscardedByFilter)
+        );
+        assert_eq!(*called.lock().unwrap(), 1);
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(&captured[0]).unwrap();
+        assert_eq!(json["events"][0]["context"], "checkout");
+        assert_eq!(
+            json["events"][0]["metaData"]["diagnostics"]["pool"],
+            "exhausted"
+        );
+    }
+
+    #[test]
+    fn test_notify_release_stages() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+        api.set_notify_release_stages(&["production", "staging"]);
+
+        assert_eq!(
+            api.notify("Error", "no stage").send(),
+            Ok(Outcome::SuppressedByReleaseStage)
+        );
+        api.set_app_info(Some("1.0.0"), Some("development"), None);
+        assert_eq!(
+            api.notify("Error", "development").send(),
+            Ok(Outcome::SuppressedByReleaseStage)
+        );
+        api.set_app_info(Some("1.0.0"), Some("staging"), None);
+        assert_eq!(api.notify("Error", "staging").send(), Ok(Outcome::Captured));
+        api.reload_config(Config::new().release_stage(Some("test")));
+        assert_eq!(
+            api.notify("Error", "config override").send(),
+            Ok(Outcome::SuppressedByReleaseStage)
+        );
+        assert_eq!(captured.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_diag_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join(crate::envelope::REPORT_PREFIX), "{}").unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut api = Bugsnag::new("0123456789abcdef", "my-dir");
+        api.set_endpoint(&endpoint);
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.rate_limit(RateLimit::new(
+            vec![SendLimit::new(Duration::from_secs(60), 5)],
+            dir.join("rate_limit.json"),
+            None,
+        ));
+
+        let diagnostics = api.diagnostics();
+        assert_eq!(diagnostics.api_key, "************cdef");
+        assert_eq!(diagnostics.endpoint_error, None);
+        assert_eq!(diagnostics.offline_backlog, Some(1));
+        assert_eq!(diagnostics.queue_depth, None);
+        assert_eq!(diagnostics.rate_limits.len(), 1);
+        assert_eq!(diagnostics.rate_limits[0].sent, 0);
+        assert_eq!(
+            diagnostics.features.contains(&"http"),
+            cfg!(feature = "http")
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&diagnostics.to_string()).unwrap();
+        assert_eq!(json["config"]["enabled"], true);
+        assert_eq!(json["notifyEndpoint"], endpoint);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_message_with() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let called = std::sync::atomic::AtomicBool::new(false);
+        api.notify("Error", "")
+            .message_with(|| {
+                called.store(true, std::sync::atomic::Ordering::Relaxed);
+                "expensive".to_owned()
+            })
+            .send()
+            .unwrap();
+        assert!(!called.load(std::sync::atomic::Ordering::Relaxed));
+
+        let mut builder = api
+            .notify("Error", "")
+            .message_with(|| "ä".repeat(MAX_MESSAGE_LEN));
+        builder.resolve_message();
+        assert_eq!(builder.message.len(), MAX_MESSAGE_LEN);
+    }
+
+    #[test]
+    fn test_rate_limit_triggered_once_across_instances() {
+        let file =
+            std::env::temp_dir().join(format!("bugsnag_rate_limit_{}", uuid::Uuid::new_v4()));
+        let rate_limit = RateLimit::new(
+            vec![SendLimit::new(std::time::Duration::from_secs(60), 2)],
+            file.clone(),
+            None,
+        );
+
+        // every thread acts like a process sharing the persistence file
+        let threads = (0..8)
+            .map(|_| {
+                let mut rate_limit = rate_limit.clone();
+                std::thread::spawn(move || {
+                    rate_limit.register_notification();
+                    rate_limit.triggered()
+                })
+            })
+            .collect::<Vec<_>>();
+        let triggered = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|triggered| *triggered)
+            .count();
+
+        assert_eq!(triggered, 1);
+        std::fs::remove_file(file).ok();
+    }
+
+    #[test]
+    fn test_rate_limit_in_memory() {
+        let rate_limit =
+            RateLimit::in_memory(vec![SendLimit::new(Duration::from_secs(60), 1)], None);
+
+        // the clones share the sent notifications
+        let triggered = (0..3)
+            .map(|_| {
+                let mut rate_limit = rate_limit.clone();
+                rate_limit.register_notification();
+                rate_limit.triggered()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(triggered, [false, true, false]);
+
+        let mut reloaded = rate_limit.synchronized();
+        assert_eq!(reloaded.usage()[0].sent, 3);
+        assert_eq!(reloaded.persistence_file, PathBuf::new());
+    }
+
+    #[test]
+    fn test_start_defaults() {
+        let api = Bugsnag::with_defaults("api-key", |name| match name {
+            "BUGSNAG_APP_VERSION" => Some("1.2.3".to_owned()),
+            _ => None,
+        });
+        let app_info = api.app_info.as_ref().unwrap();
+        assert_eq!(app_info.version(), Some("1.2.3"));
+        assert_eq!(app_info.release_stage(), Some("production"));
+        assert!(api.queue.is_some());
+        assert!(api.config().rate_limit.unwrap().memory.is_some());
+        let storage = super::default_storage_dir("api-key");
+        assert_eq!(api.offline_storage, Some(storage.display().to_string()));
+        assert_ne!(storage, super::default_storage_dir("other-key"));
+
+        let api = Bugsnag::with_defaults("api-key", |name| match name {
+            "BUGSNAG_RELEASE_STAGE" => Some("staging".to_owned()),
+            _ => Some(String::new()),
+        });
+        let app_info = api.app_info.as_ref().unwrap();
+        assert_eq!(app_info.version(), None);
+        assert_eq!(app_info.release_stage(), Some("staging"));
+    }
+
+    #[test]
+    fn test_panic_rate_limit() {
+        let file = std::env::temp_dir().join(format!("bugsnag_panic_{}", uuid::Uuid::new_v4()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        api.reload_config(Config::new().panic_rate_limit(Some(RateLimit::new(
+            vec![SendLimit::new(std::time::Duration::from_secs(60), 1)],
+            file.clone(),
+            None,
+        ))));
+
+        let panic = |api: &mut Bugsnag| {
+            api.notify("Panic", "crash")
+                .panic_payload(&"crash")
+                .send()
+                .unwrap()
+        };
+        assert_eq!(panic(&mut api), Outcome::WrittenToConsole);
+        assert_eq!(panic(&mut api), Outcome::SuppressedByRateLimit);
+        assert_eq!(
+            api.notify("Error", "handled").send().unwrap(),
+            Outcome::WrittenToConsole
+        );
+
+        std::fs::remove_file(file).ok();
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let child = api.child();
+        child.leave_breadcrumb("started", BreadcrumbType::Process, None);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        let breadcrumbs = json["events"][0]["breadcrumbs"].as_array().unwrap();
+        assert_eq!(breadcrumbs.len(), 1);
+        assert_eq!(breadcrumbs[0]["name"], "started");
+        assert_eq!(breadcrumbs[0]["type"], "process");
+    }
+
+    #[test]
+    fn test_reentrant_notifications_are_stored_offline() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_reentrant_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_offline_storage(dir.to_str().unwrap());
+
+        let guard = NotifyGuard::enter();
+        let outcome = api.notify("Error", "nested").send().unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+        drop(guard);
+
+        // the builder is dropped while unwinding
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _builder = api.notify("Error", "unwinding");
+            panic!("unwinding");
+        }));
+        assert!(res.is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_project_packages() {
+        let mut api = Bugsnag::new("api-key", "/build/my-app");
+        assert!(api.is_in_project("/build/my-app/src/main.rs", "my_app::main"));
+        assert!(!api.is_in_project("/tmp/ci-1234/src/main.rs", "my_app::main"));
+
+        api.set_project_packages(&["my_app", "my-app-core"]);
+        assert!(api.is_in_project("/tmp/ci-1234/src/main.rs", "my_app::main"));
+        assert!(api.is_in_project("", "<my_app_core::Db as core::ops::Drop>::drop"));
+        assert!(!api.is_in_project("/build/my-app/src/main.rs", "tokio::runtime::run"));
+    }
+
+    #[test]
+    fn test_metadata_limits() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_metadata_limits(2, 2);
+
+        let builder = api
+            .notify("Error", "message")
+            .metadata(&json!({"request": {"headers": {"accept": "*/*"}}, "ids": [1, 2, 3]}))
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        assert_eq!(
+            json["events"][0]["metaData"]["metaData"],
+            json!({
+                "ids": [1, 2, "[1 more omitted]"],
+                "request": {"headers": "[map of 1 nested too deep]"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_max_cause_depth() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_max_cause_depth(2);
+
+        let mut builder = api.notify("Error", "message");
+        builder.causes = (1..=5)
+            .map(|i| (format!("Layer{i}"), format!("layer {i} failed")))
+            .collect();
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+
+        let exceptions = json["events"][0]["exceptions"].as_array().unwrap();
+        let classes: Vec<_> = exceptions.iter().map(|e| &e["errorClass"]).collect();
+        assert_eq!(classes, ["Error", "Layer1", "Layer2", "CausesOmitted"]);
+        assert_eq!(
+            exceptions[3]["message"],
+            "3 further causes omitted, root cause Layer5: layer 5 failed"
+        );
+    }
+
+    #[test]
+    fn test_stable_grouping() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        assert!(json["events"][0].get("groupingHash").is_none());
+
+        api.set_stable_grouping(true);
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        assert_eq!(
+            json["events"][0]["groupingHash"].as_str().unwrap().len(),
+            16
+        );
+
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .grouping_hash("explicit")
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["events"][0]["groupingHash"], "explicit");
+    }
+
+    #[test]
+    fn test_user_provider() {
+        thread_local! {
+            static CURRENT_USER: std::cell::RefCell<Option<User>> = const { std::cell::RefCell::new(None) };
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_user(User::new_id("fallback"));
+        api.set_user_provider(|| CURRENT_USER.with(|u| u.borrow().clone()));
+
+        let user_id = |api: &mut Bugsnag| {
+            let json: serde_json::Value =
+                serde_json::from_str(&api.notify("Error", "message").create_json().unwrap())
+                    .unwrap();
+            json["events"][0]["user"]["id"].clone()
+        };
+
+        assert_eq!(user_id(&mut api), "fallback");
+        CURRENT_USER.with(|u| *u.borrow_mut() = Some(User::new_id("request-user")));
+        assert_eq!(user_id(&mut api), "request-user");
+    }
+
+    #[test]
+    fn test_add_metadata() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.add_metadata("k8s", "pod", json!("web-1"));
+        api.add_metadata("k8s", "region", json!("eu-west-1"));
+        api.add_metadata("io", "host", json!("db-1"));
+        api.add_metadata("io", "kind", json!("overridden"));
+        api.add_metadata("metaData", "build", json!("abc123"));
+        api.add_metadata("removed", "key", json!(1));
+        api.clear_metadata("removed");
+
+        let err = std::io::Error::from(std::io::ErrorKind::TimedOut);
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .from_io_error(&err)
+                .metadata(&json!({"build": "def456", "request": "r-1"}))
+                .unwrap()
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let metadata = &json["events"][0]["metaData"];
+        assert_eq!(
+            metadata["k8s"],
+            json!({"pod": "web-1", "region": "eu-west-1"})
+        );
+        assert_eq!(metadata["io"], json!({"host": "db-1", "kind": "TimedOut"}));
+        assert_eq!(
+            metadata["metaData"],
+            json!({"build": "def456", "request": "r-1"})
+        );
+        assert!(metadata.get("removed").is_none());
+    }
+
+    #[test]
+    fn test_redacted_keys() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.add_on_error(|event| {
+            event.add_metadata("request", "Authorization", json!("Bearer abc"));
+            true
+        });
+        api.leave_breadcrumb(
+            "login",
+            BreadcrumbType::User,
+            Some(json!({"user": "alice", "password": "hunter2"})),
+        );
+
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .metadata(&json!({"db": {"password": "hunter2", "host": "db-1"}}))
+                .unwrap()
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let metadata = &json["events"][0]["metaData"];
+        assert_eq!(metadata["metaData"]["db"]["password"], "[REDACTED]");
+        assert_eq!(metadata["metaData"]["db"]["host"], "db-1");
+        assert_eq!(metadata["request"]["Authorization"], "[REDACTED]");
+        let breadcrumb = &json["events"][0]["breadcrumbs"][0]["metaData"];
+        assert_eq!(breadcrumb["password"], "[REDACTED]");
+        assert_eq!(breadcrumb["user"], "alice");
+
+        api.set_redacted_keys(&["Host"]);
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .metadata(&json!({"db": {"password": "hunter2", "host": "db-1"}}))
+                .unwrap()
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let metadata = &json["events"][0]["metaData"];
+        assert_eq!(metadata["metaData"]["db"]["password"], "hunter2");
+        assert_eq!(metadata["metaData"]["db"]["host"], "[REDACTED]");
+
+        api.reload_config(Config::new().enabled(false).redacted_keys(&[]));
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .metadata(&json!({"db": {"password": "hunter2", "host": "db-1"}}))
+                .unwrap()
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let metadata = &json["events"][0]["metaData"];
+        assert_eq!(metadata["metaData"]["db"]["host"], "db-1");
+    }
+
+    #[test]
+    fn test_supplied_stacktrace() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_symbolication_data(true);
+        api.add_path_prefix_rewrite("/build/", "src/");
+
+        let mut frame = crate::Frame::new("/build/motor.c", 120, "motor_step", true);
+        frame.set_column_number(7);
+        let frames = vec![frame, crate::Frame::new("irq.c", 40, "irq_dispatch", false)];
+        let json = api
+            .notify("HardFault", "bus error")
+            .stacktrace(frames)
+            .create_json()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let event = &json["events"][0];
+        assert_eq!(
+            event["exceptions"][0]["stacktrace"],
+            json!([
+                {"file": "src/motor.c", "lineNumber": 120, "columnNumber": 7,
+                 "method": "motor_step", "inProject": true},
+                {"file": "irq.c", "lineNumber": 40, "method": "irq_dispatch", "inProject": false},
+            ])
+        );
+        assert!(event["metaData"].get("symbolication").is_none());
+    }
+
+    #[test]
+    fn test_source_snippets() {
+        let mut api = Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+        api.reload_config(Config::new().enabled(false));
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        assert!(!json.contains("\"code\""));
+
+        api.set_source_snippets(true);
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let frames = json["events"][0]["exceptions"][0]["stacktrace"]
+            .as_array()
+            .unwrap();
+        let frame = frames
+            .iter()
+            .find(|f| {
+                f["method"]
+                    .as_str()
+                    .unwrap()
+                    .contains("test_source_snippets")
+            })
+            .unwrap();
+        let line = frame["lineNumber"].to_string();
+        assert!(frame["code"][line]
+            .as_str()
+            .unwrap()
+            .contains("api.notify(\"Error\", \"message\")"));
+    }
+
+    #[test]
+    fn test_runtime_versions() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.add_runtime_version("lua", "5.4.6");
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let device = &json["events"][0]["device"];
+        assert_eq!(device["runtimeVersions"]["lua"], "5.4.6");
+        assert!(device["runtimeVersions"]["rustc"].is_string());
+        assert!(device["freeMemory"].is_u64());
+    }
+
+    #[test]
+    fn test_device_user() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_user(User::new_id("customer-1"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        assert!(json["events"][0]["metaData"].get("deviceUser").is_none());
+
+        api.set_device_user(User::new_id("machine"));
+        api.set_device_user_provider(|| Some(User::new_name("operator")));
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["user"]["id"], "customer-1");
+        assert_eq!(event["metaData"]["appUser"]["id"], "customer-1");
+        assert_eq!(event["metaData"]["deviceUser"]["name"], "operator");
+    }
+
+    #[test]
+    fn test_app_version_provider() {
+        let bundle_version = Arc::new(Mutex::new(None));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_app_info(Some("1.0.0"), Some("production"), None);
+        let version = bundle_version.clone();
+        api.set_app_version_provider(move || version.lock().unwrap().clone());
+
+        let app = |api: &mut Bugsnag| {
+            let json: serde_json::Value =
+                serde_json::from_str(&api.notify("Error", "message").create_json().unwrap())
+                    .unwrap();
+            let mut app = json["events"][0]["app"].clone();
+            app.as_object_mut().unwrap().remove("duration");
+            app
+        };
+
+        assert_eq!(app(&mut api)["version"], "1.0.0");
+        *bundle_version.lock().unwrap() = Some("1.0.0+bundle.7".to_owned());
+        assert_eq!(
+            app(&mut api),
+            json!({"version": "1.0.0+bundle.7", "releaseStage": "production"})
+        );
+    }
+
+    #[test]
+    fn test_context_provider() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_context_provider(|| Some("worker:email-digest".to_owned()));
+
+        let context = |builder: NotifyBuilder| {
+            let json: serde_json::Value =
+                serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+            json["events"][0]["context"].clone()
+        };
+
+        assert_eq!(
+            context(api.notify("Error", "message")),
+            "worker:email-digest"
+        );
+        assert_eq!(
+            context(api.notify("Error", "message").context("explicit")),
+            "explicit"
+        );
+        api.set_context("main");
+        assert_eq!(context(api.notify("Error", "message")), "main");
+    }
+
+    #[test]
+    fn test_class_budget() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        api.reload_config(Config::new().class_budget(Some(1)));
+        api.set_app_info(Some("1.0"), None, None);
+
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::WrittenToConsole
+        );
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::SuppressedByClassBudget
+        );
+
+        api.set_app_info(Some("1.1"), None, None);
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::WrittenToConsole
+        );
+    }
+
+    #[test]
+    fn test_report_startup() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        api.set_app_info(Some("1.2.0"), Some("production"), None);
+        assert_eq!(api.report_startup().unwrap(), Outcome::WrittenToConsole);
+
+        api.reload_config(Config::new().enabled(false));
+        assert_eq!(api.report_startup().unwrap(), Outcome::DiscardedByFilter);
+    }
+
+    #[test]
+    fn test_report_startup_config_hash() {
+        let hashes = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = hashes.clone();
+        api.set_capture_callback(move |json| {
+            let json: serde_json::Value = serde_json::from_str(json).unwrap();
+            let hash = &json["events"][0]["metaData"]["metaData"]["startup"]["configHash"];
+            if let Some(hash) = hash.as_str() {
+                sink.lock().unwrap().push(hash.to_owned());
+            }
+        });
+        let file = std::env::temp_dir().join(format!("bugsnag_hash_{}", uuid::Uuid::new_v4()));
+        let config = || {
+            Config::new().rate_limit(Some(RateLimit::new(
+                vec![SendLimit::new(Duration::from_secs(60), 100)],
+                file.clone(),
+                None,
+            )))
+        };
+        api.reload_config(config());
+
+        api.report_startup().unwrap();
+        api.notify("Error", "traffic").send().unwrap();
+        // reads the sent notifications from the persistence file
+        api.reload_config(config());
+        api.report_startup().unwrap();
+
+        let hashes = hashes.lock().unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes[0], hashes[1]);
+        std::fs::remove_file(file).ok();
+    }
+
+    #[test]
+    fn test_stack_captured_at_notify() {
+        #[inline(never)]
+        fn notify_here(api: &mut Bugsnag) -> NotifyBuilder<'static, '_> {
+            api.notify("Error", "message")
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let json = notify_here(&mut api).create_json().unwrap();
+
+        assert!(json.contains("test_stack_captured_at_notify::notify_here"));
+    }
+
+    #[test]
+    fn test_error_panic_payload_chain() {
+        #[derive(Debug)]
+        struct ConfigError(std::num::ParseIntError);
+
+        impl std::fmt::Display for ConfigError {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "invalid config")
+            }
+        }
+
+        impl std::error::Error for ConfigError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let err = ConfigError("x".parse::<u32>().unwrap_err());
+        let payload: Box<dyn std::any::Any + Send> =
+            Box::new(Box::new(err) as Box<dyn std::error::Error + Send + Sync>);
+        let message = crate::panic::payload_to_message(payload.as_ref());
+
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Panic", &message)
+                .panic_payload(payload.as_ref())
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let exceptions = &json["events"][0]["exceptions"];
+        assert_eq!(exceptions[0]["errorClass"], "Panic");
+        assert_eq!(exceptions[0]["message"], "invalid config");
+        assert_eq!(exceptions[1]["errorClass"], "ParseIntError");
+        assert_eq!(exceptions[1]["message"], "invalid digit found in string");
+        assert_eq!(exceptions.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_owned_strings() {
+        fn build(api: &mut Bugsnag, code: u32) -> NotifyBuilder<'static, '_> {
+            api.notify(format!("Http{code}"), format!("request failed with {code}"))
+                .context(format!("GET /items/{code}"))
+                .grouping_hash(code.to_string())
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&build(&mut api, 503).create_json().unwrap()).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["exceptions"][0]["errorClass"], "Http503");
+        assert_eq!(event["exceptions"][0]["message"], "request failed with 503");
+        assert_eq!(event["context"], "GET /items/503");
+        assert_eq!(event["groupingHash"], "503");
+    }
+
+    #[test]
+    fn test_send_builder_to_thread() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+
+        let request_id = String::from("req-1");
+        let builder = api
+            .notify("Error", "message")
+            .context(request_id.as_str())
+            .message_with(|| format!("failed request {request_id}"));
+        assert_send(&builder);
+        let mut builder = builder.into_owned();
+        drop(request_id);
+
+        let outcome = std::thread::spawn(move || builder.send())
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome, Outcome::WrittenToConsole);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_delivery_receipts() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 65536];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        });
+        let path = std::env::temp_dir().join(format!("bugsnag_receipts_{}", uuid::Uuid::new_v4()));
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.use_delivery_receipts(path.to_str().unwrap());
+        let mut builder = api.notify("Error", "message");
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        assert_eq!(builder.send().unwrap(), Outcome::Delivered);
+        server.join().unwrap();
+
+        let receipt: serde_json::Value =
+            serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(
+            receipt["eventId"],
+            json["events"][0]["metaData"]["bugsnag-rs"]["eventId"]
+        );
+        assert_eq!(receipt["status"], 200);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_start_session() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 65536];
+            let len = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        });
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints("http://127.0.0.1:1", &url);
+        assert_eq!(api.start_session(), Ok(()));
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST / "));
+        assert!(request.contains("bugsnag-payload-version: 1.0"));
+        assert!(request.contains("\"sessionsStarted\":1"));
+    }
+
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    #[test]
+    fn test_send_async() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 65536];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let task = runtime.spawn(async move { api.notify("Error", "message").send_async().await });
+
+        assert_eq!(runtime.block_on(task).unwrap(), Ok(Outcome::Delivered));
+        server.join().unwrap();
+    }
+
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    #[test]
+    fn test_send_async_retry_policy() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let responses: [&[u8]; 2] = [
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 65536];
+                let _ = stream.read(&mut buf).unwrap();
+                stream.write_all(response).unwrap();
+            }
+        });
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.set_endpoints(&url, &url);
+        api.set_retry_policy(crate::transport::RetryPolicy::new(
+            3,
+            Duration::from_millis(10),
+        ));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let task = runtime.spawn(async move { api.notify("Error", "message").send_async().await });
+
+        assert_eq!(runtime.block_on(task).unwrap(), Ok(Outcome::Delivered));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_path_prefix_rewrite() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.add_path_prefix_rewrite("/build/workspace/", "src/");
+        api.add_path_prefix_rewrite("/build/", "other/");
+
+        let mut frames = vec![
+            crate::Frame::new("/build/workspace/main.rs", 1, "main", true),
+            crate::Frame::new("/build/gen.rs", 1, "gen", true),
+            crate::Frame::new("/rustc/lib.rs", 1, "std", false),
+        ];
+        api.rewrite_path_prefixes(&mut frames);
+
+        let files: Vec<_> = frames.iter().map(|f| f.file()).collect();
+        assert_eq!(files, ["src/main.rs", "other/gen.rs", "/rustc/lib.rs"]);
+    }
+
+    #[test]
+    fn test_unhandled_override() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(json["events"][0].get("unhandled").is_none());
+
+        let builder = api
+            .notify("Storage::Corrupted", "checksum mismatch")
+            .unhandled_override(true, "tolerated data corruption");
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        assert_eq!(json["events"][0]["unhandled"], true);
+        assert_eq!(
+            json["events"][0]["severityReason"],
+            json!({
+                "type": "handledException",
+                "unhandledOverridden": true,
+                "attributes": {"reason": "tolerated data corruption"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_app_duration() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        std::thread::sleep(Duration::from_millis(20));
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(json["events"][0]["app"]["duration"].as_u64().unwrap() >= 20);
+    }
+
+    #[test]
+    fn test_capture_all_threads() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(json["events"][0].get("threads").is_none());
+
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let worker = std::thread::spawn(move || rx.recv());
+        let builder = api
+            .notify("Deadlock", "lock timeout")
+            .capture_all_thread_stacks();
+        tx.send(()).unwrap();
+        worker.join().unwrap().unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        let event = &json["events"][0];
+        let threads = event["threads"].as_array().unwrap();
+        let reporting = threads
+            .iter()
+            .filter(|t| t["errorReportingThread"] == true)
+            .collect::<Vec<_>>();
+        assert_eq!(reporting.len(), 1);
+        assert_eq!(
+            reporting[0]["stacktrace"],
+            event["exceptions"][0]["stacktrace"]
+        );
+        if cfg!(target_os = "linux") {
+            assert!(threads.len() >= 2);
+        }
+    }
+
+    #[test]
+    fn test_pump() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+        assert_eq!(api.pump(), 0);
+
+        let pending = api.use_pending_errors(2);
+        assert!(pending.record("Frame::Overrun", "frame took 40ms"));
+        assert!(pending.record("Frame::Overrun", "frame took 45ms"));
+        assert!(!pending.record("Frame::Overrun", "frame took 50ms"));
+        assert_eq!(api.pump(), 2);
+
+        let json: serde_json::Value = serde_json::from_str(&captured.lock().unwrap()[0]).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["exceptions"][0]["errorClass"], "Frame::Overrun");
+        assert_eq!(event["metaData"]["pending"]["dropped"], 1);
+        // not the stack of the thread calling pump
+        assert_eq!(event["exceptions"][0]["stacktrace"], json!([]));
+    }
+
+    #[test]
+    fn test_notify_error() {
+        #[derive(Debug)]
+        struct ConfigError(std::num::ParseIntError);
+
+        impl std::fmt::Display for ConfigError {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "invalid port")
+            }
+        }
+
+        impl std::error::Error for ConfigError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let err = ConfigError("80a".parse::<u16>().unwrap_err());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify_error(&err).create_json().unwrap()).unwrap();
+        let exceptions = &json["events"][0]["exceptions"];
+        assert_eq!(exceptions[0]["errorClass"], "ConfigError");
+        assert_eq!(exceptions[0]["message"], "invalid port");
+        assert_eq!(exceptions[1]["errorClass"], "ParseIntError");
+        assert_eq!(exceptions[1]["message"], "invalid digit found in string");
+        assert!(exceptions.get(2).is_none());
+    }
+
+    #[test]
+    fn test_internal_tab() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_internal_tab(Some("notifier"));
+
+        let metadata = |api: &mut Bugsnag| {
+            let json: serde_json::Value =
+                serde_json::from_str(&api.notify("Error", "message").create_json().unwrap())
+                    .unwrap();
+            json["events"][0]["metaData"].clone()
+        };
+
+        let tabs = metadata(&mut api);
+        assert!(tabs["notifier"]["occurred"].is_string());
+        assert!(tabs.get("bugsnag-rs").is_none());
+
+        api.set_internal_tab(None);
+        assert!(metadata(&mut api).get("notifier").is_none());
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_from_anyhow() {
+        use anyhow::Context;
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let err = "80a"
+            .parse::<u16>()
+            .context("invalid port")
+            .context("failed to load config")
+            .unwrap_err();
+
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .from_anyhow(&err)
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let exceptions = &json["events"][0]["exceptions"];
+        assert_eq!(exceptions[0]["message"], "failed to load config");
+        assert_eq!(exceptions[0]["errorClass"], "ParseIntError");
+        assert_eq!(exceptions[1]["message"], "invalid port");
+        assert_eq!(exceptions[1]["errorClass"], "ParseIntError");
+        assert_eq!(exceptions[2]["errorClass"], "ParseIntError");
+        assert!(exceptions.get(3).is_none());
+
+        let err = anyhow::anyhow!("port out of range").context("failed to load config");
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Config::Invalid", "message")
+                .from_anyhow(&err)
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let exceptions = &json["events"][0]["exceptions"];
+        assert_eq!(exceptions[0]["errorClass"], "Config::Invalid");
+        assert_eq!(exceptions[1]["errorClass"], "Config::Invalid");
+        assert_eq!(exceptions[1]["message"], "port out of range");
+    }
+
+    #[test]
+    fn test_std_backtrace() {
+        fn create_error() -> std::backtrace::Backtrace {
+            std::backtrace::Backtrace::force_capture()
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        let backtrace = create_error();
+
+        let builder = api.notify("Error", "message").std_backtrace(&backtrace);
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        let frames = json["events"][0]["exceptions"][0]["stacktrace"]
+            .as_array()
+            .unwrap();
+        assert!(frames
+            .iter()
+            .any(|f| f["method"].as_str().unwrap().ends_with("create_error")));
+        drop(builder);
+
+        let disabled = std::backtrace::Backtrace::disabled();
+        let builder = api.notify("Error", "message").std_backtrace(&disabled);
+        assert!(builder.std_backtrace.is_none());
+    }
+
+    #[test]
+    fn test_flush() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_flush_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        assert!(api.flush(Duration::ZERO));
+        api.set_endpoints("http://127.0.0.1:1", "http://127.0.0.1:1");
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.use_background_delivery(8, Duration::from_secs(60));
+
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::Queued
+        );
+        assert!(api.flush(Duration::from_secs(10)));
+        assert_eq!(crate::envelope::stored_reports(&dir).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_symbolication() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_symbolication_data(true);
+        api.set_frame_rewriter(|frames| {
+            frames.retain(|f| f.method().contains("test_symbolication"));
+            for frame in frames.iter_mut() {
+                frame.set_in_project(true);
+            }
+        });
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        let event = &json["events"][0];
+        let frames = event["exceptions"][0]["stacktrace"].as_array().unwrap();
+        assert!(!frames.is_empty());
+        assert!(frames.iter().all(|f| f["inProject"] == true));
+        assert!(event["metaData"]["symbolication"]["frames"][0]["frameAddress"].is_string());
+    }
+
+    #[test]
+    fn test_register_plugin() {
+        struct Tenant(&'static str);
+
+        impl crate::plugin::Plugin for Tenant {
+            fn enrich(&self, event: &mut crate::plugin::Event) {
+                event.add_metadata("tenant", "id", json!(self.0));
+                event.set_context(&format!("{}/{}", self.0, event.error_class()));
+            }
+        }
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false).release_stage(Some("staging")));
+        api.register_plugin(Tenant("acme"));
+        api.register_plugin(Tenant("globex"));
+        assert!(format!("{api:?}").contains("Tenant"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["metaData"]["tenant"]["id"], "globex");
+        assert_eq!(event["context"], "globex/Error");
+        assert_eq!(event["app"]["releaseStage"], "staging");
+        assert!(event["metaData"]["rust"].is_object());
+        assert!(event["metaData"]["app"]["buildTarget"].is_string());
+        assert!(event["device"]["time"].is_string());
+
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .tab("rust", json!({"toolchain": "custom"}))
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["events"][0]["metaData"]["rust"]["toolchain"], "custom");
+    }
+
+    #[test]
+    fn test_notify_json_error() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let err = serde_json::from_str::<Vec<u32>>("[1,\n \"2\"]").unwrap_err();
+        let builder = api.notify_json_error(&err, Some("[1]"));
+        let line = line!() - 1;
+        let json: serde_json::Value =
+            serde_json::from_str(&builder.create_json().unwrap()).unwrap();
+        let event = &json["events"][0];
+
+        assert_eq!(event["exceptions"][0]["errorClass"], "serde_json::Error");
+        assert_eq!(
+            event["metaData"]["json"],
+            json!({"line": 2, "column": 4, "category": "data", "path": "[1]",
+                "location": format!("src/bugsnag_impl.rs:{line}:27")})
+        );
+        let frame = &event["exceptions"][0]["stacktrace"][0];
+        assert_eq!(frame["file"], "src/bugsnag_impl.rs");
+        assert_eq!(frame["lineNumber"], line);
+        assert_eq!(frame["method"], "<deserialization>");
+    }
+
+    #[test]
+    fn test_from_io_error() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let err = std::fs::File::open("/does/not/exist").unwrap_err();
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify("Error", "message")
+                .from_io_error(&err)
+                .create_json()
+                .unwrap(),
+        )
+        .unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["exceptions"][0]["errorClass"], "Io::NotFound");
+        assert_eq!(event["metaData"]["io"]["kind"], "NotFound");
+        assert!(event["metaData"]["io"]["osError"].is_i64());
+
+        let err = std::io::Error::new(std::io::ErrorKind::TimedOut, "slow");
+        assert_eq!(io_error_class(err.kind()), "Io::TimedOut");
+    }
+
+    #[test]
+    fn test_pseudonymization() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        api.set_device_info(Some("build-host-7"), None);
+        api.set_user(User::new("42", "Jane", "jane@example.com"));
+        api.set_pseudonymization_key(Some(b"secret"));
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let event = &json["events"][0];
+
+        let hostname = event["device"]["hostname"].as_str().unwrap();
+        assert_eq!(hostname.len(), 64);
+        assert!(!json.to_string().contains("build-host-7"));
+        assert!(!json.to_string().contains("jane@example.com"));
+
+        // stable across events
+        let json = api.notify("Error", "message").create_json().unwrap();
+        assert!(json.contains(hostname));
+    }
+
+    #[test]
+    fn test_notify_record() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        let kvs = [("user_id", 42)];
+        let json: serde_json::Value = serde_json::from_str(
+            &api.notify_record(
+                &log::Record::builder()
+                    .args(format_args!("payment failed"))
+                    .level(log::Level::Warn)
+                    .target("billing")
+                    .key_values(&kvs)
+                    .build(),
+            )
+            .create_json()
+            .unwrap(),
+        )
+        .unwrap();
+
+        let event = &json["events"][0];
+        assert_eq!(event["severity"], "warning");
+        assert_eq!(event["context"], "billing");
+        assert_eq!(event["exceptions"][0]["message"], "payment failed");
+        assert_eq!(event["metaData"]["log"]["user_id"], 42);
+        assert_eq!(event["metaData"]["log"]["level"], "WARN");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_failure_injection() {
+        use crate::testing::FailureInjection;
+        use crate::transport::TransportOptions;
+
+        let dir = std::env::temp_dir().join(format!("bugsnag_injection_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_offline_storage(dir.to_str().unwrap());
+        api.set_transport_options(
+            TransportOptions::new().failure_injection(FailureInjection::new().fail_first(1)),
+        );
+
+        let outcome = api.notify("Error", "message").send().unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(_)));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_info_as_breadcrumbs() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        api.reload_config(Config::new().info_as_breadcrumbs(true));
+
+        assert_eq!(
+            api.notify("CacheMiss", "user 42")
+                .severity(Severity::Info)
+                .send()
+                .unwrap(),
+            Outcome::ConvertedToBreadcrumb
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        let crumb = &json["events"][0]["breadcrumbs"][0];
+        assert_eq!(crumb["name"], "CacheMiss");
+        assert_eq!(crumb["metaData"]["message"], "user 42");
+
+        // kept for the following events as well
+        let json: serde_json::Value =
+            serde_json::from_str(&api.notify("Error", "message").create_json().unwrap()).unwrap();
+        assert_eq!(json["events"][0]["breadcrumbs"][0]["name"], "CacheMiss");
+    }
+
+    #[test]
+    fn test_capture_callback() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::Captured
+        );
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        assert!(captured[0].contains("\"errorClass\":\"Error\""));
+    }
+
+    #[cfg(not(feature = "http"))]
+    #[test]
+    fn test_capture_only_stores_offline() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_capture_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_offline_storage(dir.to_str().unwrap());
+
+        let outcome = api.notify("Error", "message").send().unwrap();
+        assert!(matches!(outcome, Outcome::StoredOffline(path) if path.starts_with(&dir)));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        api.set_coalescing(Some(Duration::from_millis(200)));
+        let barrier = Arc::new(std::sync::Barrier::new(3));
+
+        let threads = (0..3)
+            .map(|_| {
+                let mut api = api.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    api.notify("Error", "timeout").send().unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        let outcomes = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .collect::<Vec<_>>();
+
+        let queued = outcomes.iter().filter(|o| **o == Outcome::Queued).count();
+        assert_eq!(queued, 1);
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes
+            .iter()
+            .all(|o| *o == Outcome::Queued || *o == Outcome::Coalesced));
+        assert!(api.flush(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_coalescing_does_not_block() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+        api.set_coalescing(Some(Duration::from_secs(2)));
+
+        let start = std::time::Instant::now();
+        assert_eq!(
+            api.notify("Error", "timeout").send().unwrap(),
+            Outcome::Queued
+        );
+        assert_eq!(
+            api.notify("Error", "timeout").send().unwrap(),
+            Outcome::Coalesced
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(captured.lock().unwrap().is_empty());
+
+        // sends with a deadline are not held back
+        assert_ne!(
+            api.notify("Error", "timeout")
+                .send_with_deadline(Duration::from_secs(1))
+                .unwrap(),
+            Outcome::Queued
+        );
+        assert_eq!(captured.lock().unwrap().len(), 1);
+
+        assert!(api.flush(Duration::from_secs(5)));
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(&captured[1]).unwrap();
+        assert_eq!(json["events"][0]["metaData"]["coalesced"]["occurrences"], 2);
+    }
+
+    #[test]
+    fn test_notify_once() {
+        let key = format!("cache-degraded-{}", uuid::Uuid::new_v4());
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+
+        let mut notify = || {
+            api.notify_once(&key, "Cache::Degraded", "falling back to the database")
+                .send()
+                .unwrap()
+        };
+        assert_eq!(notify(), Outcome::WrittenToConsole);
+        assert_eq!(notify(), Outcome::SuppressedOnce);
+        assert_eq!(notify(), Outcome::SuppressedOnce);
+        assert_eq!(api.once_count(&key), 3);
+    }
+
+    #[test]
+    fn test_notify_once_vetoed_keeps_key() {
+        let key = format!("cache-degraded-{}", uuid::Uuid::new_v4());
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        let outcome = api
+            .notify_once(&key, "Cache::Degraded", "falling back to the database")
+            .on_before_send(|_| false)
+            .send()
+            .unwrap();
+        assert_eq!(outcome, Outcome::DiscardedByFilter);
+        let outcome = api
+            .notify_once(&key, "Cache::Degraded", "falling back to the database")
+            .send()
+            .unwrap();
+        assert_eq!(outcome, Outcome::Captured);
+
+        let json: serde_json::Value = serde_json::from_str(&captured.lock().unwrap()[0]).unwrap();
+        assert_eq!(json["events"][0]["metaData"]["once"]["hits"], 2);
+    }
+
+    #[test]
+    fn test_console_output() {
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.use_console_output(true);
+        assert_eq!(
+            api.notify("Error", "message").send().unwrap(),
+            Outcome::WrittenToConsole
+        );
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn rate_limit() {
         let mut rate_limit = RateLimit::new(
             vec![SendLimit::new(std::time::Duration::from_millis(1000), 10)],
@@ -0,0 +1,148 @@
+//! Helpers for the build script of an application that uses this crate.
+//!
+//! # Example
+//!
+//! In `build.rs` of the application, with `bugsnag` as build-dependency:
+//!
+//! ```no_run
+//! bugsnag::build::embed_dependencies();
+//! bugsnag::build::embed_build_info();
+//! ```
+//!
+//! And in the application:
+//!
+//! ```ignore
+//! api.set_dependencies(bugsnag::dependencies!()).unwrap();
+//! api.set_build_info(bugsnag::build_info!()).unwrap();
+//! ```
+
+use super::buildenv;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const DEPENDENCIES_FILE: &str = "bugsnag_dependencies.json";
+
+/// Embeds the resolved dependencies from the `Cargo.lock` of the package that is built.
+/// The dependencies can be accessed in the package with the `bugsnag::dependencies!()` macro.
+///
+/// # Panics
+///
+/// When called outside of a build script.
+pub fn embed_dependencies() {
+    let manifest_dir =
+        PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is not set"));
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is not set"));
+
+    let dependencies = match find_lock_file(&manifest_dir) {
+        Some(lock_file) => {
+            println!("cargo:rerun-if-changed={}", lock_file.display());
+            let lock = std::fs::read_to_string(&lock_file).unwrap_or_default();
+            parse_lock_file(&lock)
+        }
+        None => {
+            println!("cargo:warning=bugsnag: no Cargo.lock found, dependencies are not embedded");
+            BTreeMap::new()
+        }
+    };
+
+    let out_file = out_dir.join(DEPENDENCIES_FILE);
+    let json = serde_json::to_string(&dependencies).expect("failed to serialize dependencies");
+    std::fs::write(&out_file, json).expect("failed to write dependencies");
+
+    println!(
+        "cargo:rustc-env=BUGSNAG_DEPENDENCIES={}",
+        out_file.display()
+    );
+}
+
+/// Embeds the target, profile, optimization level and LTO setting of the package that is
+/// built. The settings can be accessed in the package with the `bugsnag::build_info!()`
+/// macro. Without it, the `app` tab reports the settings of the build of this crate,
+/// which may differ from the ones of the application, e.g. with per-package profiles.
+///
+/// # Panics
+///
+/// When called outside of a build script.
+pub fn embed_build_info() {
+    let var = |name| env::var(name).unwrap_or_else(|_| "unknown".to_owned());
+    let build_info = json!({
+        "buildTarget": var("TARGET"),
+        "buildProfile": var("PROFILE"),
+        "optLevel": var("OPT_LEVEL"),
+        "lto": buildenv::lto(),
+    });
+    println!("cargo:rustc-env=BUGSNAG_APP_BUILD_INFO={build_info}");
+    buildenv::rerun_if_lto_changed();
+}
+
+/// Searches the `Cargo.lock` in the given directory and its parents, as the lock file of a
+/// workspace is located in the workspace root.
+fn find_lock_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join("Cargo.lock"))
+        .find(|f| f.is_file())
+}
+
+/// Parses the packages of a `Cargo.lock` into a map of name to versions.
+fn parse_lock_file(lock: &str) -> BTreeMap<String, String> {
+    let mut dependencies: BTreeMap<String, String> = BTreeMap::new();
+    let mut name: Option<&str> = None;
+
+    for line in lock.lines().map(str::trim) {
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some(val) = line.strip_prefix("name = ") {
+            name = Some(val.trim_matches('"'));
+        } else if let Some(val) = line.strip_prefix("version = ") {
+            if let Some(name) = name {
+                let version = val.trim_matches('"');
+                dependencies
+                    .entry(name.to_owned())
+                    .and_modify(|v| {
+                        v.push_str(", ");
+                        v.push_str(version);
+                    })
+                    .or_insert_with(|| version.to_owned());
+            }
+        }
+    }
+
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_lock_file;
+
+    #[test]
+    fn test_parse_lock_file() {
+        let lock = r#"
+# This file is automatically @generated by Cargo.
+version = 3
+
+[[package]]
+name = "bitflags"
+version = "1.3.2"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "bitflags"
+version = "2.5.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "my_app"
+version = "0.1.0"
+dependencies = [
+ "bitflags 2.5.0",
+]
+"#;
+        let dependencies = parse_lock_file(lock);
+
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(dependencies["bitflags"], "1.3.2, 2.5.0");
+        assert_eq!(dependencies["my_app"], "0.1.0");
+    }
+}
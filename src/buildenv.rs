@@ -0,0 +1,37 @@
+//! Settings of a build that are read from the environment of a build script. Shared by
+//! the build script of this crate and `build::embed_build_info`.
+
+use std::env;
+
+/// Returns the LTO setting from the rustflags or the profile environment variables.
+/// Cargo does not pass the LTO setting of `Cargo.toml` to build scripts, so it is
+/// `unknown` if it is only configured there.
+pub fn lto() -> String {
+    let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let mut flags = rustflags.split('\x1f');
+    while let Some(flag) = flags.next() {
+        let codegen = match flag {
+            "-C" | "--codegen" => flags.next().unwrap_or_default(),
+            _ => flag.strip_prefix("-C").unwrap_or_default(),
+        };
+        if codegen == "lto" {
+            return "true".to_owned();
+        }
+        if let Some(val) = codegen.strip_prefix("lto=") {
+            return val.to_owned();
+        }
+    }
+
+    let profile = match env::var("PROFILE").as_deref() {
+        Ok("release") => "RELEASE",
+        _ => "DEV",
+    };
+    env::var(format!("CARGO_PROFILE_{profile}_LTO")).unwrap_or_else(|_| "unknown".to_owned())
+}
+
+/// Prints the instructions that rerun the build script when the LTO setting changes.
+pub fn rerun_if_lto_changed() {
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+    println!("cargo:rerun-if-env-changed=CARGO_PROFILE_DEV_LTO");
+    println!("cargo:rerun-if-env-changed=CARGO_PROFILE_RELEASE_LTO");
+}
@@ -0,0 +1,210 @@
+//! Module for reading the build id of binaries, which identifies the debug info that is
+//! needed to symbolicate addresses of stripped binaries.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+const SHT_NOTE: u32 = 7;
+const NT_GNU_BUILD_ID: u32 = 3;
+const MAX_NOTES_SIZE: u64 = 64 * 1024;
+
+/// Build ids already read, by path of the binary.
+static CACHE: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+
+/// Returns the GNU build id of the ELF binary at `path` as hex string. The result is
+/// cached, as the same binaries show up in every stacktrace.
+pub fn of_file(path: &str) -> Option<String> {
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+
+    cache
+        .entry(path.to_owned())
+        .or_insert_with(|| {
+            File::open(path)
+                .and_then(|mut file| read_build_id(&mut file))
+                .ok()
+                .flatten()
+        })
+        .clone()
+}
+
+/// Returns the build id of the running executable.
+pub fn of_current_exe() -> Option<String> {
+    let exe = std::env::current_exe().ok()?;
+    of_file(exe.to_str()?)
+}
+
+/// Reads numbers of the byte order of the ELF file.
+struct Reader {
+    big_endian: bool,
+}
+
+impl Reader {
+    fn u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+
+    fn u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+
+    fn u64(&self, b: &[u8]) -> u64 {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&b[..8]);
+        if self.big_endian {
+            u64::from_be_bytes(buf)
+        } else {
+            u64::from_le_bytes(buf)
+        }
+    }
+}
+
+fn read_at<R: Read + Seek>(file: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads the build id from the note sections of an ELF file. Returns `None` if the file
+/// is no ELF file or has no build id.
+fn read_build_id<R: Read + Seek>(file: &mut R) -> io::Result<Option<String>> {
+    let header = read_at(file, 0, 64)?;
+    if &header[..4] != b"\x7fELF" {
+        return Ok(None);
+    }
+    let is_64 = header[4] == 2;
+    let r = Reader {
+        big_endian: header[5] == 2,
+    };
+
+    let (sh_offset, sh_entsize, sh_num) = if is_64 {
+        (
+            r.u64(&header[0x28..]),
+            r.u16(&header[0x3a..]),
+            r.u16(&header[0x3c..]),
+        )
+    } else {
+        (
+            u64::from(r.u32(&header[0x20..])),
+            r.u16(&header[0x2e..]),
+            r.u16(&header[0x30..]),
+        )
+    };
+    if usize::from(sh_entsize) != if is_64 { 64 } else { 40 } {
+        return Ok(None);
+    }
+    let sections = read_at(
+        file,
+        sh_offset,
+        usize::from(sh_entsize) * usize::from(sh_num),
+    )?;
+
+    for section in sections.chunks_exact(usize::from(sh_entsize)) {
+        if r.u32(&section[4..]) != SHT_NOTE {
+            continue;
+        }
+        let (offset, size) = if is_64 {
+            (r.u64(&section[0x18..]), r.u64(&section[0x20..]))
+        } else {
+            (
+                u64::from(r.u32(&section[0x10..])),
+                u64::from(r.u32(&section[0x14..])),
+            )
+        };
+        // build ids are small, larger note sections are not worth reading
+        if size > MAX_NOTES_SIZE {
+            continue;
+        }
+        let notes = read_at(file, offset, size as usize)?;
+        if let Some(id) = find_build_id(&r, &notes) {
+            return Ok(Some(id));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Searches the notes of a note section for the GNU build id.
+fn find_build_id(r: &Reader, mut notes: &[u8]) -> Option<String> {
+    let align = |n: usize| (n + 3) & !3;
+
+    while notes.len() >= 12 {
+        let name_size = r.u32(notes) as usize;
+        let desc_size = r.u32(&notes[4..]) as usize;
+        let note_type = r.u32(&notes[8..]);
+        let desc_start = 12 + align(name_size);
+        let desc = notes.get(desc_start..desc_start + desc_size)?;
+
+        if note_type == NT_GNU_BUILD_ID && notes.get(12..12 + name_size)? == b"GNU\0" {
+            return Some(desc.iter().map(|b| format!("{b:02x}")).collect());
+        }
+        notes = notes.get(desc_start + align(desc_size)..)?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_build_id;
+    use std::io::Cursor;
+
+    /// Creates a 64 bit little endian ELF file with a single note section.
+    fn elf_with_note(note_type: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&4u32.to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&note_type.to_le_bytes());
+        note.extend_from_slice(b"GNU\0");
+        note.extend_from_slice(desc);
+
+        let mut elf = vec![0; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 2;
+        elf[5] = 1;
+        let note_offset = elf.len() as u64;
+        elf.extend_from_slice(&note);
+
+        let sh_offset = elf.len() as u64;
+        elf[0x28..0x30].copy_from_slice(&sh_offset.to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&1u16.to_le_bytes());
+
+        let mut section = vec![0; 64];
+        section[4..8].copy_from_slice(&7u32.to_le_bytes());
+        section[0x18..0x20].copy_from_slice(&note_offset.to_le_bytes());
+        section[0x20..0x28].copy_from_slice(&(note.len() as u64).to_le_bytes());
+        elf.extend_from_slice(&section);
+
+        elf
+    }
+
+    #[test]
+    fn test_read_build_id() {
+        let elf = elf_with_note(3, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            read_build_id(&mut Cursor::new(elf)).unwrap(),
+            Some("deadbeef".to_owned())
+        );
+
+        let elf = elf_with_note(1, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(read_build_id(&mut Cursor::new(elf)).unwrap(), None);
+
+        let mut not_elf = vec![0; 64];
+        not_elf[..4].copy_from_slice(b"MZ\0\0");
+        assert_eq!(read_build_id(&mut Cursor::new(not_elf)).unwrap(), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Module for recording the invoked command of command line tools built with clap.
+//!
+//! The subcommand chain, e.g. `backup restore`, is used as context and the arguments given
+//! on the command line are reported in the `cli` metadata tab, so reports show which
+//! command failed. Values of arguments that look like credentials are redacted, as well
+//! as values that contain one of the redacted keys of the `Config`, e.g.
+//! `--header "Authorization: Bearer ..."`.
+//!
+//! # Example
+//!
+//! ```
+//! let matches = clap::Command::new("tool")
+//!     .subcommand(clap::Command::new("sync"))
+//!     .get_matches_from(["tool", "sync"]);
+//!
+//! let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! bugsnag::cli::record_matches(&mut api, "tool", &matches);
+//! ```
+
+use super::plugin::{Event, Plugin};
+use super::redact::REDACTED;
+use super::Bugsnag;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde_json::{json, Map, Value};
+
+/// Parts of argument names whose values are not reported.
+const SENSITIVE: [&str; 6] = ["password", "passwd", "secret", "token", "key", "auth"];
+
+/// Adds the `cli` tab to every event.
+struct CliPlugin {
+    tab: Value,
+}
+
+impl Plugin for CliPlugin {
+    fn name(&self) -> &str {
+        "cli"
+    }
+
+    fn enrich(&self, event: &mut Event) {
+        event.set_metadata_tab("cli", self.tab.clone());
+    }
+}
+
+/// Returns the arguments of `matches` that were given on the command line. Values that
+/// contain one of `redacted_keys`, which are lowercase, are redacted.
+fn explicit_args(matches: &ArgMatches, redacted_keys: &[String]) -> Map<String, Value> {
+    let mut args = Map::new();
+
+    for id in matches.ids() {
+        let id = id.as_str();
+        if matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let lower = id.to_lowercase();
+        let value = if SENSITIVE.iter().any(|s| lower.contains(s)) {
+            json!(REDACTED)
+        } else {
+            match matches.get_raw(id) {
+                Some(values) => {
+                    let values = values
+                        .map(|v| v.to_string_lossy().into_owned())
+                        .map(|v| {
+                            let lower = v.to_lowercase();
+                            match redacted_keys.iter().any(|k| lower.contains(k.as_str())) {
+                                true => REDACTED.to_owned(),
+                                false => v,
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    match values.as_slice() {
+                        [] => json!(true),
+                        [value] => json!(value),
+                        _ => json!(values),
+                    }
+                }
+                None => json!(true),
+            }
+        };
+        args.insert(id.to_owned(), value);
+    }
+
+    args
+}
+
+/// Records the invoked command of `bin`. The chain of subcommands is set as context of
+/// `bugsnag` and the command with its arguments is added to the `cli` tab of every event.
+/// The arguments are listed in the order of the chain, the first are the arguments of
+/// `bin`. Values are redacted with the redacted keys of the current `Config`.
+pub fn record_matches(bugsnag: &mut Bugsnag, bin: &str, matches: &ArgMatches) {
+    let redacted_keys = bugsnag.config().redacted_keys;
+    let mut command = vec![bin.to_owned()];
+    let mut args = vec![Value::Object(explicit_args(matches, &redacted_keys))];
+
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        command.push(name.to_owned());
+        args.push(Value::Object(explicit_args(sub, &redacted_keys)));
+        current = sub;
+    }
+
+    let command = command.join(" ");
+    bugsnag.set_context(&command);
+    bugsnag.register_plugin(CliPlugin {
+        tab: json!({"command": command, "args": args}),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::record_matches;
+    use crate::config::Config;
+    use crate::Bugsnag;
+    use clap::{Arg, ArgAction, Command};
+
+    #[test]
+    fn test_record_matches() {
+        let matches = Command::new("tool")
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .action(ArgAction::SetTrue),
+            )
+            .subcommand(
+                Command::new("upload")
+                    .arg(Arg::new("file").long("file"))
+                    .arg(Arg::new("header").long("header"))
+                    .arg(Arg::new("api-token").long("api-token"))
+                    .arg(Arg::new("retries").long("retries").default_value("3")),
+            )
+            .get_matches_from([
+                "tool",
+                "--verbose",
+                "upload",
+                "--file",
+                "a.txt",
+                "--header",
+                "Authorization: Bearer abc",
+                "--api-token",
+                "hunter2",
+            ]);
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        record_matches(&mut api, "tool", &matches);
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let event = &json["events"][0];
+
+        assert_eq!(event["context"], "tool upload");
+        assert_eq!(
+            event["metaData"]["cli"],
+            serde_json::json!({
+                "command": "tool upload",
+                "args": [
+                    {"verbose": "true"},
+                    {"file": "a.txt", "header": "[REDACTED]", "api-token": "[REDACTED]"}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_record_matches_subcommand_named_like_bin() {
+        let matches = Command::new("tool")
+            .arg(Arg::new("level").long("level"))
+            .subcommand(Command::new("tool").arg(Arg::new("file").long("file")))
+            .get_matches_from(["tool", "--level", "2", "tool", "--file", "a.txt"]);
+
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+        record_matches(&mut api, "tool", &matches);
+
+        let json = api.notify("Error", "message").create_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json["events"][0]["metaData"]["cli"]["args"],
+            serde_json::json!([{"level": "2"}, {"file": "a.txt"}])
+        );
+    }
+}
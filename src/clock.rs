@@ -0,0 +1,168 @@
+//! Module for reading the monotonic clock, which keeps counting correctly on devices
+//! whose real time clock is wrong. Together with the wall clock time of the upload, the
+//! monotonic time of an event gives its real time of occurrence.
+
+use chrono::{DateTime, Utc};
+use std::sync::OnceLock;
+
+/// A reading of the monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reading {
+    /// Milliseconds since the start of the clock.
+    pub millis: u64,
+    /// The start of the clock, `boot` or `process`.
+    pub since: &'static str,
+}
+
+/// Reads the time since boot, including time spent in suspend where supported.
+#[cfg(unix)]
+pub fn now() -> Reading {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const CLOCK: libc::clockid_t = libc::CLOCK_BOOTTIME;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is valid for writes. Cannot fail for a supported clock and a valid
+    // pointer.
+    unsafe { libc::clock_gettime(CLOCK, &mut ts) };
+
+    Reading {
+        millis: ts.tv_sec as u64 * 1000 + ts.tv_nsec as u64 / 1_000_000,
+        since: "boot",
+    }
+}
+
+/// Reads the time since the clock was first read by this process.
+#[cfg(not(unix))]
+pub fn now() -> Reading {
+    static START: OnceLock<std::time::Instant> = OnceLock::new();
+
+    Reading {
+        millis: START
+            .get_or_init(std::time::Instant::now)
+            .elapsed()
+            .as_millis() as u64,
+        since: "process",
+    }
+}
+
+/// Returns the reading of the monotonic clock when the process was launched. On Linux it
+/// is the start time of the process, elsewhere the first call is taken as the launch, so
+/// it is called when the first instance of the api is created.
+pub fn launch() -> Reading {
+    static LAUNCH: OnceLock<Reading> = OnceLock::new();
+    *LAUNCH.get_or_init(|| process_start().unwrap_or_else(now))
+}
+
+/// Returns the milliseconds between the launch of the process and a reading.
+pub fn since_launch(reading: Reading) -> Option<u64> {
+    let launch = launch();
+    (launch.since == reading.since).then(|| reading.millis.saturating_sub(launch.millis))
+}
+
+/// Reads the start time of the process since boot from `/proc/self/stat`.
+#[cfg(target_os = "linux")]
+fn process_start() -> Option<Reading> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // the name in parentheses may contain spaces, the start time is the 20th field
+    // after it
+    let start_ticks = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)?
+        .parse::<u64>()
+        .ok()?;
+    // SAFETY: `sysconf` only reads a configuration value, an unknown name returns -1.
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_second <= 0 {
+        return None;
+    }
+
+    Some(Reading {
+        millis: start_ticks * 1000 / ticks_per_second as u64,
+        since: "boot",
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_start() -> Option<Reading> {
+    None
+}
+
+/// Returns the id of the current boot, which tells if two readings since boot are
+/// comparable.
+pub fn boot_id() -> Option<&'static str> {
+    #[cfg(target_os = "linux")]
+    {
+        static BOOT_ID: OnceLock<Option<String>> = OnceLock::new();
+        BOOT_ID
+            .get_or_init(|| {
+                std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+                    .ok()
+                    .map(|id| id.trim().to_owned())
+            })
+            .as_deref()
+    }
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Returns the real time of an earlier reading, measured back from the current wall clock
+/// time, or `None` if the reading was taken before the last boot.
+pub fn wall_time(reading: Reading, boot: &str) -> Option<DateTime<Utc>> {
+    let now = now();
+    if reading.since != "boot" || now.since != "boot" || boot_id() != Some(boot) {
+        return None;
+    }
+    let elapsed = now.millis.checked_sub(reading.millis)?;
+    Some(Utc::now() - chrono::Duration::milliseconds(elapsed as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{launch, now, since_launch};
+
+    #[test]
+    fn test_monotonic() {
+        let first = now();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let second = now();
+
+        assert!(second.millis >= first.millis + 20);
+        assert_eq!(first.since, second.since);
+    }
+
+    #[test]
+    fn test_since_launch() {
+        let launch = launch();
+        assert!(launch.millis <= now().millis);
+        assert_eq!(launch, super::launch());
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(since_launch(now()).unwrap() >= 20);
+        let other = super::Reading {
+            millis: 0,
+            since: "other",
+        };
+        assert_eq!(since_launch(other), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_wall_time() {
+        let reading = super::Reading {
+            millis: now().millis - 60_000,
+            since: "boot",
+        };
+        let boot = super::boot_id().unwrap();
+        let ago = chrono::Utc::now() - super::wall_time(reading, boot).unwrap();
+        assert!((59_000..61_000).contains(&ago.num_milliseconds()));
+
+        assert_eq!(super::wall_time(reading, "other boot"), None);
+    }
+}
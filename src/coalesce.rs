@@ -0,0 +1,302 @@
+//! Module for coalescing identical notifications that are sent at the same time, e.g. by
+//! many threads hitting the same failure, into a single event.
+//!
+//! The first notification is held back for the window by the timer thread of the
+//! coalescer, so none of the callers waits for it. Identical notifications sent in the
+//! meantime are only counted. The timer thread runs while notifications are held back.
+
+use super::reentrancy::ReportingGuard;
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The notifications coalesced into one event.
+#[derive(Debug, Default, PartialEq)]
+pub struct Occurrences {
+    pub count: u32,
+    pub threads: Vec<String>,
+}
+
+impl Occurrences {
+    fn add_current_thread(&mut self) {
+        self.count += 1;
+        let name = std::thread::current()
+            .name()
+            .map(|name| name.to_owned())
+            .unwrap_or_else(|| format!("{:?}", std::thread::current().id()));
+        if !self.threads.contains(&name) {
+            self.threads.push(name);
+        }
+    }
+}
+
+/// Adds the occurrences to the `coalesced` tab of the events of a notification, if it
+/// stands for more than one notification.
+pub fn add_occurrences(json: String, occurrences: &Occurrences) -> String {
+    if occurrences.count < 2 {
+        return json;
+    }
+    let Ok(mut notification) = serde_json::from_str::<Value>(&json) else {
+        return json;
+    };
+    if let Some(events) = notification["events"].as_array_mut() {
+        for event in events.iter_mut().filter(|e| e.is_object()) {
+            let metadata = &mut event["metaData"];
+            if !metadata.is_object() {
+                *metadata = json!({});
+            }
+            metadata["coalesced"] =
+                json!({"occurrences": occurrences.count, "threads": occurrences.threads});
+        }
+    }
+    notification.to_string()
+}
+
+type SendFn = Box<dyn FnOnce(Occurrences) + Send>;
+
+/// The notifications held back by the timer thread.
+#[derive(Default)]
+struct Timer {
+    /// The ids of the held back notifications, the earliest deadline first.
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    sends: HashMap<u64, (String, SendFn)>,
+    next_id: u64,
+    /// Whether the timer thread is running. It exits once no notification is held back.
+    running: bool,
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timer")
+            .field("deadlines", &self.deadlines)
+            .field("running", &self.running)
+            .finish()
+    }
+}
+
+/// Tracks the notifications waiting for identical notifications to coalesce with.
+#[derive(Debug)]
+pub struct Coalescer {
+    window: Duration,
+    pending: Mutex<HashMap<String, Occurrences>>,
+    /// The number of notifications held back by the timer thread.
+    deferred: AtomicUsize,
+    timer: Mutex<Timer>,
+    /// Wakes up the timer thread when a notification is held back.
+    wakeup: Condvar,
+}
+
+impl Coalescer {
+    pub fn new(window: Duration) -> Coalescer {
+        Coalescer {
+            window,
+            pending: Mutex::new(HashMap::new()),
+            deferred: AtomicUsize::new(0),
+            timer: Mutex::new(Timer::default()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// Registers a notification with `key`. Returns true if it is the first one, which
+    /// is to be passed to `defer` or `cancel`. Otherwise an identical notification is
+    /// already waiting and the notification is counted there.
+    pub fn register(&self, key: &str) -> bool {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(occurrences) = pending.get_mut(key) {
+            occurrences.add_current_thread();
+            return false;
+        }
+        pending
+            .entry(key.to_owned())
+            .or_default()
+            .add_current_thread();
+        true
+    }
+
+    /// Forgets the first notification with `key`, e.g. because it was dropped by a filter.
+    /// The notifications counted for it are dropped as well.
+    pub fn cancel(&self, key: &str) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+    }
+
+    /// Calls `send` with the occurrences of `key` once the window is over, on the timer
+    /// thread. If the timer thread cannot be started, `send` is called right away on the
+    /// current thread.
+    pub fn defer<F>(self: &Arc<Self>, key: String, send: F)
+    where
+        F: FnOnce(Occurrences) + Send + 'static,
+    {
+        let mut timer = self.timer.lock().unwrap_or_else(PoisonError::into_inner);
+        if !timer.running {
+            let coalescer = self.clone();
+            let spawned = std::thread::Builder::new()
+                .name("bugsnag-coalesce".to_owned())
+                .spawn(move || coalescer.run());
+            if spawned.is_err() {
+                drop(timer);
+                send(self.take(&key));
+                return;
+            }
+            timer.running = true;
+        }
+
+        let id = timer.next_id;
+        timer.next_id += 1;
+        timer
+            .deadlines
+            .push(Reverse((Instant::now() + self.window, id)));
+        timer.sends.insert(id, (key, Box::new(send)));
+        self.deferred.fetch_add(1, Ordering::SeqCst);
+        self.wakeup.notify_one();
+    }
+
+    /// Removes the occurrences of `key`, so the next notification with `key` is not
+    /// coalesced with them.
+    fn take(&self, key: &str) -> Occurrences {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key)
+            .unwrap_or_default()
+    }
+
+    /// The timer thread, calls the held back sends once their window is over.
+    fn run(&self) {
+        // everything the timer thread logs is part of reporting
+        let _reporting = ReportingGuard::enter();
+        let mut timer = self.timer.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            let Some(&Reverse((deadline, id))) = timer.deadlines.peek() else {
+                timer.running = false;
+                return;
+            };
+            let now = Instant::now();
+            if deadline > now {
+                timer = self
+                    .wakeup
+                    .wait_timeout(timer, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+                continue;
+            }
+
+            timer.deadlines.pop();
+            let Some((key, send)) = timer.sends.remove(&id) else {
+                continue;
+            };
+            drop(timer);
+            let occurrences = self.take(&key);
+            // a panicking send must not stop the sends of the other notifications
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| send(occurrences)));
+            self.deferred.fetch_sub(1, Ordering::SeqCst);
+            timer = self.timer.lock().unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Returns the number of notifications that are held back.
+    pub fn deferred(&self) -> usize {
+        self.deferred.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_occurrences, Coalescer, Occurrences};
+    use std::sync::{mpsc, Arc, Barrier};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_coalesce() {
+        let coalescer = Arc::new(Coalescer::new(Duration::from_millis(500)));
+        let barrier = Arc::new(Barrier::new(4));
+        let (tx, rx) = mpsc::channel();
+
+        let start = Instant::now();
+        let threads = (0..4)
+            .map(|i| {
+                let coalescer = coalescer.clone();
+                let barrier = barrier.clone();
+                let tx = tx.clone();
+                std::thread::Builder::new()
+                    .name(format!("worker-{i}"))
+                    .spawn(move || {
+                        barrier.wait();
+                        let first = coalescer.register("Error: timeout");
+                        // the timer is started after all notifications are counted
+                        barrier.wait();
+                        if first {
+                            coalescer
+                                .defer("Error: timeout".to_owned(), move |o| tx.send(o).unwrap());
+                        }
+                        first
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let first = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|first| *first)
+            .count();
+        assert_eq!(first, 1);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(coalescer.deferred(), 1);
+
+        let mut occurrences = rx.recv().unwrap();
+        assert_eq!(occurrences.count, 4);
+        occurrences.threads.sort();
+        assert_eq!(
+            occurrences.threads,
+            vec!["worker-0", "worker-1", "worker-2", "worker-3"]
+        );
+
+        // the window is over, the next notification is not coalesced
+        assert!(coalescer.register("Error: timeout"));
+        coalescer.cancel("Error: timeout");
+        assert!(coalescer.register("Error: timeout"));
+    }
+
+    #[test]
+    fn test_single_timer_thread() {
+        let coalescer = Arc::new(Coalescer::new(Duration::from_millis(50)));
+        let (tx, rx) = mpsc::channel();
+        for key in ["Error: a", "Error: b", "Error: c"] {
+            assert!(coalescer.register(key));
+            let tx = tx.clone();
+            coalescer.defer(key.to_owned(), move |_| {
+                tx.send((key, std::thread::current().id())).unwrap()
+            });
+        }
+        assert_eq!(coalescer.deferred(), 3);
+
+        let sent = rx.iter().take(3).collect::<Vec<_>>();
+        let keys = sent.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        assert_eq!(keys, ["Error: a", "Error: b", "Error: c"]);
+        assert!(sent.iter().all(|(_, thread)| *thread == sent[0].1));
+    }
+
+    #[test]
+    fn test_add_occurrences() {
+        let json = r#"{"events":[{"metaData":{}}]}"#.to_owned();
+        let once = Occurrences {
+            count: 1,
+            threads: vec!["main".to_owned()],
+        };
+        assert_eq!(add_occurrences(json.clone(), &once), json);
+
+        let twice = Occurrences {
+            count: 2,
+            threads: vec!["main".to_owned()],
+        };
+        let json: serde_json::Value = serde_json::from_str(&add_occurrences(json, &twice)).unwrap();
+        assert_eq!(json["events"][0]["metaData"]["coalesced"]["occurrences"], 2);
+    }
+}
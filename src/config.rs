@@ -0,0 +1,211 @@
+//! Module for the settings of a `Bugsnag` instance that can be changed at runtime.
+
+use super::{redact, Bugsnag, RateLimit};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Settings that can be swapped on a live `Bugsnag` instance with `Bugsnag::reload_config`.
+/// All clones of an instance share the same configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub(crate) enabled: bool,
+    pub(crate) release_stage: Option<String>,
+    pub(crate) sample_rate: f64,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) panic_rate_limit: Option<RateLimit>,
+    pub(crate) class_budget: Option<u32>,
+    pub(crate) info_as_breadcrumbs: bool,
+    pub(crate) redacted_keys: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            enabled: true,
+            release_stage: None,
+            sample_rate: 1.0,
+            rate_limit: None,
+            panic_rate_limit: None,
+            class_budget: None,
+            info_as_breadcrumbs: false,
+            redacted_keys: redact::DEFAULT_REDACTED_KEYS
+                .iter()
+                .map(|k| (*k).to_owned())
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    /// Creates the default configuration, which sends every notification.
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    /// Enables or disables sending notifications.
+    pub fn enabled(mut self, val: bool) -> Self {
+        self.enabled = val;
+        self
+    }
+
+    /// Overrides the release stage of the app info.
+    pub fn release_stage(mut self, val: Option<&str>) -> Self {
+        self.release_stage = val.map(|v| v.to_owned());
+        self
+    }
+
+    /// Sets the fraction of notifications that are sent, between `0.0` and `1.0`.
+    pub fn sample_rate(mut self, val: f64) -> Self {
+        self.sample_rate = val.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the rate limit for notifications.
+    pub fn rate_limit(mut self, val: Option<RateLimit>) -> Self {
+        self.rate_limit = val;
+        self
+    }
+
+    /// Sets a separate rate limit for notifications of panics, which replaces the rate
+    /// limit for notifications marked with `NotifyBuilder::panic`, so a crash looping
+    /// thread can not use up the limit of handled errors. Use a different persistence
+    /// file than for `rate_limit`.
+    pub fn panic_rate_limit(mut self, val: Option<RateLimit>) -> Self {
+        self.panic_rate_limit = val;
+        self
+    }
+
+    /// Sets the maximum number of notifications per error class and app version. Once
+    /// the budget of a class is used up, the class is muted until the app version changes
+    /// or the process restarts.
+    pub fn class_budget(mut self, val: Option<u32>) -> Self {
+        self.class_budget = val;
+        self
+    }
+
+    /// Converts notifications with `Severity::Info` into breadcrumbs instead of sending them
+    /// as events. Like the breadcrumbs of `Bugsnag::leave_breadcrumb`, they are attached to
+    /// every following warning or error, until newer breadcrumbs push them out of the ring
+    /// of `Bugsnag::set_max_breadcrumbs`.
+    pub fn info_as_breadcrumbs(mut self, val: bool) -> Self {
+        self.info_as_breadcrumbs = val;
+        self
+    }
+
+    /// Sets the keys whose values are replaced by `[REDACTED]` in the metadata of events.
+    /// A key is redacted if it contains one of `keys`, ignoring case. Replaces the default
+    /// keys `password`, `authorization` and `secret`, an empty list disables the redaction.
+    pub fn redacted_keys(mut self, keys: &[&str]) -> Self {
+        self.redacted_keys = keys.iter().map(|k| k.to_lowercase()).collect();
+        self
+    }
+
+    /// Checks if a notification should be dropped by sampling.
+    pub(crate) fn sampled_out(&self) -> bool {
+        self.sample_rate < 1.0 && rand::random::<f64>() >= self.sample_rate
+    }
+}
+
+/// Handle of a thread that watches a configuration file. The thread stops when the
+/// handle is dropped.
+#[must_use = "the config file is no longer watched when the watcher is dropped"]
+pub struct ConfigWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Watches a json file containing a `Config` and reloads the configuration of `bugsnag`
+/// whenever the modification time of the file changes.
+///
+/// # Arguments
+///
+/// * `bugsnag` - The instance to reload, all of its clones see the new configuration.
+/// * `path` - The json file to watch.
+/// * `interval` - How often the modification time of the file is checked.
+pub fn watch_file(bugsnag: &Bugsnag, path: PathBuf, interval: Duration) -> ConfigWatcher {
+    let stop = Arc::new(AtomicBool::new(false));
+    let watcher = ConfigWatcher { stop: stop.clone() };
+    let bugsnag = bugsnag.clone();
+
+    std::thread::spawn(move || {
+        let mut last_modified: Option<SystemTime> = None;
+
+        while !stop.load(Ordering::Relaxed) {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+
+            if modified.is_some() && modified != last_modified {
+                last_modified = modified;
+
+                match std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| {
+                        serde_json::from_str::<Config>(&json).map_err(|e| e.to_string())
+                    }) {
+                    Ok(config) => bugsnag.reload_config(config),
+                    Err(e) => warn!("failed to reload config from {}: {e}", path.display()),
+                }
+            }
+
+            std::thread::sleep(interval);
+        }
+    });
+
+    watcher
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{watch_file, Config};
+    use crate::Bugsnag;
+    use std::time::Duration;
+
+    #[test]
+    fn test_config_from_json() {
+        let config: Config =
+            serde_json::from_str(r#"{"enabled": false, "release_stage": "staging"}"#).unwrap();
+
+        assert!(!config.enabled);
+        assert_eq!(config.release_stage.as_deref(), Some("staging"));
+        assert_eq!(config.sample_rate, 1.0);
+        assert!(config.rate_limit.is_none());
+        assert_eq!(
+            config.redacted_keys,
+            ["password", "authorization", "secret"]
+        );
+
+        let config: Config = serde_json::from_str(r#"{"redacted_keys": ["token"]}"#).unwrap();
+        assert_eq!(config.redacted_keys, ["token"]);
+    }
+
+    #[test]
+    fn test_config_sampling() {
+        assert!(!Config::new().sampled_out());
+        assert!(Config::new().sample_rate(0.0).sampled_out());
+        assert_eq!(Config::new().sample_rate(2.0).sample_rate, 1.0);
+    }
+
+    #[test]
+    fn test_watch_file() {
+        let path = std::env::temp_dir().join(format!("bugsnag_config_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"sample_rate": 0.5}"#).unwrap();
+
+        let api = Bugsnag::new("api-key", "my-dir");
+        let watcher = watch_file(&api, path.clone(), Duration::from_millis(10));
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(api.config().sample_rate, 0.5);
+
+        drop(watcher);
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,121 @@
+//! Module for printing notifications to the console during development.
+
+use serde_json::Value;
+use std::fmt::Write;
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+
+fn severity_color(severity: &str) -> &'static str {
+    match severity {
+        "error" => "\x1b[1;31m",
+        "warning" => "\x1b[1;33m",
+        _ => "\x1b[1;34m",
+    }
+}
+
+/// Checks if colors should be used for stderr.
+pub fn use_color() -> bool {
+    use std::io::IsTerminal;
+    std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+}
+
+/// Renders the events of a notification in a human readable form. Stack frames are
+/// rendered as `file:line:column`, which terminals and IDEs turn into links.
+pub fn render(json: &str, color: bool) -> String {
+    let paint = |code: &'static str| if color { code } else { "" };
+    let reset = paint(RESET);
+    let mut out = String::new();
+
+    let Ok(notification) = serde_json::from_str::<Value>(json) else {
+        return json.to_owned();
+    };
+    let events = notification["events"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    for event in events {
+        let severity = event["severity"].as_str().unwrap_or("error");
+        let exceptions = event["exceptions"].as_array().cloned().unwrap_or_default();
+
+        for (i, exception) in exceptions.iter().enumerate() {
+            let class = exception["errorClass"].as_str().unwrap_or("");
+            let message = exception["message"].as_str().unwrap_or("");
+
+            if i == 0 {
+                let _ = write!(
+                    out,
+                    "[bugsnag] {}{}{reset} ",
+                    paint(severity_color(severity)),
+                    severity.to_uppercase()
+                );
+            } else {
+                out.push_str("caused by: ");
+            }
+            let _ = writeln!(out, "{}{class}{reset}: {message}", paint(BOLD));
+
+            if i == 0 {
+                if let Some(context) = event["context"].as_str() {
+                    let _ = writeln!(out, "  context: {context}");
+                }
+            }
+
+            let frames = exception["stacktrace"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            for frame in frames {
+                let file = frame["file"].as_str().unwrap_or("");
+                let method = frame["method"].as_str().unwrap_or("unknown");
+                let mut location = file.to_owned();
+                if let Some(line) = frame["lineNumber"].as_u64() {
+                    let _ = write!(location, ":{line}");
+                    if let Some(column) = frame["columnNumber"].as_u64() {
+                        let _ = write!(location, ":{column}");
+                    }
+                }
+
+                let dim = if frame["inProject"].as_bool().unwrap_or(false) {
+                    ""
+                } else {
+                    paint(DIM)
+                };
+                let _ = writeln!(out, "{dim}    at {location} in {method}{reset}");
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let json = json!({
+            "events": [{
+                "severity": "warning",
+                "context": "main::test",
+                "exceptions": [{
+                    "errorClass": "ParseIntError",
+                    "message": "invalid digit found in string",
+                    "stacktrace": [
+                        {"file": "src/main.rs", "lineNumber": 10, "columnNumber": 5, "method": "app::test", "inProject": true},
+                        {"file": "/rustc/library/core/src/ops/function.rs", "lineNumber": 250, "method": "core::ops::function::FnOnce::call_once", "inProject": false}
+                    ]
+                }]
+            }]
+        });
+
+        assert_eq!(
+            render(&json.to_string(), false),
+            "[bugsnag] WARNING ParseIntError: invalid digit found in string\n  context: main::test\n    at src/main.rs:10:5 in app::test\n    at /rustc/library/core/src/ops/function.rs:250 in core::ops::function::FnOnce::call_once\n"
+        );
+        assert!(render(&json.to_string(), true).contains("\x1b[1;33mWARNING\x1b[0m"));
+    }
+}
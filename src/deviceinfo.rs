@@ -1,44 +1,120 @@
+use chrono::{DateTime, Utc};
 use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The version of the compiler the binary was built with.
+const RUSTC_VERSION: &str = env!("BUGSNAG_BUILD_RUSTC");
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DeviceInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os_name: Option<String>,
     os_version: String,
     hostname: String,
+    /// The total memory in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_memory: Option<u64>,
+    /// The available memory in bytes when the event occurred.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    free_memory: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_count: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cpu_abi: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    runtime_versions: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<DateTime<Utc>>,
 }
 
 impl DeviceInfo {
     pub fn new(version: &str, name: &str) -> DeviceInfo {
         DeviceInfo {
+            os_name: None,
             os_version: version.to_owned(),
             hostname: name.to_owned(),
+            total_memory: None,
+            free_memory: None,
+            cpu_count: None,
+            cpu_abi: Vec::new(),
+            runtime_versions: BTreeMap::new(),
+            time: None,
         }
     }
 
+    /// Creates the info of this device. The `BUGSNAG_HOSTNAME` and `BUGSNAG_OS_VERSION`
+    /// environment variables override the detected values, e.g. to report the node name
+    /// instead of the hostname of a container.
     pub fn generate() -> DeviceInfo {
-        let mut version = sys_info::os_type().unwrap_or("Unknown".to_owned());
-        version.push(':');
-        version.push_str(&sys_info::os_release().unwrap_or("u.k.n.o.w.n".to_owned()));
+        DeviceInfo::generate_with_env(|name| std::env::var(name).ok())
+    }
+
+    fn generate_with_env(var: impl Fn(&str) -> Option<String>) -> DeviceInfo {
+        let var = |name| var(name).filter(|value: &String| !value.is_empty());
+
+        let version = var("BUGSNAG_OS_VERSION")
+            .unwrap_or_else(|| sys_info::os_release().unwrap_or("u.k.n.o.w.n".to_owned()));
 
-        let hostname = sys_info::hostname().unwrap_or("UnknownHost".to_owned());
+        let hostname = var("BUGSNAG_HOSTNAME")
+            .unwrap_or_else(|| sys_info::hostname().unwrap_or("UnknownHost".to_owned()));
 
-        DeviceInfo::new(version.as_str(), hostname.as_str())
+        let mut info = DeviceInfo::new(version.as_str(), hostname.as_str());
+        info.os_name = Some(sys_info::os_type().unwrap_or("Unknown".to_owned()));
+        info.total_memory = sys_info::mem_info().ok().map(|mem| mem.total * 1024);
+        info.free_memory = free_memory();
+        info.cpu_count = sys_info::cpu_num().ok();
+        info.cpu_abi = vec![std::env::consts::ARCH.to_owned()];
+        info.runtime_versions
+            .insert("rustc".to_owned(), RUSTC_VERSION.to_owned());
+        info
     }
 
     pub fn set_os_version(&mut self, version: &str) {
         version.clone_into(&mut self.os_version)
     }
 
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
     pub fn set_hostname(&mut self, name: &str) {
         name.clone_into(&mut self.hostname)
     }
+
+    /// Sets the time at which the event occurred.
+    pub fn set_time(&mut self, time: DateTime<Utc>) {
+        self.time = Some(time);
+    }
+
+    /// Updates the available memory, if it was detected by `generate`.
+    pub fn refresh_free_memory(&mut self) {
+        if self.free_memory.is_some() {
+            self.free_memory = free_memory().or(self.free_memory);
+        }
+    }
+
+    /// Adds the version of a runtime the app uses, e.g. of an embedded interpreter.
+    pub fn add_runtime_version(&mut self, runtime: &str, version: &str) {
+        self.runtime_versions
+            .insert(runtime.to_owned(), version.to_owned());
+    }
+}
+
+/// Returns the available memory in bytes. Memory that can be reclaimed from caches is
+/// counted as available where the platform reports it.
+fn free_memory() -> Option<u64> {
+    let mem = sys_info::mem_info().ok()?;
+    let free = if mem.avail > 0 { mem.avail } else { mem.free };
+    Some(free * 1024)
 }
 
 #[cfg(test)]
 mod tests {
     use serde_json::json;
 
-    use super::DeviceInfo;
+    use super::{DeviceInfo, RUSTC_VERSION};
+    use chrono::{TimeZone, Utc};
 
     #[test]
     fn test_deviceinfo_to_json() {
@@ -59,11 +135,52 @@ mod tests {
         info.set_hostname("testmachine3");
         info.set_os_version("3.0.0");
 
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["osVersion"], "3.0.0");
+        assert_eq!(json["hostname"], "testmachine3");
+    }
+
+    #[test]
+    fn test_deviceinfo_generate() {
+        let mut info = DeviceInfo::generate();
+        info.add_runtime_version("lua", "5.4.6");
+        info.refresh_free_memory();
+        let json = serde_json::to_value(&info).unwrap();
+
+        assert!(json["osName"].is_string());
+        assert!(json["totalMemory"].as_u64().unwrap() >= json["freeMemory"].as_u64().unwrap());
+        assert!(json["cpuCount"].as_u64().unwrap() >= 1);
+        assert_eq!(json["cpuAbi"], json!([std::env::consts::ARCH]));
+        assert_eq!(
+            json["runtimeVersions"],
+            json!({"lua": "5.4.6", "rustc": RUSTC_VERSION})
+        );
+        assert_ne!(RUSTC_VERSION, "unknown");
+    }
+
+    #[test]
+    fn test_deviceinfo_env_overrides() {
+        let info = DeviceInfo::generate_with_env(|name| match name {
+            "BUGSNAG_HOSTNAME" => Some("node-7".to_owned()),
+            "BUGSNAG_OS_VERSION" => Some(String::new()),
+            _ => None,
+        });
+
+        assert_eq!(info.hostname(), "node-7");
+        assert_ne!(info.os_version, "");
+    }
+
+    #[test]
+    fn test_deviceinfo_with_time_to_json() {
+        let mut info = DeviceInfo::new("1.0.0", "testmachine");
+        info.set_time(Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap());
+
         assert_eq!(
             serde_json::to_value(&info).unwrap(),
             json!({
-                "osVersion": "3.0.0",
-                "hostname": "testmachine3"
+                "osVersion": "1.0.0",
+                "hostname": "testmachine",
+                "time": "2024-05-01T12:30:00Z"
             })
         );
     }
@@ -0,0 +1,169 @@
+//! Module for the diagnostics report of `Bugsnag::diagnostics`.
+//!
+//! The report answers the usual questions when notifications "don't show up": which
+//! features are compiled in, whether notifications are enabled, whether the endpoint can
+//! be reached, and whether notifications pile up in the offline storage, the delivery
+//! queue or the rate limit. Its `Display` output is pretty printed json, which can be
+//! attached to a support request. The API key is masked.
+//!
+//! # Example
+//!
+//! ```no_run
+//! let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! println!("{}", api.diagnostics());
+//! ```
+
+use super::config::Config;
+use serde::Serialize;
+use std::fmt;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long connecting to the endpoint may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The features of this crate that are compiled in.
+const FEATURES: &[(&str, bool)] = &[
+    ("anyhow", cfg!(feature = "anyhow")),
+    ("clap", cfg!(feature = "clap")),
+    ("dependency-manifest", cfg!(feature = "dependency-manifest")),
+    ("http", cfg!(feature = "http")),
+    ("journald", cfg!(feature = "journald")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("testing", cfg!(feature = "testing")),
+    ("tokio", cfg!(feature = "tokio")),
+    ("tracing", cfg!(feature = "tracing")),
+    ("windows-eventlog", cfg!(feature = "windows-eventlog")),
+];
+
+/// Structured report of the effective configuration and delivery state.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub version: &'static str,
+    pub features: Vec<&'static str>,
+    /// The API key with all but the last 4 characters masked.
+    pub api_key: String,
+    pub config: Config,
+    pub notify_endpoint: String,
+    pub sessions_endpoint: String,
+    /// The error of connecting to the notify endpoint, or `None` if a TCP connection
+    /// could be established.
+    pub endpoint_error: Option<String>,
+    pub offline_storage: Option<String>,
+    /// The number of reports in the offline storage, or `None` if it could not be read.
+    pub offline_backlog: Option<usize>,
+    /// The number of notifications waiting for background delivery, or `None` without
+    /// background delivery.
+    pub queue_depth: Option<usize>,
+    pub rate_limits: Vec<RateLimitUsage>,
+    pub console_output: bool,
+    pub capture_callback: bool,
+    pub plugins: Vec<String>,
+    pub transport: String,
+}
+
+/// The usage of a limit of the rate limit.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitUsage {
+    pub window: Duration,
+    pub limit: u32,
+    /// The number of notifications send within the window.
+    pub sent: u32,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = serde_json::to_string_pretty(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+pub(crate) fn features() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+pub(crate) fn mask_api_key(api_key: &str) -> String {
+    let visible = api_key.len().saturating_sub(4);
+    api_key
+        .char_indices()
+        .map(|(i, c)| if i < visible { '*' } else { c })
+        .collect()
+}
+
+/// Returns the host and port of an http or https URL.
+fn endpoint_address(endpoint: &str) -> Option<(&str, u16)> {
+    let (scheme, rest) = endpoint.split_once("://")?;
+    let default_port = match scheme {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, default_port),
+    };
+    Some((host.trim_start_matches('[').trim_end_matches(']'), port))
+}
+
+/// Tries to open a TCP connection to the endpoint. Returns the error if that fails.
+pub(crate) fn check_endpoint(endpoint: &str) -> Option<String> {
+    let Some((host, port)) = endpoint_address(endpoint) else {
+        return Some(format!("invalid endpoint URL {endpoint}"));
+    };
+    let addrs = match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => return Some(format!("failed to resolve {host}: {e}")),
+    };
+
+    let mut error = format!("{host} did not resolve to any address");
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(_) => return None,
+            Err(e) => error = format!("failed to connect to {addr}: {e}"),
+        }
+    }
+    Some(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_endpoint, endpoint_address, mask_api_key};
+
+    #[test]
+    fn test_endpoint_address() {
+        assert_eq!(
+            endpoint_address("https://notify.bugsnag.com"),
+            Some(("notify.bugsnag.com", 443))
+        );
+        assert_eq!(
+            endpoint_address("http://user@127.0.0.1:9000/notify?x=1"),
+            Some(("127.0.0.1", 9000))
+        );
+        assert_eq!(endpoint_address("http://[::1]/"), Some(("::1", 80)));
+        assert_eq!(endpoint_address("ftp://example.com"), None);
+    }
+
+    #[test]
+    fn test_check_endpoint() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+        assert_eq!(check_endpoint(&endpoint), None);
+        assert!(check_endpoint("notify.bugsnag.com").is_some());
+    }
+
+    #[test]
+    fn test_mask_api_key() {
+        assert_eq!(mask_api_key("0123456789abcdef"), "************cdef");
+        assert_eq!(mask_api_key("abc"), "abc");
+    }
+}
@@ -0,0 +1,58 @@
+//! Module for the error classes set with `Bugsnag::set_discard_classes`.
+
+use regex::Regex;
+
+/// An error class that is discarded.
+#[derive(Debug, Clone)]
+enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+/// The error classes of notifications that are never sent.
+#[derive(Debug, Clone, Default)]
+pub struct DiscardClasses(Vec<Pattern>);
+
+impl DiscardClasses {
+    /// Parses the patterns. A pattern enclosed in slashes, e.g. `/^Hyper::/`, is a regular
+    /// expression that matches anywhere in the error class unless it is anchored, any
+    /// other pattern matches the error class exactly.
+    pub fn parse(patterns: &[&str]) -> Result<DiscardClasses, regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| match pattern.strip_prefix('/') {
+                Some(rest) if rest.ends_with('/') => {
+                    Regex::new(&rest[..rest.len() - 1]).map(Pattern::Regex)
+                }
+                _ => Ok(Pattern::Exact((*pattern).to_owned())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(DiscardClasses(patterns))
+    }
+
+    pub fn matches(&self, error_class: &str) -> bool {
+        self.0.iter().any(|pattern| match pattern {
+            Pattern::Exact(class) => class == error_class,
+            Pattern::Regex(regex) => regex.is_match(error_class),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiscardClasses;
+
+    #[test]
+    fn test_discard_classes() {
+        let classes = DiscardClasses::parse(&["Io::BrokenPipe", "/^Hyper::/", "/"]).unwrap();
+
+        assert!(classes.matches("Io::BrokenPipe"));
+        assert!(!classes.matches("Io::BrokenPipeline"));
+        assert!(classes.matches("Hyper::IncompleteMessage"));
+        assert!(!classes.matches("Reqwest::Hyper::Timeout"));
+        assert!(classes.matches("/"));
+        assert!(!DiscardClasses::default().matches("Error"));
+
+        assert!(DiscardClasses::parse(&["/(/"]).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Module for the format of reports in the offline storage.
+//!
+//! A report is stored as envelope: a first line identifying the format, the HTTP headers
+//! needed to deliver the report, an empty line and the notification. This way a separate
+//! uploader process can transmit the reports without knowing the configuration of the
+//! process that wrote them. Reports written by older versions only contain the
+//! notification.
+
+use crate::clock;
+use log::warn;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::DirEntry;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &str = "BUGSNAG-ENVELOPE/1";
+/// Prefix of the file names of reports in the offline storage.
+pub const REPORT_PREFIX: &str = "bugsnag_report";
+
+/// Wraps a notification with the headers to deliver it.
+pub fn wrap(headers: &[(&str, &str)], json: &str) -> String {
+    let mut envelope = format!("{MAGIC}\n");
+    for (name, value) in headers {
+        envelope.push_str(&format!("{name}: {value}\n"));
+    }
+    envelope.push('\n');
+    envelope.push_str(json);
+    envelope
+}
+
+/// Splits a stored report into its headers and the notification.
+pub fn split(report: &str) -> (Vec<(&str, &str)>, &str) {
+    let Some(rest) = report
+        .strip_prefix(MAGIC)
+        .and_then(|r| r.strip_prefix('\n'))
+    else {
+        return (Vec::new(), report);
+    };
+    let (head, json) = match rest.strip_prefix('\n') {
+        Some(json) => ("", json),
+        None => rest.split_once("\n\n").unwrap_or(("", rest)),
+    };
+
+    let headers = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    (headers, json)
+}
+
+/// Adds the `defaults` to the headers of a stored report that it was stored without, e.g.
+/// by an older version.
+pub fn complete_headers<'h>(
+    mut headers: Vec<(&'h str, &'h str)>,
+    defaults: &[(&'h str, &'h str)],
+) -> Vec<(&'h str, &'h str)> {
+    for (name, value) in defaults {
+        if !headers.iter().any(|(stored, _)| stored == name) {
+            headers.push((name, value));
+        }
+    }
+    headers
+}
+
+/// Returns the reports stored in `dir`.
+pub fn stored_reports(dir: &Path) -> std::io::Result<Vec<DirEntry>> {
+    Ok(std::fs::read_dir(dir)?
+        .flatten()
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|s| s.starts_with(REPORT_PREFIX))
+        })
+        .collect())
+}
+
+/// A report of the offline storage, together with the files of its exact duplicates.
+#[derive(Debug)]
+pub struct StoredReport {
+    report: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl StoredReport {
+    /// Splits the report into its headers and the notification. If the report was stored
+    /// several times, the number of copies is added to the `offline` tab of each event.
+    /// Events that were recorded since the current boot get the time of their occurrence
+    /// corrected from their monotonic clock reading, see [`correct_occurred`].
+    pub fn split(&self) -> (Vec<(&str, &str)>, Cow<'_, str>) {
+        let (headers, json) = split(&self.report);
+
+        let Ok(mut notification) = serde_json::from_str::<Value>(json) else {
+            return (headers, Cow::Borrowed(json));
+        };
+        let mut changed = false;
+        if let Some(events) = notification["events"].as_array_mut() {
+            for event in events.iter_mut().filter(|e| e.is_object()) {
+                changed |= correct_occurred(event);
+                if self.paths.len() < 2 {
+                    continue;
+                }
+                let metadata = &mut event["metaData"];
+                if !metadata.is_object() {
+                    *metadata = json!({});
+                }
+                metadata["offline"] = json!({ "occurrences": self.paths.len() });
+                changed = true;
+            }
+        }
+        if !changed {
+            return (headers, Cow::Borrowed(json));
+        }
+        (headers, Cow::Owned(notification.to_string()))
+    }
+}
+
+/// Corrects the time of an event by the monotonic clock reading in its internal tab, for
+/// devices whose real time clock was wrong when the event occurred. This only works while
+/// the device was not rebooted since, so the reading and the current one share a start.
+/// The recorded time is kept as `recordedOccurred`. Returns if the event was changed.
+fn correct_occurred(event: &mut Value) -> bool {
+    let Some((name, occurred)) = event["metaData"].as_object().and_then(|metadata| {
+        metadata.iter().find_map(|(name, tab)| {
+            let since = match tab.get("monotonicSince")?.as_str()? {
+                "boot" => "boot",
+                _ => return None,
+            };
+            let millis = tab.get("monotonicMs")?.as_u64()?;
+            let boot = tab.get("bootId")?.as_str()?;
+            if tab.get("recordedOccurred").is_some() {
+                return None;
+            }
+            let occurred = clock::wall_time(clock::Reading { millis, since }, boot)?;
+            Some((name.clone(), occurred.to_rfc3339()))
+        })
+    }) else {
+        return false;
+    };
+
+    let tab = &mut event["metaData"][&name];
+    tab["recordedOccurred"] = tab["occurred"].take();
+    tab["occurred"] = json!(occurred);
+    if event["device"].is_object() {
+        event["device"]["time"] = json!(occurred);
+    }
+    true
+}
+
+/// Reads the reports stored in `dir`. Reports that are stored several times, e.g. by a
+/// crash loop, are identified by the SHA-256 digest of their content and returned once.
+/// Reports that can not be read are skipped, so they do not hold up the others.
+pub fn read_stored_reports(dir: &Path) -> std::io::Result<Vec<StoredReport>> {
+    let mut reports: Vec<StoredReport> = Vec::new();
+    let mut digests: HashMap<_, usize> = HashMap::new();
+
+    for entry in stored_reports(dir)? {
+        let report = match std::fs::read_to_string(entry.path()) {
+            Ok(report) => report,
+            Err(e) => {
+                warn!("skipping unreadable report {}: {e}", entry.path().display());
+                continue;
+            }
+        };
+        let digest = Sha256::digest(report.as_bytes());
+
+        match digests.get(&digest) {
+            Some(&index) => reports[index].paths.push(entry.path()),
+            None => {
+                digests.insert(digest, reports.len());
+                reports.push(StoredReport {
+                    report,
+                    paths: vec![entry.path()],
+                });
+            }
+        }
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete_headers, read_stored_reports, split, wrap, REPORT_PREFIX};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_envelope() {
+        let json = "{\"events\":[]}";
+        let report = wrap(
+            &[("Bugsnag-Api-Key", "key"), ("Bugsnag-Payload-Version", "5")],
+            json,
+        );
+        assert_eq!(
+            split(&report),
+            (
+                vec![("Bugsnag-Api-Key", "key"), ("Bugsnag-Payload-Version", "5")],
+                json
+            )
+        );
+        assert_eq!(split(&wrap(&[], json)), (vec![], json));
+        assert_eq!(split(json), (vec![], json));
+    }
+
+    #[test]
+    fn test_complete_headers() {
+        let defaults = [("Bugsnag-Api-Key", "own"), ("Bugsnag-Payload-Version", "5")];
+        assert_eq!(
+            complete_headers(vec![("Bugsnag-Api-Key", "stored")], &defaults),
+            vec![
+                ("Bugsnag-Api-Key", "stored"),
+                ("Bugsnag-Payload-Version", "5")
+            ]
+        );
+        assert_eq!(complete_headers(vec![], &defaults), defaults.to_vec());
+    }
+
+    #[test]
+    fn test_read_stored_reports() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_dedup_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let crash = wrap(
+            &[("Bugsnag-Api-Key", "key")],
+            r#"{"events":[{"metaData":{}}]}"#,
+        );
+        for i in 0..3 {
+            std::fs::write(dir.join(format!("{REPORT_PREFIX}_{i}")), &crash).unwrap();
+        }
+        std::fs::write(dir.join(format!("{REPORT_PREFIX}_3")), r#"{"events":[{}]}"#).unwrap();
+        std::fs::write(dir.join(format!("{REPORT_PREFIX}_4")), [0xff, 0xfe]).unwrap();
+
+        let mut reports = read_stored_reports(&dir).unwrap();
+        reports.sort_by_key(|r| r.paths.len());
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].split().1, r#"{"events":[{}]}"#);
+
+        let (headers, json) = reports[1].split();
+        assert_eq!(headers, vec![("Bugsnag-Api-Key", "key")]);
+        let json: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["events"][0]["metaData"]["offline"]["occurrences"], 3);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_correct_occurred() {
+        let reading = crate::clock::now();
+        let tab = json!({
+            "occurred": "1970-01-01T00:00:00+00:00",
+            "monotonicMs": reading.millis - 60_000,
+            "monotonicSince": reading.since,
+            "bootId": crate::clock::boot_id(),
+        });
+        let mut event = json!({
+            "device": { "time": "1970-01-01T00:00:00+00:00" },
+            "metaData": { "bugsnag-rs": tab },
+        });
+
+        assert!(super::correct_occurred(&mut event));
+        let tab = &event["metaData"]["bugsnag-rs"];
+        assert_eq!(tab["recordedOccurred"], "1970-01-01T00:00:00+00:00");
+        assert_eq!(event["device"]["time"], tab["occurred"]);
+        let occurred: chrono::DateTime<chrono::Utc> =
+            serde_json::from_value(tab["occurred"].clone()).unwrap();
+        let ago = chrono::Utc::now() - occurred;
+        assert!((59_000..61_000).contains(&ago.num_milliseconds()));
+        // a report is only corrected once
+        assert!(!super::correct_occurred(&mut event));
+
+        let mut other_boot = json!({
+            "metaData": { "bugsnag-rs": {
+                "monotonicMs": 0,
+                "monotonicSince": "boot",
+                "bootId": "other boot",
+            }},
+        });
+        assert!(!super::correct_occurred(&mut other_boot));
+    }
+}
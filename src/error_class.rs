@@ -0,0 +1,147 @@
+//! Module for error classes following the `Namespace::Kind` convention.
+//!
+//! # Example
+//!
+//! ```
+//! use bugsnag::ErrorClass;
+//!
+//! const PAYMENT_DECLINED: ErrorClass = ErrorClass::from_static("Payment::Declined");
+//!
+//! let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! api.notify(PAYMENT_DECLINED, "card expired");
+//! ```
+
+use super::Error;
+use std::borrow::Cow;
+use std::fmt;
+
+/// The name of an error class, made of at least two `::` separated segments, e.g.
+/// `Io::NotFound` or `Billing::Invoice::Overdue`. Segments start with an ASCII letter
+/// and contain only ASCII letters, digits and underscores.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorClass(Cow<'static, str>);
+
+/// Checks the `Namespace::Kind` convention. A `const fn`, so constants are checked when
+/// they are compiled.
+const fn is_valid(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let mut segments = 1;
+    let mut segment_start = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b':' {
+            if segment_start || i + 1 >= bytes.len() || bytes[i + 1] != b':' {
+                return false;
+            }
+            segments += 1;
+            segment_start = true;
+            i += 2;
+            continue;
+        }
+        if segment_start && !b.is_ascii_alphabetic() {
+            return false;
+        }
+        if !b.is_ascii_alphanumeric() && b != b'_' {
+            return false;
+        }
+        segment_start = false;
+        i += 1;
+    }
+
+    segments >= 2 && !segment_start
+}
+
+impl ErrorClass {
+    /// Creates an error class from a string literal. Panics if `name` does not follow
+    /// the convention, which fails the build when used in a constant.
+    pub const fn from_static(name: &'static str) -> ErrorClass {
+        if !is_valid(name) {
+            panic!("error classes must follow the `Namespace::Kind` convention");
+        }
+        ErrorClass(Cow::Borrowed(name))
+    }
+
+    /// Creates the error class `namespace::kind`.
+    pub fn new(namespace: &str, kind: &str) -> Result<ErrorClass, Error> {
+        ErrorClass::parse(&format!("{namespace}::{kind}"))
+    }
+
+    /// Checks that `name` follows the convention and creates an error class from it.
+    pub fn parse(name: &str) -> Result<ErrorClass, Error> {
+        if is_valid(name) {
+            Ok(ErrorClass(Cow::Owned(name.to_owned())))
+        } else {
+            Err(Error::InvalidErrorClass)
+        }
+    }
+
+    /// Creates a class in the namespace of this class, e.g. `Io::NotFound` from `Io`.
+    pub fn child(&self, kind: &str) -> Result<ErrorClass, Error> {
+        ErrorClass::new(&self.0, kind)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the first segment of the class.
+    pub fn namespace(&self) -> &str {
+        self.0.split("::").next().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<ErrorClass> for Cow<'static, str> {
+    fn from(class: ErrorClass) -> Cow<'static, str> {
+        class.0
+    }
+}
+
+impl<'a> From<&'a ErrorClass> for Cow<'a, str> {
+    fn from(class: &'a ErrorClass) -> Cow<'a, str> {
+        Cow::Borrowed(class.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorClass;
+    use crate::Error;
+
+    const NOT_FOUND: ErrorClass = ErrorClass::from_static("Io::NotFound");
+
+    #[test]
+    fn test_error_class() {
+        assert_eq!(NOT_FOUND.as_str(), "Io::NotFound");
+        assert_eq!(NOT_FOUND.namespace(), "Io");
+        assert_eq!(
+            ErrorClass::new("Billing", "Invoice_2").unwrap().to_string(),
+            "Billing::Invoice_2"
+        );
+        assert!(ErrorClass::parse("Billing::Invoice::Overdue").is_ok());
+
+        for invalid in [
+            "Io",
+            "Io::",
+            "::Io",
+            "Io:NotFound",
+            "Io::Not Found",
+            "1o::X",
+        ] {
+            assert_eq!(ErrorClass::parse(invalid), Err(Error::InvalidErrorClass));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_static_invalid() {
+        ErrorClass::from_static("not a class");
+    }
+}
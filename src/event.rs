@@ -1,6 +1,8 @@
 use super::appinfo::AppInfo;
+use super::breadcrumbs::Breadcrumb;
 use super::deviceinfo::DeviceInfo;
 use super::exception::Exception;
+use super::threads::Thread;
 use super::user::User;
 use super::Severity;
 use serde::Serialize;
@@ -22,6 +24,29 @@ pub struct Event<'a> {
     meta_data: &'a Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     grouping_hash: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[Breadcrumb]>::is_empty")]
+    breadcrumbs: &'a [Breadcrumb],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unhandled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity_reason: Option<SeverityReason<'a>>,
+    #[serde(skip_serializing_if = "<[Thread]>::is_empty")]
+    threads: &'a [Thread],
+}
+
+/// Why an event is counted as handled or unhandled.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeverityReason<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    unhandled_overridden: bool,
+    attributes: SeverityReasonAttributes<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct SeverityReasonAttributes<'a> {
+    reason: &'a str,
 }
 
 impl<'a> Event<'a> {
@@ -45,8 +70,45 @@ impl<'a> Event<'a> {
             user,
             meta_data,
             grouping_hash,
+            breadcrumbs: &[],
+            unhandled: None,
+            severity_reason: None,
+            threads: &[],
         }
     }
+
+    /// Overrides whether the event counts as unhandled, e.g. for the stability score.
+    /// `originally_unhandled` tells if the event was unhandled without the override.
+    pub fn with_unhandled_override(
+        mut self,
+        unhandled: bool,
+        originally_unhandled: bool,
+        reason: &'a str,
+    ) -> Event<'a> {
+        self.unhandled = Some(unhandled);
+        self.severity_reason = Some(SeverityReason {
+            kind: if originally_unhandled {
+                "unhandledException"
+            } else {
+                "handledException"
+            },
+            unhandled_overridden: unhandled != originally_unhandled,
+            attributes: SeverityReasonAttributes { reason },
+        });
+        self
+    }
+
+    /// Sets the breadcrumbs that led up to the event.
+    pub fn with_breadcrumbs(mut self, breadcrumbs: &'a [Breadcrumb]) -> Event<'a> {
+        self.breadcrumbs = breadcrumbs;
+        self
+    }
+
+    /// Sets the threads of the process when the event occurred.
+    pub fn with_threads(mut self, threads: &'a [Thread]) -> Event<'a> {
+        self.threads = threads;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,104 @@
+//! Module for writing to the Windows Event Log while mirroring the entries as breadcrumbs.
+//!
+//! Windows services usually have no console, so their Event Log entries are the closest
+//! thing to a log. Writing through `EventLog` records each entry as a `Log` breadcrumb, so
+//! the entries that led up to an error are attached to its report.
+//!
+//! # Example
+//!
+//! ```no_run
+//! let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! let event_log = bugsnag::eventlog::EventLog::register(&api, "MyService").unwrap();
+//!
+//! event_log.write(bugsnag::Severity::Info, "service started").unwrap();
+//! ```
+
+use super::breadcrumbs::BreadcrumbType;
+use super::{Bugsnag, Severity};
+use serde_json::json;
+use std::io;
+use std::iter::once;
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+};
+
+/// An event source of the Windows Event Log.
+pub struct EventLog {
+    handle: HANDLE,
+    source: String,
+    bugsnag: Bugsnag,
+}
+
+// the handle returned by RegisterEventSourceW can be used from any thread
+unsafe impl Send for EventLog {}
+unsafe impl Sync for EventLog {}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(once(0)).collect()
+}
+
+impl EventLog {
+    /// Registers the event source `source` on the local machine. Entries written to it are
+    /// recorded as breadcrumbs of `bugsnag` and all of its clones.
+    pub fn register(bugsnag: &Bugsnag, source: &str) -> io::Result<EventLog> {
+        let wide_source = to_wide(source);
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), wide_source.as_ptr()) };
+
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(EventLog {
+            handle,
+            source: source.to_owned(),
+            bugsnag: bugsnag.clone(),
+        })
+    }
+
+    /// Writes an entry to the Event Log and records it as breadcrumb.
+    pub fn write(&self, severity: Severity, message: &str) -> io::Result<()> {
+        let (event_type, level) = match severity {
+            Severity::Error => (EVENTLOG_ERROR_TYPE, "error"),
+            Severity::Warning => (EVENTLOG_WARNING_TYPE, "warning"),
+            Severity::Info => (EVENTLOG_INFORMATION_TYPE, "information"),
+        };
+
+        self.bugsnag.leave_breadcrumb(
+            message,
+            BreadcrumbType::Log,
+            Some(json!({"source": self.source, "level": level})),
+        );
+
+        let wide_message = to_wide(message);
+        let strings = [wide_message.as_ptr()];
+        let res = unsafe {
+            ReportEventW(
+                self.handle,
+                event_type,
+                0,
+                0,
+                std::ptr::null_mut(),
+                strings.len() as u16,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        unsafe {
+            DeregisterEventSource(self.handle);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Module for hashes that are stable across builds and Rust releases.
+
+/// FNV-1a hasher, as the hashers of std are not guaranteed to be stable across releases.
+#[derive(Debug, Clone)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StableHasher {
+    /// Adds a value to the hash. Each value is terminated, so `"ab", "c"` and `"a", "bc"`
+    /// result in different hashes.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes.iter().chain(&[0]) {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Returns the hash as 16 hex digits.
+    pub fn finish_hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StableHasher;
+
+    #[test]
+    fn test_stable_hasher() {
+        let mut hasher = StableHasher::default();
+        assert_eq!(hasher.finish_hex(), "cbf29ce484222325");
+
+        hasher.write(b"ab");
+        hasher.write(b"c");
+        let mut other = StableHasher::default();
+        other.write(b"a");
+        other.write(b"bc");
+        assert_ne!(hasher.finish_hex(), other.finish_hex());
+    }
+}
@@ -0,0 +1,25 @@
+//! Module for reporting the health of the notification delivery through the `metrics`
+//! crate. Without the `metrics` feature, the functions do nothing.
+
+use std::time::Duration;
+
+/// Counts a notification that was delivered to Bugsnag.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub fn event_sent() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("bugsnag_events_sent").increment(1);
+}
+
+/// Records the number of notifications waiting in the delivery queue.
+pub fn queue_depth(_depth: usize) {
+    #[cfg(feature = "metrics")]
+    ::metrics::gauge!("bugsnag_queue_depth").set(_depth as f64);
+}
+
+/// Records how long a delivery attempt took, labeled with whether it succeeded.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub fn delivery_latency(_latency: Duration, _success: bool) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!("bugsnag_delivery_latency_seconds", "success" => _success.to_string())
+        .record(_latency.as_secs_f64());
+}
@@ -0,0 +1,128 @@
+//! Module for writing to the systemd journal while mirroring the entries as breadcrumbs.
+//!
+//! Daemons usually log to the journal instead of stdout. Writing through `Journal` records
+//! each entry as a `Log` breadcrumb, so the entries that led up to an error are attached
+//! to its report.
+//!
+//! # Example
+//!
+//! ```no_run
+//! let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! let journal = bugsnag::journald::Journal::connect(&api, "my-daemon").unwrap();
+//!
+//! journal.write(bugsnag::Severity::Info, "daemon started").unwrap();
+//! ```
+
+use super::breadcrumbs::BreadcrumbType;
+use super::{Bugsnag, Severity};
+use serde_json::json;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// A connection to the systemd journal.
+pub struct Journal {
+    socket: UnixDatagram,
+    identifier: String,
+    bugsnag: Bugsnag,
+}
+
+/// Appends a field in the native journal protocol. Values containing a newline
+/// are written with an explicit length.
+fn append_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+impl Journal {
+    /// Connects to the journal of the system. Entries are written with `identifier` as
+    /// `SYSLOG_IDENTIFIER` and recorded as breadcrumbs of `bugsnag` and all of its clones.
+    pub fn connect(bugsnag: &Bugsnag, identifier: &str) -> io::Result<Journal> {
+        Journal::connect_to(bugsnag, identifier, Path::new(JOURNAL_SOCKET))
+    }
+
+    fn connect_to(bugsnag: &Bugsnag, identifier: &str, socket: &Path) -> io::Result<Journal> {
+        let sock = UnixDatagram::unbound()?;
+        sock.connect(socket)?;
+
+        Ok(Journal {
+            socket: sock,
+            identifier: identifier.to_owned(),
+            bugsnag: bugsnag.clone(),
+        })
+    }
+
+    /// Writes an entry to the journal and records it as breadcrumb.
+    pub fn write(&self, severity: Severity, message: &str) -> io::Result<()> {
+        let (priority, level) = match severity {
+            Severity::Error => ("3", "error"),
+            Severity::Warning => ("4", "warning"),
+            Severity::Info => ("6", "info"),
+        };
+
+        self.bugsnag.leave_breadcrumb(
+            message,
+            BreadcrumbType::Log,
+            Some(json!({"identifier": self.identifier, "level": level})),
+        );
+
+        let mut buf = Vec::new();
+        append_field(&mut buf, "PRIORITY", priority);
+        append_field(&mut buf, "SYSLOG_IDENTIFIER", &self.identifier);
+        append_field(&mut buf, "MESSAGE", message);
+
+        self.socket.send(&buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_field, Journal};
+    use crate::{Bugsnag, Severity};
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_append_field() {
+        let mut buf = Vec::new();
+        append_field(&mut buf, "MESSAGE", "hello");
+        assert_eq!(buf, b"MESSAGE=hello\n");
+
+        let mut buf = Vec::new();
+        append_field(&mut buf, "MESSAGE", "a\nb");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_write_to_journal() {
+        let path = std::env::temp_dir().join(format!("bugsnag_journal_{}", uuid::Uuid::new_v4()));
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let api = Bugsnag::new("api-key", "my-dir");
+        let journal = Journal::connect_to(&api, "test-daemon", &path).unwrap();
+        journal
+            .write(Severity::Warning, "disk almost full")
+            .unwrap();
+
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"PRIORITY=4\nSYSLOG_IDENTIFIER=test-daemon\nMESSAGE=disk almost full\n"
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -16,16 +16,149 @@
 //!       .severity(bugsnag::Severity::Info);
 //! ```
 //!
+//! `Bugsnag::start` creates an instance with the defaults of the official Bugsnag SDKs
+//! instead, e.g. a panic hook, background delivery and an offline storage.
+//!
 //! For more examples on how to integrate bugsnag into a project, the examples
 //! folder provides some reference implementations.
+//!
+//! # Capture-only builds
+//!
+//! Without the default `http` feature the HTTP client is not compiled in. Notifications
+//! are then only handed to the callback set with `Bugsnag::set_capture_callback` or
+//! written to the offline storage, for environments where another process uploads the
+//! reports later.
+//!
+//! # Debugging the delivery
+//!
+//! If a notification never reaches the dashboard, set the `BUGSNAG_DEBUG` environment
+//! variable, e.g. to `1`. Every decision on the notify path, e.g. that the notification
+//! was sampled, rate limited, queued or stored offline, is then logged through the `log`
+//! crate with the target `bugsnag::trace` and the id of the event.
+//!
+//! # Async delivery
+//!
+//! With the `tokio` feature, `NotifyBuilder::send_async` delivers notifications with the
+//! async HTTP client, so async services do not block their runtime while reporting.
 
+mod bounded;
+mod budget;
 mod bugsnag_impl;
+#[cfg(feature = "dependency-manifest")]
+mod buildenv;
+mod buildid;
+mod clock;
+mod coalesce;
+mod console;
+mod discard;
+mod envelope;
+mod error_class;
 mod event;
 mod exception;
+mod hashing;
+mod instrumentation;
 mod notification;
+mod pseudonym;
+mod queue;
+#[cfg(feature = "http")]
+mod receipts;
+mod redact;
+mod reentrancy;
+mod rustinfo;
 mod stacktrace;
+mod threads;
+mod trace;
+#[cfg(feature = "http")]
+mod upload;
 pub use self::bugsnag_impl::*;
-mod appinfo;
+pub use self::error_class::ErrorClass;
+pub use self::panic::ffi_guard;
+pub use self::reporter::Reporter;
+pub use self::stacktrace::{Frame, RawFrames, MAX_RAW_FRAMES};
+#[cfg(feature = "http")]
+pub use self::upload::upload_stored_reports;
+pub mod appinfo;
+pub mod breadcrumbs;
+#[cfg(feature = "dependency-manifest")]
+pub mod build;
+#[cfg(feature = "clap")]
+pub mod cli;
+pub mod config;
 mod deviceinfo;
+pub mod diagnostics;
+#[cfg(all(windows, feature = "windows-eventlog"))]
+pub mod eventlog;
+#[cfg(all(target_os = "linux", feature = "journald"))]
+pub mod journald;
+pub mod logger;
 pub mod panic;
+pub mod pending;
+pub mod plugin;
+pub mod reporter;
+#[cfg(unix)]
+pub mod signal;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+pub mod transport;
 pub mod user;
+
+/// Reports a warning instead of panicking if the condition is false, and continues.
+/// The first argument is the `Bugsnag` instance, the optional message takes the same
+/// arguments as `format!`, see `Bugsnag::notify_assertion`.
+///
+/// ```
+/// # let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+/// # api.reload_config(bugsnag::config::Config::new().enabled(false));
+/// let balance = 10;
+/// bugsnag::bugsnag_assert!(api, balance >= 0, "negative balance: {}", balance);
+/// ```
+#[macro_export]
+macro_rules! bugsnag_assert {
+    ($api:expr, $cond:expr $(,)?) => {
+        if !$cond {
+            let _ = $api.notify_assertion(stringify!($cond), "").send();
+        }
+    };
+    ($api:expr, $cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            let _ = $api
+                .notify_assertion(stringify!($cond), format!($($arg)+))
+                .send();
+        }
+    };
+}
+
+/// Panics like `debug_assert!` in debug builds, and reports a warning like
+/// `bugsnag_assert!` in release builds, where `debug_assert!` would not check anything.
+#[macro_export]
+macro_rules! bugsnag_debug_assert {
+    ($api:expr, $cond:expr $(, $($arg:tt)+)?) => {
+        if cfg!(debug_assertions) {
+            assert!($cond $(, $($arg)+)?);
+        } else {
+            $crate::bugsnag_assert!($api, $cond $(, $($arg)+)?);
+        }
+    };
+}
+
+/// Expands to the dependencies embedded by `bugsnag::build::embed_dependencies` in the
+/// build script of the package, to be passed to `Bugsnag::set_dependencies`.
+#[cfg(feature = "dependency-manifest")]
+#[macro_export]
+macro_rules! dependencies {
+    () => {
+        include_str!(env!("BUGSNAG_DEPENDENCIES"))
+    };
+}
+
+/// Expands to the build settings embedded by `bugsnag::build::embed_build_info` in the
+/// build script of the package, to be passed to `Bugsnag::set_build_info`.
+#[cfg(feature = "dependency-manifest")]
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        env!("BUGSNAG_APP_BUILD_INFO")
+    };
+}
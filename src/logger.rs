@@ -0,0 +1,151 @@
+//! Module for reporting the records of the `log` crate, e.g. of every `log::error!`.
+//!
+//! `BugsnagLogger` reports the records up to its level with `Bugsnag::notify_record` and
+//! passes all records to an optional inner logger, e.g. the one that writes to stderr.
+//! Records of the targets of this crate, `bugsnag` and `bugsnag::*`, and records logged
+//! while a record is reported, e.g. about a failed delivery, are only passed on.
+//!
+//! # Example
+//!
+//! ```no_run
+//! let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! bugsnag::logger::BugsnagLogger::new(&api)
+//!     .level(log::LevelFilter::Warn)
+//!     .init()
+//!     .unwrap();
+//!
+//! log::error!("payment failed");
+//! ```
+
+use super::reentrancy::{self, Limiter, ReportingGuard};
+use super::Bugsnag;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::time::Duration;
+
+/// Logger that reports records to Bugsnag.
+pub struct BugsnagLogger {
+    bugsnag: Bugsnag,
+    level: LevelFilter,
+    limiter: Limiter,
+    inner: Option<Box<dyn Log>>,
+}
+
+impl BugsnagLogger {
+    /// Creates a logger that reports errors and warnings with a clone of `bugsnag`, at
+    /// most 10 per minute.
+    pub fn new(bugsnag: &Bugsnag) -> BugsnagLogger {
+        BugsnagLogger {
+            bugsnag: bugsnag.clone(),
+            level: LevelFilter::Warn,
+            limiter: Limiter::new(10, Duration::from_secs(60)),
+            inner: None,
+        }
+    }
+
+    /// Sets the most verbose level that is reported.
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Reports at most `max` records per `window`, further records are dropped.
+    pub fn rate_limit(mut self, max: u32, window: Duration) -> Self {
+        self.limiter.max = max;
+        self.limiter.window = window;
+        self
+    }
+
+    /// Passes all records to `inner` as well, which decides on its own which ones it logs.
+    pub fn forward_to(mut self, inner: Box<dyn Log>) -> Self {
+        self.inner = Some(inner);
+        self
+    }
+
+    /// Installs the logger as the logger of the `log` crate.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let max_level = match self.inner {
+            Some(_) => LevelFilter::Trace,
+            None => self.level,
+        };
+        log::set_logger(Box::leak(Box::new(self)))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    fn reports(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && !reentrancy::is_own_target(metadata.target())
+    }
+}
+
+impl Log for BugsnagLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.reports(metadata) || self.inner.as_ref().is_some_and(|i| i.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(inner) = &self.inner {
+            if inner.enabled(record.metadata()) {
+                inner.log(record);
+            }
+        }
+
+        // records logged while reporting, e.g. by the HTTP client, are not reported
+        if !self.reports(record.metadata()) {
+            return;
+        }
+        let Some(_guard) = ReportingGuard::enter() else {
+            return;
+        };
+
+        if self.limiter.allow() {
+            let mut bugsnag = self.bugsnag.clone();
+            let _ = bugsnag.notify_record(record).send();
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BugsnagLogger;
+    use crate::Bugsnag;
+    use log::{Level, Log, Record};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_logger() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        let logger = BugsnagLogger::new(&api).rate_limit(2, Duration::from_secs(60));
+        let log = |level: Level, target: &str| {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target(target)
+                    .args(format_args!("payment failed"))
+                    .build(),
+            )
+        };
+
+        log(Level::Info, "shop");
+        log(Level::Error, "bugsnag::bugsnag_impl");
+        log(Level::Error, "shop");
+        log(Level::Warn, "shop");
+        log(Level::Error, "shop");
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(&captured[0]).unwrap();
+        assert_eq!(json["events"][0]["context"], "shop");
+        assert_eq!(json["events"][0]["severity"], "error");
+    }
+}
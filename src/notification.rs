@@ -1,10 +1,15 @@
+use super::appinfo::AppInfo;
+use super::deviceinfo::DeviceInfo;
 use super::event::Event;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
 use serde::Serialize;
 
 const NOTIFIER_NAME: &str = "Bugsnag Rust";
 const NOTIFIER_VERSION: &str = env!("CARGO_PKG_VERSION");
 const NOTIFIER_URL: &str = "https://github.com/Hum-Systems/bugsnag-rs";
 pub const PAYLOAD_VERSION: &str = "5";
+#[cfg(feature = "http")]
+pub const SESSION_PAYLOAD_VERSION: &str = "1.0";
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -36,12 +41,56 @@ impl<'a> Notification<'a> {
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionCount {
+    started_at: DateTime<Utc>,
+    sessions_started: u32,
+}
+
+/// The payload of the sessions endpoint, which counts the sessions per minute.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sessions<'a> {
+    notifier: Notifier,
+    app: &'a Option<AppInfo>,
+    device: &'a DeviceInfo,
+    session_counts: [SessionCount; 1],
+}
+
+impl<'a> Sessions<'a> {
+    /// Creates the payload of a single session that started at `started_at`.
+    pub fn new(
+        app: &'a Option<AppInfo>,
+        device: &'a DeviceInfo,
+        started_at: DateTime<Utc>,
+    ) -> Sessions<'a> {
+        Sessions {
+            notifier: Notifier {
+                name: NOTIFIER_NAME,
+                version: NOTIFIER_VERSION,
+                url: NOTIFIER_URL,
+            },
+            app,
+            device,
+            session_counts: [SessionCount {
+                started_at: started_at
+                    .duration_trunc(TimeDelta::minutes(1))
+                    .unwrap_or(started_at),
+                sessions_started: 1,
+            }],
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
 
     use super::super::{deviceinfo, event, exception, stacktrace};
-    use super::{Notification, NOTIFIER_NAME, NOTIFIER_URL, NOTIFIER_VERSION, PAYLOAD_VERSION};
+    use super::{
+        Notification, Sessions, NOTIFIER_NAME, NOTIFIER_URL, NOTIFIER_VERSION, PAYLOAD_VERSION,
+    };
 
     #[test]
     fn test_notification_to_json() {
@@ -117,4 +166,37 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_sessions_to_json() {
+        use chrono::TimeZone;
+
+        let device = deviceinfo::DeviceInfo::new("1.0.0", "testmachine");
+        let started_at = chrono::Utc
+            .with_ymd_and_hms(2024, 5, 1, 12, 30, 45)
+            .unwrap();
+        let sessions = Sessions::new(&None, &device, started_at);
+
+        assert_eq!(
+            serde_json::to_value(&sessions).unwrap(),
+            json!({
+                "notifier": {
+                    "name": NOTIFIER_NAME,
+                    "version": NOTIFIER_VERSION,
+                    "url": NOTIFIER_URL,
+                },
+                "app": null,
+                "device": {
+                    "osVersion": "1.0.0",
+                    "hostname": "testmachine"
+                },
+                "sessionCounts": [
+                    {
+                        "startedAt": "2024-05-01T12:30:00Z",
+                        "sessionsStarted": 1
+                    }
+                ]
+            })
+        );
+    }
 }
@@ -1,11 +1,237 @@
+//! Module with helpers for reporting panics.
+
+use super::{Bugsnag, Severity};
+use std::any::{type_name, Any, TypeId};
+use std::error::Error as StdError;
+use std::fmt::Debug;
+#[allow(deprecated)]
 use std::panic::PanicInfo;
+use std::panic::{catch_unwind, AssertUnwindSafe, PanicHookInfo, UnwindSafe};
+use std::sync::{PoisonError, RwLock};
+
+/// A panic payload type that can be named and formatted.
+struct PayloadType {
+    type_id: TypeId,
+    name: &'static str,
+    to_message: fn(&(dyn Any + Send)) -> Option<String>,
+}
+
+/// Payload types registered by the application with `register_payload_type`.
+static REGISTRY: RwLock<Vec<PayloadType>> = RwLock::new(Vec::new());
+
+fn builtin_payload_types() -> [PayloadType; 4] {
+    [
+        PayloadType {
+            type_id: TypeId::of::<String>(),
+            name: type_name::<String>(),
+            to_message: |p| p.downcast_ref::<String>().cloned(),
+        },
+        PayloadType {
+            type_id: TypeId::of::<&str>(),
+            name: type_name::<&str>(),
+            to_message: |p| p.downcast_ref::<&str>().map(|s| (*s).to_owned()),
+        },
+        PayloadType {
+            type_id: TypeId::of::<Box<dyn StdError + Send + Sync>>(),
+            name: type_name::<Box<dyn StdError + Send + Sync>>(),
+            to_message: |p| {
+                p.downcast_ref::<Box<dyn StdError + Send + Sync>>()
+                    .map(|e| e.to_string())
+            },
+        },
+        PayloadType {
+            type_id: TypeId::of::<Box<dyn StdError + Send>>(),
+            name: type_name::<Box<dyn StdError + Send>>(),
+            to_message: |p| {
+                p.downcast_ref::<Box<dyn StdError + Send>>()
+                    .map(|e| e.to_string())
+            },
+        },
+    ]
+}
+
+/// Registers a custom panic payload type, e.g. for `std::panic::panic_any(MyError)`.
+/// Panics with a payload of this type are reported with the type name and the
+/// `Debug` output of the payload as message.
+pub fn register_payload_type<T: Any + Debug>() {
+    let type_id = TypeId::of::<T>();
+    let mut registry = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+
+    if registry.iter().all(|t| t.type_id != type_id) {
+        registry.push(PayloadType {
+            type_id,
+            name: type_name::<T>(),
+            to_message: |p| p.downcast_ref::<T>().map(|v| format!("{v:?}")),
+        });
+    }
+}
+
+/// Calls `f` with the payload type of `payload`, if the type is known.
+fn with_payload_type<R>(
+    payload: &(dyn Any + Send),
+    f: impl FnOnce(&PayloadType) -> R,
+) -> Option<R> {
+    let type_id = payload.type_id();
+
+    if let Some(t) = builtin_payload_types()
+        .iter()
+        .find(|t| t.type_id == type_id)
+    {
+        return Some(f(t));
+    }
+
+    REGISTRY
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|t| t.type_id == type_id)
+        .map(f)
+}
+
+/// Converts a panic payload, e.g. from `std::panic::catch_unwind`, into a message.
+pub fn payload_to_message(payload: &(dyn Any + Send)) -> String {
+    with_payload_type(payload, |t| (t.to_message)(payload))
+        .flatten()
+        .unwrap_or_else(|| "Error: panic payload of unknown type".to_owned())
+}
+
+/// Returns the type name of a panic payload, if it is a known type.
+pub fn payload_type_name_of(payload: &(dyn Any + Send)) -> &'static str {
+    with_payload_type(payload, |t| t.name).unwrap_or("unknown")
+}
+
+/// Returns the error of a panic payload of type `Box<dyn Error + Send>` or
+/// `Box<dyn Error + Send + Sync>`.
+pub(crate) fn payload_error(payload: &(dyn Any + Send)) -> Option<&(dyn StdError + 'static)> {
+    if let Some(err) = payload.downcast_ref::<Box<dyn StdError + Send + Sync>>() {
+        return Some(err.as_ref());
+    }
+    payload
+        .downcast_ref::<Box<dyn StdError + Send>>()
+        .map(|err| err.as_ref() as &(dyn StdError + 'static))
+}
 
+#[allow(deprecated)]
 pub fn to_message(info: &PanicInfo) -> String {
-    if let Some(data) = info.payload().downcast_ref::<String>() {
-        data.to_owned()
-    } else if let Some(data) = info.payload().downcast_ref::<&str>() {
-        (*data).to_owned()
-    } else {
-        format!("Error: {:?}", info.payload())
+    payload_to_message(info.payload())
+}
+
+/// Returns the type name of the panic payload, if it is a known type.
+pub fn payload_type_name(info: &PanicHookInfo) -> &'static str {
+    payload_type_name_of(info.payload())
+}
+
+/// Runs `f` and catches a panic, so it does not unwind across an FFI boundary.
+/// Use it to wrap the body of `extern "C"` callbacks. A panic is reported to Bugsnag
+/// with `name` as context and `error_value` is returned instead.
+///
+/// # Example
+///
+/// ```
+/// extern "C" fn on_data(len: usize) -> i32 {
+///     # let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+///     bugsnag::ffi_guard(&api, "on_data", -1, || {
+///         // handle the data
+///         0
+///     })
+/// }
+/// ```
+pub fn ffi_guard<R, F>(bugsnag: &Bugsnag, name: &str, error_value: R, f: F) -> R
+where
+    F: FnOnce() -> R + UnwindSafe,
+{
+    match catch_unwind(f) {
+        Ok(val) => val,
+        Err(payload) => {
+            // reporting must not unwind either
+            let _ = catch_unwind(AssertUnwindSafe(|| {
+                let mut bugsnag = bugsnag.clone();
+                let message = payload_to_message(payload.as_ref());
+                let _ = bugsnag
+                    .notify("Panic", &message)
+                    .severity(Severity::Error)
+                    .context(name)
+                    .panic_payload(payload.as_ref())
+                    .send();
+            }));
+            error_value
+        }
+    }
+}
+
+/// Installs a panic hook that reports panics to Bugsnag and then calls the previously
+/// installed hook, e.g. the default hook that prints the panic message.
+pub fn install_hook(bugsnag: &Bugsnag) {
+    let bugsnag = bugsnag.clone();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let mut bugsnag = bugsnag.clone();
+        let _ = bugsnag
+            .notify("Panic", to_message(info))
+            .severity(Severity::Error)
+            .panic(info)
+            .send();
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ffi_guard, payload_to_message, payload_type_name_of, register_payload_type};
+    use crate::config::Config;
+    use crate::Bugsnag;
+    use std::any::Any;
+    use std::error::Error as StdError;
+
+    #[derive(Debug)]
+    struct CustomPayload(#[allow(dead_code)] i32);
+
+    #[test]
+    fn test_string_payloads() {
+        let payload: Box<dyn Any + Send> = Box::new("message");
+        assert_eq!(payload_to_message(payload.as_ref()), "message");
+        assert_eq!(payload_type_name_of(payload.as_ref()), "&str");
+
+        let payload: Box<dyn Any + Send> = Box::new("message".to_owned());
+        assert_eq!(payload_to_message(payload.as_ref()), "message");
+        assert_eq!(
+            payload_type_name_of(payload.as_ref()),
+            "alloc::string::String"
+        );
+    }
+
+    #[test]
+    fn test_error_payload() {
+        let err: Box<dyn StdError + Send + Sync> = "invalid input".into();
+        let payload: Box<dyn Any + Send> = Box::new(err);
+        assert_eq!(payload_to_message(payload.as_ref()), "invalid input");
+    }
+
+    #[test]
+    fn test_custom_payload() {
+        let payload: Box<dyn Any + Send> = Box::new(CustomPayload(42));
+        assert_eq!(
+            payload_to_message(payload.as_ref()),
+            "Error: panic payload of unknown type"
+        );
+        assert_eq!(payload_type_name_of(payload.as_ref()), "unknown");
+
+        register_payload_type::<CustomPayload>();
+        assert_eq!(payload_to_message(payload.as_ref()), "CustomPayload(42)");
+        assert!(payload_type_name_of(payload.as_ref()).ends_with("CustomPayload"));
+    }
+
+    #[test]
+    fn test_ffi_guard() {
+        let api = Bugsnag::new("api-key", "my-dir");
+        api.reload_config(Config::new().enabled(false));
+
+        assert_eq!(ffi_guard(&api, "callback", -1, || 1), 1);
+        assert_eq!(
+            ffi_guard(&api, "callback", -1, || -> i32 {
+                panic!("callback failed")
+            }),
+            -1
+        );
     }
 }
@@ -0,0 +1,238 @@
+//! Module for recording errors in hot loops and reporting them later.
+//!
+//! Recording an error only writes a small record into a fixed-size, lock-free ring, so
+//! it never allocates and, except for unwinding the stack in
+//! `PendingErrors::record_with_frames`, never blocks. The records are turned into notifications by
+//! `Bugsnag::pump`, e.g. from a low-priority tick of a game engine.
+//!
+//! # Example
+//!
+//! ```
+//! let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! api.reload_config(bugsnag::config::Config::new().enabled(false));
+//! let pending = api.use_pending_errors(64);
+//!
+//! // on the frame thread
+//! pending.record("Audio::Underrun", "buffer ran empty");
+//!
+//! // on a low-priority tick
+//! assert_eq!(api.pump(), 1);
+//! ```
+
+use super::stacktrace::RawFrames;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// An error recorded with `PendingErrors::record`.
+pub(crate) struct PendingError {
+    pub error_class: &'static str,
+    pub message: &'static str,
+    pub frames: Option<RawFrames>,
+    pub recorded: Instant,
+}
+
+struct Slot {
+    /// Position of the ring the slot can be written at, or read at plus one.
+    sequence: AtomicUsize,
+    error: UnsafeCell<MaybeUninit<PendingError>>,
+}
+
+/// Bounded multi-producer, multi-consumer ring, in which each slot carries the position
+/// it is ready for, so producers and consumers only synchronize on that slot.
+struct Ring {
+    slots: Box<[Slot]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+// a slot is only accessed by the thread that claimed its position
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new(capacity: usize) -> Ring {
+        // with a single slot, a written and a writable slot would have the same sequence
+        let slots = (0..capacity.max(2))
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                error: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Ring {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, error: PendingError) -> bool {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+
+            if sequence == pos {
+                match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.error.get()).write(error) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return true;
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (sequence.wrapping_sub(pos) as isize) < 0 {
+                // the slot still holds the error of the previous round
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            } else {
+                pos = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<PendingError> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let ready = pos.wrapping_add(1);
+
+            if sequence == ready {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    ready,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let error = unsafe { (*slot.error.get()).assume_init_read() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.slots.len()), Ordering::Release);
+                        return Some(error);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (sequence.wrapping_sub(ready) as isize) < 0 {
+                return None;
+            } else {
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Handle for recording errors from threads that must not block, e.g. the frame thread
+/// of a game or an audio callback. Created by `Bugsnag::use_pending_errors` and shared
+/// with all clones.
+#[derive(Clone)]
+pub struct PendingErrors {
+    ring: Arc<Ring>,
+}
+
+impl fmt::Debug for PendingErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingErrors")
+            .field("capacity", &self.ring.slots.len())
+            .field("dropped", &self.ring.dropped.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl PendingErrors {
+    pub(crate) fn new(capacity: usize) -> PendingErrors {
+        PendingErrors {
+            ring: Arc::new(Ring::new(capacity)),
+        }
+    }
+
+    /// Records an error. Returns `false` if the ring is full and the error was dropped.
+    pub fn record(&self, error_class: &'static str, message: &'static str) -> bool {
+        self.push(error_class, message, None)
+    }
+
+    /// Records an error with the frames of the current stack, see `RawFrames::capture`.
+    /// Unlike `record`, this may block while the stack is unwound.
+    pub fn record_with_frames(&self, error_class: &'static str, message: &'static str) -> bool {
+        self.push(error_class, message, Some(RawFrames::capture()))
+    }
+
+    fn push(
+        &self,
+        error_class: &'static str,
+        message: &'static str,
+        frames: Option<RawFrames>,
+    ) -> bool {
+        self.ring.push(PendingError {
+            error_class,
+            message,
+            frames,
+            recorded: Instant::now(),
+        })
+    }
+
+    /// Takes the oldest recorded error.
+    pub(crate) fn take(&self) -> Option<PendingError> {
+        self.ring.pop()
+    }
+
+    /// Returns the number of errors dropped since the last call, because the ring was full.
+    pub(crate) fn take_dropped(&self) -> usize {
+        self.ring.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PendingErrors;
+
+    #[test]
+    fn test_ring() {
+        let pending = PendingErrors::new(2);
+        assert!(pending.record("A", "first"));
+        assert!(pending.record_with_frames("B", "second"));
+        assert!(!pending.record("C", "third"));
+        assert_eq!(pending.take_dropped(), 1);
+
+        let first = pending.take().unwrap();
+        assert_eq!((first.error_class, first.message), ("A", "first"));
+        assert!(first.frames.is_none());
+        assert!(pending.record("D", "fourth"));
+        assert!(!pending.take().unwrap().frames.unwrap().is_empty());
+        assert_eq!(pending.take().unwrap().error_class, "D");
+        assert!(pending.take().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_record() {
+        let pending = PendingErrors::new(1024);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let pending = pending.clone();
+                std::thread::spawn(move || (0..200).all(|_| pending.record("Error", "message")))
+            })
+            .collect();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+
+        assert_eq!(std::iter::from_fn(|| pending.take()).count(), 800);
+    }
+}
@@ -0,0 +1,382 @@
+//! Module for plugins that enrich events before they are sent.
+//!
+//! The built-in enrichers for the device, app, process, cloud and, with the `tokio`
+//! feature, async runtime data run first, in this order, followed by the plugins
+//! registered with `Bugsnag::register_plugin` in the order of their registration. Later
+//! plugins see and may override the data of earlier ones.
+//!
+//! # Example
+//!
+//! ```
+//! use bugsnag::plugin::{Event, Plugin};
+//!
+//! struct Tenant(String);
+//!
+//! impl Plugin for Tenant {
+//!     fn enrich(&self, event: &mut Event) {
+//!         event.add_metadata("tenant", "id", self.0.clone().into());
+//!     }
+//! }
+//!
+//! let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! api.register_plugin(Tenant("acme".to_owned()));
+//! ```
+
+use super::appinfo::AppInfo;
+use super::deviceinfo::DeviceInfo;
+use super::rustinfo;
+use super::user::User;
+use super::Severity;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+
+/// Enriches events with additional data.
+pub trait Plugin: Send + Sync {
+    /// Returns the name of the plugin, used for debugging.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Adds data to or changes the data of an event.
+    fn enrich(&self, event: &mut Event);
+}
+
+/// The data of an event that plugins can enrich.
+#[derive(Debug)]
+pub struct Event {
+    pub(crate) error_class: String,
+    pub(crate) message: String,
+    pub(crate) severity: Option<Severity>,
+    pub(crate) occurred: DateTime<Utc>,
+    pub(crate) context: Option<String>,
+    pub(crate) device: DeviceInfo,
+    pub(crate) app: Option<AppInfo>,
+    pub(crate) user: Option<User>,
+    pub(crate) device_user: Option<User>,
+    pub(crate) metadata: Map<String, Value>,
+}
+
+impl Event {
+    /// Returns the error class, e.g. `std::io::Error`.
+    pub fn error_class(&self) -> &str {
+        &self.error_class
+    }
+
+    /// Returns the error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the severity, or `None` if the default severity of Bugsnag applies.
+    pub fn severity(&self) -> Option<&Severity> {
+        self.severity.as_ref()
+    }
+
+    /// Sets the severity, e.g. to downgrade expected errors to warnings.
+    pub fn set_severity(&mut self, severity: Severity) {
+        self.severity = Some(severity);
+    }
+
+    /// Returns the time at which the notification was created.
+    pub fn occurred(&self) -> DateTime<Utc> {
+        self.occurred
+    }
+
+    /// Returns the context, e.g. the route or job the error occurred in.
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    /// Sets the context, which Bugsnag shows as the title of the event.
+    pub fn set_context(&mut self, context: &str) {
+        self.context = Some(context.to_owned());
+    }
+
+    /// Returns the user of the application.
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    /// Sets the user of the application.
+    pub fn set_user(&mut self, user: User) {
+        self.user = Some(user);
+    }
+
+    /// Returns the user of the device, e.g. the operator of a kiosk, as opposed to the
+    /// user of the application returned by `user`.
+    pub fn device_user(&self) -> Option<&User> {
+        self.device_user.as_ref()
+    }
+
+    /// Sets the user of the device, see `device_user`.
+    pub fn set_device_user(&mut self, user: User) {
+        self.device_user = Some(user);
+    }
+
+    /// Returns the hostname of the device.
+    pub fn hostname(&self) -> &str {
+        self.device.hostname()
+    }
+
+    /// Sets the hostname of the device.
+    pub fn set_hostname(&mut self, hostname: &str) {
+        self.device.set_hostname(hostname);
+    }
+
+    /// Sets the version of the operating system of the device.
+    pub fn set_os_version(&mut self, version: &str) {
+        self.device.set_os_version(version);
+    }
+
+    /// Returns the version of the app.
+    pub fn app_version(&self) -> Option<&str> {
+        self.app.as_ref().and_then(|app| app.version())
+    }
+
+    /// Sets the version of the app.
+    pub fn set_app_version(&mut self, version: &str) {
+        self.app
+            .get_or_insert_with(|| AppInfo::new(None, None, None))
+            .set_version(version);
+    }
+
+    /// Returns the release stage of the app, e.g. `production`.
+    pub fn release_stage(&self) -> Option<&str> {
+        self.app.as_ref().and_then(|app| app.release_stage())
+    }
+
+    /// Sets the release stage of the app.
+    pub fn set_release_stage(&mut self, release_stage: &str) {
+        self.app
+            .get_or_insert_with(|| AppInfo::new(None, None, None))
+            .set_release_stage(release_stage);
+    }
+
+    /// Returns the milliseconds since the launch of the process.
+    pub fn app_duration(&self) -> Option<u64> {
+        self.app.as_ref().and_then(|app| app.duration())
+    }
+
+    /// Sets the milliseconds since the launch of the process.
+    pub fn set_app_duration(&mut self, duration: u64) {
+        self.app
+            .get_or_insert_with(|| AppInfo::new(None, None, None))
+            .set_duration(duration);
+    }
+
+    /// Returns a metadata tab.
+    pub fn metadata(&self, tab: &str) -> Option<&Value> {
+        self.metadata.get(tab)
+    }
+
+    /// Sets a value in a metadata tab, creating the tab if needed.
+    pub fn add_metadata(&mut self, tab: &str, key: &str, value: Value) {
+        let tab = self
+            .metadata
+            .entry(tab.to_owned())
+            .or_insert_with(|| Value::Object(Map::new()));
+
+        if !tab.is_object() {
+            *tab = Value::Object(Map::new());
+        }
+        tab[key] = value;
+    }
+
+    /// Replaces a whole metadata tab.
+    pub fn set_metadata_tab(&mut self, tab: &str, value: Value) {
+        self.metadata.insert(tab.to_owned(), value);
+    }
+}
+
+/// Sets the time of the device to the time the notification was created.
+pub(crate) struct DevicePlugin;
+
+impl Plugin for DevicePlugin {
+    fn enrich(&self, event: &mut Event) {
+        event.device.set_time(event.occurred);
+        event.device.refresh_free_memory();
+    }
+}
+
+/// Applies the provided app version and the release stage override of the configuration.
+pub(crate) struct AppPlugin<'a> {
+    pub version: Option<&'a str>,
+    pub release_stage: Option<&'a str>,
+    pub duration: Option<u64>,
+}
+
+impl Plugin for AppPlugin<'_> {
+    fn enrich(&self, event: &mut Event) {
+        if let Some(version) = self.version {
+            event.set_app_version(version);
+        }
+        if let Some(stage) = self.release_stage {
+            event.set_release_stage(stage);
+        }
+        if let Some(duration) = self.duration {
+            event.set_app_duration(duration);
+        }
+    }
+}
+
+/// Adds the target and profile of the build of the process to the `app` tab, the ones set
+/// with `Bugsnag::set_build_info` or else the ones of this crate.
+pub(crate) struct ProcessPlugin<'a> {
+    pub build_info: Option<&'a Value>,
+}
+
+impl Plugin for ProcessPlugin<'_> {
+    fn enrich(&self, event: &mut Event) {
+        let build = match self.build_info {
+            Some(build_info) => build_info.clone(),
+            None => rustinfo::build_metadata(),
+        };
+        if let Value::Object(build) = build {
+            for (key, value) in build {
+                event.add_metadata("app", &key, value);
+            }
+        }
+    }
+}
+
+/// Adds the `cloud` tab if the environment variables of a known platform are set.
+pub(crate) struct CloudPlugin;
+
+impl CloudPlugin {
+    fn detect(var: impl Fn(&str) -> Option<String>) -> Option<Value> {
+        if let Some(function) = var("AWS_LAMBDA_FUNCTION_NAME") {
+            let region = var("AWS_REGION");
+            return Some(json!({"provider": "aws", "function": function, "region": region}));
+        }
+        if let Some(service) = var("K_SERVICE") {
+            let revision = var("K_REVISION");
+            return Some(json!({"provider": "gcp", "service": service, "revision": revision}));
+        }
+        if let Some(app) = var("FLY_APP_NAME") {
+            return Some(json!({"provider": "fly", "app": app, "region": var("FLY_REGION")}));
+        }
+        if var("KUBERNETES_SERVICE_HOST").is_some() {
+            return Some(json!({"provider": "kubernetes", "pod": var("HOSTNAME")}));
+        }
+        None
+    }
+}
+
+impl Plugin for CloudPlugin {
+    fn enrich(&self, event: &mut Event) {
+        if let Some(cloud) = CloudPlugin::detect(|name| std::env::var(name).ok()) {
+            event.set_metadata_tab("cloud", cloud);
+        }
+    }
+}
+
+/// Adds the `tokio` tab with the metrics of the runtime the notification is created on,
+/// to diagnose errors caused by starved or overloaded runtimes. The depth of the blocking
+/// pool is only available if the application is built with `--cfg tokio_unstable`.
+#[cfg(feature = "tokio")]
+pub(crate) struct TokioPlugin;
+
+#[cfg(feature = "tokio")]
+impl TokioPlugin {
+    fn metrics(handle: &tokio::runtime::Handle) -> Value {
+        let metrics = handle.metrics();
+        #[allow(unused_mut)]
+        let mut tab = json!({
+            "flavor": format!("{:?}", handle.runtime_flavor()),
+            "workers": metrics.num_workers(),
+            "aliveTasks": metrics.num_alive_tasks(),
+            "globalQueueDepth": metrics.global_queue_depth(),
+        });
+        #[cfg(tokio_unstable)]
+        {
+            tab["blockingThreads"] = json!(metrics.num_blocking_threads());
+            tab["idleBlockingThreads"] = json!(metrics.num_idle_blocking_threads());
+            tab["blockingQueueDepth"] = json!(metrics.blocking_queue_depth());
+        }
+        tab
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Plugin for TokioPlugin {
+    fn enrich(&self, event: &mut Event) {
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            event.set_metadata_tab("tokio", TokioPlugin::metrics(&handle));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CloudPlugin, Event};
+    use crate::deviceinfo::DeviceInfo;
+    use chrono::Utc;
+    use serde_json::{json, Map};
+
+    fn event() -> Event {
+        Event {
+            error_class: "Error".to_owned(),
+            message: "message".to_owned(),
+            severity: None,
+            occurred: Utc::now(),
+            context: None,
+            device: DeviceInfo::new("1.0.0", "testmachine"),
+            app: None,
+            user: None,
+            device_user: None,
+            metadata: Map::new(),
+        }
+    }
+
+    #[test]
+    fn test_add_metadata() {
+        let mut event = event();
+        event.add_metadata("tenant", "id", json!("acme"));
+        event.add_metadata("tenant", "plan", json!("pro"));
+        event.set_release_stage("staging");
+
+        assert_eq!(
+            event.metadata("tenant"),
+            Some(&json!({"id": "acme", "plan": "pro"}))
+        );
+        assert_eq!(event.release_stage(), Some("staging"));
+    }
+
+    #[test]
+    fn test_detect_cloud() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| (*v).to_owned())
+            }
+        };
+
+        assert_eq!(
+            CloudPlugin::detect(env(&[("K_SERVICE", "api"), ("K_REVISION", "api-001")])),
+            Some(json!({"provider": "gcp", "service": "api", "revision": "api-001"}))
+        );
+        assert_eq!(CloudPlugin::detect(env(&[])), None);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_tokio_metrics() {
+        use super::{Plugin, TokioPlugin};
+
+        let mut outside = event();
+        TokioPlugin.enrich(&mut outside);
+        assert_eq!(outside.metadata("tokio"), None);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut inside = event();
+        runtime.block_on(async { TokioPlugin.enrich(&mut inside) });
+
+        let tab = inside.metadata("tokio").unwrap();
+        assert_eq!(tab["flavor"], "CurrentThread");
+        assert_eq!(tab["workers"], 1);
+        assert!(tab["aliveTasks"].is_u64());
+    }
+}
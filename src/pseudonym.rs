@@ -0,0 +1,76 @@
+//! Module for pseudonymizing the identifiers of hosts and users before they are sent.
+//!
+//! Identifiers are replaced by an HMAC-SHA256 with a key of the application, so events of
+//! the same host or user can still be correlated, but the raw identifiers can not be
+//! recovered without the key.
+
+use super::plugin::{Event, Plugin};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::Write;
+
+/// Replaces the hostname and the user fields of events by their HMAC.
+#[derive(Clone)]
+pub(crate) struct Pseudonymizer {
+    key: Vec<u8>,
+}
+
+impl std::fmt::Debug for Pseudonymizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // the key must not end up in logs
+        f.write_str("Pseudonymizer")
+    }
+}
+
+impl Pseudonymizer {
+    pub fn new(key: &[u8]) -> Pseudonymizer {
+        Pseudonymizer { key: key.to_vec() }
+    }
+
+    /// Returns the hex encoded HMAC of `value`.
+    pub fn hash(&self, value: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::new(), |mut hex, b| {
+                let _ = write!(hex, "{b:02x}");
+                hex
+            })
+    }
+}
+
+impl Plugin for Pseudonymizer {
+    fn name(&self) -> &str {
+        "pseudonymizer"
+    }
+
+    fn enrich(&self, event: &mut Event) {
+        let hostname = self.hash(event.device.hostname());
+        event.device.set_hostname(&hostname);
+        for user in event.user.iter_mut().chain(event.device_user.iter_mut()) {
+            user.map_fields(|value| self.hash(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pseudonymizer;
+
+    #[test]
+    fn test_hash() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            Pseudonymizer::new(b"Jefe").hash("what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_ne!(
+            Pseudonymizer::new(b"other").hash("what do ya want for nothing?"),
+            Pseudonymizer::new(b"Jefe").hash("what do ya want for nothing?")
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Module for delivering notifications from a background thread.
+
+use super::reentrancy::ReportingGuard;
+use super::{instrumentation, trace, Bugsnag};
+use log::info;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A notification waiting for delivery, together with the instance that created it.
+pub struct Delivery {
+    pub bugsnag: Box<Bugsnag>,
+    pub json: String,
+    /// The deadline of the delivery, after which the notification is stored offline.
+    pub deadline: Option<Instant>,
+    /// The id of the traced event, see the `trace` module.
+    pub event_id: Option<String>,
+}
+
+/// Bounded queue of notifications that are delivered by a worker thread.
+#[derive(Debug)]
+pub struct DeliveryQueue {
+    sender: SyncSender<Delivery>,
+    depth: Arc<AtomicUsize>,
+    unfinished: Arc<AtomicUsize>,
+}
+
+/// The receiving end of a `DeliveryQueue`, used by the worker thread.
+pub struct QueueReceiver {
+    receiver: Receiver<Delivery>,
+    depth: Arc<AtomicUsize>,
+    unfinished: Arc<AtomicUsize>,
+}
+
+impl QueueReceiver {
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Delivery, RecvTimeoutError> {
+        let delivery = self.receiver.recv_timeout(timeout)?;
+        let depth = self.depth.fetch_sub(1, Ordering::Relaxed) - 1;
+        instrumentation::queue_depth(depth);
+        Ok(delivery)
+    }
+
+    /// Marks a received notification as delivered or stored.
+    pub fn finished(&self) {
+        self.unfinished.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl DeliveryQueue {
+    /// Creates the queue and spawns `worker` with the receiving end of the queue.
+    pub fn new<F>(capacity: usize, worker: F) -> DeliveryQueue
+    where
+        F: FnOnce(QueueReceiver) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let depth = Arc::new(AtomicUsize::new(0));
+        let unfinished = Arc::new(AtomicUsize::new(0));
+        let receiver = QueueReceiver {
+            receiver,
+            depth: depth.clone(),
+            unfinished: unfinished.clone(),
+        };
+
+        std::thread::Builder::new()
+            .name("bugsnag-delivery".to_owned())
+            .spawn(move || {
+                // everything the worker logs is part of reporting
+                let _reporting = ReportingGuard::enter();
+                worker(receiver)
+            })
+            .expect("failed to spawn bugsnag delivery thread");
+
+        DeliveryQueue {
+            sender,
+            depth,
+            unfinished,
+        }
+    }
+
+    /// Adds a notification to the queue. The notification is handed back if the queue
+    /// is full or the worker is gone.
+    pub fn enqueue(&self, delivery: Delivery) -> Result<(), Delivery> {
+        // counted before sending, so the worker never decrements below zero
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.unfinished.fetch_add(1, Ordering::SeqCst);
+
+        match self.sender.try_send(delivery) {
+            Ok(()) => {
+                instrumentation::queue_depth(depth);
+                Ok(())
+            }
+            Err(TrySendError::Full(d)) | Err(TrySendError::Disconnected(d)) => {
+                self.depth.fetch_sub(1, Ordering::Relaxed);
+                self.unfinished.fetch_sub(1, Ordering::SeqCst);
+                Err(d)
+            }
+        }
+    }
+
+    /// Returns the number of notifications waiting for delivery.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of notifications waiting for or in delivery.
+    pub fn unfinished(&self) -> usize {
+        self.unfinished.load(Ordering::SeqCst)
+    }
+}
+
+/// Delivers queued notifications. While the queue is idle, reports from the offline
+/// storage are retransmitted every `retry_interval`, using the offline storage of the
+/// instance that queued the last notification or of `bugsnag` initially.
+pub fn deliver(receiver: QueueReceiver, bugsnag: Bugsnag, retry_interval: Duration) {
+    let mut last = Box::new(bugsnag);
+
+    loop {
+        match receiver.recv_timeout(retry_interval) {
+            Ok(delivery) => {
+                let _event = delivery.event_id.as_deref().map(trace::enter);
+                let res = delivery
+                    .bugsnag
+                    .send_before(&delivery.json, delivery.deadline);
+                trace::decision!("background delivery, outcome {res:?}");
+                if res.is_err() {
+                    info!("background delivery failed");
+                }
+                receiver.finished();
+                last = delivery.bugsnag;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if last.has_offline_storage() {
+                    last.retry_from_storage().ok();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delivery, DeliveryQueue};
+    use crate::Bugsnag;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn delivery() -> Delivery {
+        Delivery {
+            bugsnag: Box::new(Bugsnag::new("api-key", "my-dir")),
+            json: "{}".to_owned(),
+            deadline: None,
+            event_id: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_depth() {
+        let (start, started) = mpsc::channel();
+        let queue = DeliveryQueue::new(2, move |rx| {
+            started.recv().unwrap();
+            while rx.recv_timeout(Duration::from_millis(10)).is_ok() {}
+        });
+
+        assert!(queue.enqueue(delivery()).is_ok());
+        assert!(queue.enqueue(delivery()).is_ok());
+        assert!(queue.enqueue(delivery()).is_err());
+        assert_eq!(queue.depth(), 2);
+
+        start.send(()).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(queue.depth(), 0);
+    }
+}
@@ -0,0 +1,76 @@
+//! Module for the local log of delivered notifications.
+//!
+//! Each delivery appends one json line per event with the id of the event, the time of
+//! the delivery and the HTTP status returned by Bugsnag. The log is never rewritten, so it
+//! can serve as audit trail of the reported incidents.
+
+use chrono::Utc;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Returns the ids of the events of a notification, as reported in the metadata tab `tab`.
+fn event_ids(json: &str, tab: &str) -> Vec<String> {
+    let Ok(notification) = serde_json::from_str::<serde_json::Value>(json) else {
+        return Vec::new();
+    };
+
+    notification["events"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|event| event["metaData"][tab]["eventId"].as_str())
+        .map(|id| id.to_owned())
+        .collect()
+}
+
+/// Appends a receipt for each event of the delivered notification `json` to `path`. The
+/// ids of the events are read from the metadata tab `tab`.
+pub fn record(path: &Path, json: &str, tab: &str, status: u16) -> io::Result<()> {
+    let delivered_at = Utc::now().to_rfc3339();
+    let mut lines = String::new();
+
+    for id in event_ids(json, tab) {
+        lines.push_str(
+            &json!({"eventId": id, "deliveredAt": delivered_at, "status": status}).to_string(),
+        );
+        lines.push('\n');
+    }
+
+    // written at once, so concurrent deliveries do not interleave their lines
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(lines.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::record;
+    use serde_json::json;
+
+    #[test]
+    fn test_record() {
+        let path = std::env::temp_dir().join(format!("bugsnag_receipts_{}", uuid::Uuid::new_v4()));
+        let notification =
+            json!({"events": [{"metaData": {"bugsnag-rs": {"eventId": "abc"}}}]}).to_string();
+
+        record(&path, &notification, "bugsnag-rs", 200).unwrap();
+        record(&path, &notification, "bugsnag-rs", 202).unwrap();
+        record(&path, &notification, "other", 200).unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let receipts = log
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0]["eventId"], "abc");
+        assert_eq!(receipts[0]["status"], 200);
+        assert_eq!(receipts[1]["status"], 202);
+
+        std::fs::remove_file(path).ok();
+    }
+}
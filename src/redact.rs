@@ -0,0 +1,69 @@
+//! Module for redacting the values of sensitive keys in the metadata of events.
+
+use serde_json::{json, Map, Value};
+
+/// The keys redacted by default.
+pub const DEFAULT_REDACTED_KEYS: [&str; 3] = ["password", "authorization", "secret"];
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Replaces the values of all keys that contain one of `keys`, ignoring case, by
+/// `[REDACTED]`, in nested objects and arrays as well. `keys` have to be lowercase.
+pub fn redact(map: &mut Map<String, Value>, keys: &[String]) {
+    if keys.is_empty() {
+        return;
+    }
+    for (key, value) in map.iter_mut() {
+        let lower = key.to_lowercase();
+        if keys.iter().any(|k| lower.contains(k.as_str())) {
+            *value = json!(REDACTED);
+        } else {
+            redact_value(value, keys);
+        }
+    }
+}
+
+fn redact_value(value: &mut Value, keys: &[String]) {
+    match value {
+        Value::Object(map) => redact(map, keys),
+        Value::Array(values) => values.iter_mut().for_each(|v| redact_value(v, keys)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, DEFAULT_REDACTED_KEYS};
+    use serde_json::json;
+
+    #[test]
+    fn test_redact() {
+        let keys: Vec<String> = DEFAULT_REDACTED_KEYS
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        let mut metadata = json!({
+            "request": {
+                "headers": {"Authorization": "Bearer abc", "Accept": "*/*"},
+                "forms": [{"user": "alice", "db_password": "hunter2"}],
+            },
+            "client_secret": {"nested": "value"},
+        });
+
+        redact(metadata.as_object_mut().unwrap(), &keys);
+        assert_eq!(
+            metadata,
+            json!({
+                "request": {
+                    "headers": {"Authorization": "[REDACTED]", "Accept": "*/*"},
+                    "forms": [{"user": "alice", "db_password": "[REDACTED]"}],
+                },
+                "client_secret": "[REDACTED]",
+            })
+        );
+
+        let mut metadata = json!({"password": "hunter2"});
+        redact(metadata.as_object_mut().unwrap(), &[]);
+        assert_eq!(metadata, json!({"password": "hunter2"}));
+    }
+}
@@ -0,0 +1,106 @@
+//! Guards of the integrations that turn the output of other crates into reports.
+//!
+//! Reporting logs itself, e.g. the HTTP client or a failed delivery, so a report may emit
+//! a log record or tracing event that would be reported again. `ReportingGuard` marks the
+//! threads that are reporting, the records they emit in the meantime are not reported.
+//! The threads of this crate that deliver notifications are marked for their whole
+//! lifetime. `is_own_target` tells the records of this crate apart. `Limiter` caps the
+//! number of reports of an integration, for bursts of records.
+
+use std::cell::Cell;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// Set while the current thread reports a record or event.
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as reporting until it is dropped, even if the report panicked.
+pub(crate) struct ReportingGuard(());
+
+impl ReportingGuard {
+    /// Returns `None` if the current thread is already reporting.
+    pub(crate) fn enter() -> Option<ReportingGuard> {
+        match REPORTING.with(|reporting| reporting.replace(true)) {
+            true => None,
+            false => Some(ReportingGuard(())),
+        }
+    }
+}
+
+impl Drop for ReportingGuard {
+    fn drop(&mut self) {
+        REPORTING.with(|reporting| reporting.set(false));
+    }
+}
+
+/// Checks if a log record or tracing event was emitted by this crate, e.g. by
+/// `bugsnag::trace`. Those are never reported, unlike the ones of other crates whose name
+/// starts with `bugsnag`, e.g. an application named `bugsnag_demo`.
+pub(crate) fn is_own_target(target: &str) -> bool {
+    target == "bugsnag" || target.starts_with("bugsnag::")
+}
+
+/// Allows up to `max` reports per `window`.
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    pub max: u32,
+    pub window: Duration,
+    state: Mutex<(Instant, u32)>,
+}
+
+impl Limiter {
+    pub(crate) fn new(max: u32, window: Duration) -> Limiter {
+        Limiter {
+            max,
+            window,
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    pub(crate) fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.0.elapsed() >= self.window {
+            *state = (Instant::now(), 0);
+        }
+        if state.1 >= self.max {
+            return false;
+        }
+        state.1 += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_own_target, Limiter, ReportingGuard};
+    use std::time::Duration;
+
+    #[test]
+    fn test_reporting_guard() {
+        let guard = ReportingGuard::enter();
+        assert!(guard.is_some());
+        assert!(ReportingGuard::enter().is_none());
+        drop(guard);
+        assert!(ReportingGuard::enter().is_some());
+    }
+
+    #[test]
+    fn test_is_own_target() {
+        assert!(is_own_target("bugsnag"));
+        assert!(is_own_target("bugsnag::trace"));
+        assert!(!is_own_target("bugsnag_demo"));
+        assert!(!is_own_target("bugsnagger::db"));
+    }
+
+    #[test]
+    fn test_limiter() {
+        let limiter = Limiter::new(2, Duration::from_millis(50));
+        assert!(limiter.allow());
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.allow());
+    }
+}
@@ -0,0 +1,166 @@
+//! Module for libraries that report their internal failures.
+//!
+//! The Bugsnag client and the panic hook belong to the application. A library declares a
+//! `Reporter` instead, which stays silent until the application enables it with its
+//! client. The reports are then send with the transport, offline storage and queue of the
+//! application, to the project of the library's own API key if it has one, and carry the
+//! name and version of the library in the `library` tab.
+//!
+//! # Example
+//!
+//! ```
+//! // in the library
+//! pub static REPORTER: bugsnag::Reporter =
+//!     bugsnag::Reporter::new("my-lib", env!("CARGO_PKG_VERSION"));
+//!
+//! fn checkout() {
+//!     REPORTER.report("Pool::Exhausted", "no connection available");
+//! }
+//!
+//! // in the application
+//! let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! REPORTER.enable(&api);
+//! ```
+
+use super::plugin::{Event, Plugin};
+use super::{Bugsnag, Error, Outcome};
+use serde_json::json;
+use std::error::Error as StdError;
+use std::sync::{PoisonError, RwLock};
+
+/// Adds the `library` tab to the reports of a `Reporter`.
+struct LibraryPlugin {
+    name: &'static str,
+    version: &'static str,
+}
+
+impl Plugin for LibraryPlugin {
+    fn enrich(&self, event: &mut Event) {
+        event.set_metadata_tab(
+            "library",
+            json!({"name": self.name, "version": self.version}),
+        );
+    }
+}
+
+/// Reports the failures of a library, once the application enabled it.
+#[derive(Debug)]
+pub struct Reporter {
+    name: &'static str,
+    version: &'static str,
+    api_key: Option<&'static str>,
+    bugsnag: RwLock<Option<Bugsnag>>,
+}
+
+impl Reporter {
+    /// Creates a disabled reporter for the library `name` in `version`.
+    pub const fn new(name: &'static str, version: &'static str) -> Reporter {
+        Reporter {
+            name,
+            version,
+            api_key: None,
+            bugsnag: RwLock::new(None),
+        }
+    }
+
+    /// Creates a disabled reporter that sends to the project of `api_key` instead of the
+    /// project of the application.
+    pub const fn with_api_key(
+        name: &'static str,
+        version: &'static str,
+        api_key: &'static str,
+    ) -> Reporter {
+        Reporter {
+            name,
+            version,
+            api_key: Some(api_key),
+            bugsnag: RwLock::new(None),
+        }
+    }
+
+    /// Enables the reporter with a child of the client of the application. Changes to
+    /// the client after this call are not seen by the reporter.
+    pub fn enable(&self, bugsnag: &Bugsnag) {
+        let mut child = bugsnag.child();
+        if let Some(api_key) = self.api_key {
+            child.set_api_key(api_key);
+        }
+        child.register_plugin(LibraryPlugin {
+            name: self.name,
+            version: self.version,
+        });
+
+        *self.bugsnag.write().unwrap_or_else(PoisonError::into_inner) = Some(child);
+    }
+
+    /// Disables the reporter, later reports are dropped.
+    pub fn disable(&self) {
+        *self.bugsnag.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.client().is_some()
+    }
+
+    /// Reports an error. Returns `None` if the reporter is not enabled.
+    pub fn report(&self, error_class: &str, message: &str) -> Option<Result<Outcome, Error>> {
+        let mut bugsnag = self.client()?;
+        let outcome = bugsnag.notify(error_class, message).send();
+        Some(outcome)
+    }
+
+    /// Reports an error and its causes, see `Bugsnag::notify_error`. Returns `None` if
+    /// the reporter is not enabled.
+    pub fn report_error(&self, err: &dyn StdError) -> Option<Result<Outcome, Error>> {
+        let mut bugsnag = self.client()?;
+        let outcome = bugsnag.notify_error(err).send();
+        Some(outcome)
+    }
+
+    /// Returns a copy of the client, so no lock is held while sending.
+    fn client(&self) -> Option<Bugsnag> {
+        self.bugsnag
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reporter;
+    use crate::Bugsnag;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    static REPORTER: Reporter = Reporter::with_api_key("my-lib", "1.2.3", "lib-key");
+
+    #[test]
+    fn test_reporter() {
+        assert!(REPORTER
+            .report("Pool::Exhausted", "no connection")
+            .is_none());
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("app-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        REPORTER.enable(&api);
+        assert!(REPORTER.is_enabled());
+        assert!(REPORTER
+            .report("Pool::Exhausted", "no connection")
+            .unwrap()
+            .is_ok());
+
+        let captured = captured.lock().unwrap();
+        let json: Value = serde_json::from_str(&captured[0]).unwrap();
+        assert_eq!(
+            json["events"][0]["metaData"]["library"],
+            serde_json::json!({"name": "my-lib", "version": "1.2.3"})
+        );
+
+        REPORTER.disable();
+        assert!(!REPORTER.is_enabled());
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+const TARGET: &str = env!("BUGSNAG_BUILD_TARGET");
+const OPT_LEVEL: &str = env!("BUGSNAG_BUILD_OPT_LEVEL");
+const PROFILE: &str = env!("BUGSNAG_BUILD_PROFILE");
+const LTO: &str = env!("BUGSNAG_BUILD_LTO");
+
+/// Rust specific diagnostics that are attached as the `rust` metadata tab. The build
+/// settings are the ones of this crate, see `build_metadata`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RustInfo {
+    panic: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_type: Option<String>,
+    target: &'static str,
+    opt_level: &'static str,
+    profile: &'static str,
+    lto: &'static str,
+    debug_assertions: bool,
+}
+
+impl RustInfo {
+    pub fn generate(panic_payload_type: Option<&str>) -> RustInfo {
+        RustInfo {
+            panic: panic_payload_type.is_some(),
+            payload_type: panic_payload_type.map(|t| t.to_owned()),
+            target: TARGET,
+            opt_level: OPT_LEVEL,
+            profile: PROFILE,
+            lto: LTO,
+            debug_assertions: cfg!(debug_assertions),
+        }
+    }
+}
+
+/// Returns the build settings that are added to the `app` metadata tab, so events can be
+/// filtered by the target and profile of the build.
+///
+/// The settings are the ones of the compilation of this crate, read by its build script.
+/// The target is the one of the application, but the profile, optimization level and LTO
+/// setting may differ, e.g. with a profile override for dependencies. The application
+/// can report its own settings with `build::embed_build_info` and
+/// `Bugsnag::set_build_info`, which replace these.
+pub fn build_metadata() -> Value {
+    json!({
+        "buildTarget": TARGET,
+        "buildProfile": PROFILE,
+        "optLevel": OPT_LEVEL,
+        "lto": LTO,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{build_metadata, RustInfo, LTO, OPT_LEVEL, PROFILE, TARGET};
+
+    #[test]
+    fn test_rustinfo_to_json() {
+        let info = RustInfo::generate(None);
+
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            json!({
+                "panic": false,
+                "target": TARGET,
+                "opt_level": OPT_LEVEL,
+                "profile": PROFILE,
+                "lto": LTO,
+                "debug_assertions": cfg!(debug_assertions)
+            })
+        );
+    }
+
+    #[test]
+    fn test_rustinfo_with_panic_to_json() {
+        let info = RustInfo::generate(Some("&str"));
+        let json = serde_json::to_value(&info).unwrap();
+
+        assert_eq!(json["panic"], true);
+        assert_eq!(json["payload_type"], "&str");
+    }
+
+    #[test]
+    fn test_build_metadata() {
+        let build = build_metadata();
+
+        assert_eq!(build["buildTarget"], TARGET);
+        assert_eq!(build["buildProfile"], PROFILE);
+        assert_ne!(build["lto"], "");
+    }
+}
@@ -0,0 +1,213 @@
+//! Module for flushing queued notifications when the process is asked to terminate.
+//!
+//! Orchestrators like Kubernetes stop a pod with `SIGTERM`, which terminates the process
+//! before the worker of `Bugsnag::use_background_delivery` delivered the notifications
+//! created during shutdown. With `flush_on_termination`, the first `SIGTERM` or `SIGINT`
+//! waits for the queue to be flushed, bounded by a timeout, before the signal is raised
+//! again with the disposition installed before, e.g. the default action that terminates
+//! the process or a handler of the application. A second signal is raised again
+//! immediately.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! api.use_background_delivery(64, Duration::from_secs(60));
+//! bugsnag::signal::flush_on_termination(&api, Duration::from_secs(5)).unwrap();
+//! ```
+
+use super::Bugsnag;
+use log::warn;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, Ordering};
+use std::time::Duration;
+
+const SIGNALS: [libc::c_int; 2] = [libc::SIGTERM, libc::SIGINT];
+
+/// Write end of the pipe that wakes up the flushing thread.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+/// The dispositions of `SIGNALS` before the handlers were installed, null if unknown.
+static PREVIOUS: [AtomicPtr<libc::sigaction>; 2] =
+    [const { AtomicPtr::new(std::ptr::null_mut()) }; 2];
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    // only async-signal-safe functions may be called here
+    if SIGNALLED.swap(true, Ordering::SeqCst) {
+        raise_previous(signal);
+        return;
+    }
+
+    let byte = signal as u8;
+    // SAFETY: `write` is async-signal-safe and `byte` outlives the call. If the pipe is
+    // already closed, the write fails without effect.
+    unsafe {
+        libc::write(
+            PIPE.load(Ordering::SeqCst),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        )
+    };
+}
+
+/// Restores the disposition of `signal` from before the handlers were installed, and
+/// raises `signal` again, so the application handles it as if the handlers were never
+/// installed. Async-signal-safe.
+fn raise_previous(signal: libc::c_int) {
+    let previous = SIGNALS
+        .iter()
+        .position(|s| *s == signal)
+        .map_or(std::ptr::null_mut(), |i| PREVIOUS[i].load(Ordering::SeqCst));
+    // SAFETY: `sigaction`, `signal` and `raise` are async-signal-safe. `previous` is
+    // either null or was filled by `sigaction` and is never freed.
+    unsafe {
+        if previous.is_null() {
+            libc::signal(signal, libc::SIG_DFL);
+        } else {
+            libc::sigaction(signal, previous, std::ptr::null_mut());
+        }
+        libc::raise(signal);
+    }
+}
+
+/// Restores the dispositions of `SIGNALS` that were saved by `install`, after installing
+/// failed.
+fn restore_previous() {
+    for (i, &signal) in SIGNALS.iter().enumerate() {
+        let previous = PREVIOUS[i].swap(std::ptr::null_mut(), Ordering::SeqCst);
+        if !previous.is_null() {
+            // SAFETY: `previous` was filled by `sigaction` and is never freed.
+            unsafe { libc::sigaction(signal, previous, std::ptr::null_mut()) };
+        }
+    }
+}
+
+/// Saves the dispositions of `SIGNALS` in `PREVIOUS` and installs `on_signal`.
+fn install() -> io::Result<()> {
+    for (i, &signal) in SIGNALS.iter().enumerate() {
+        // SAFETY: `sigaction` is a plain C struct, for which all zeros is a valid value.
+        let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+        // SAFETY: `previous` is valid for writes, a null action only reads the disposition.
+        if unsafe { libc::sigaction(signal, std::ptr::null(), &mut previous) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // the disposition is saved before `on_signal` can run, and lives as long as the
+        // process, as `on_signal` may read it at any time
+        PREVIOUS[i].store(Box::into_raw(Box::new(previous)), Ordering::SeqCst);
+
+        // SAFETY: see above.
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        // SAFETY: `action` is valid, and `on_signal` only calls async-signal-safe functions.
+        unsafe {
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Waits until `on_signal` writes a signal to `pipe` and flushes the delivery queue of
+/// `bugsnag` for at most `timeout`. Returns the signal, or `None` if the pipe was closed.
+fn flush_on_signal(
+    pipe: &mut impl Read,
+    bugsnag: &Bugsnag,
+    timeout: Duration,
+) -> Option<libc::c_int> {
+    let mut signal = [0u8];
+    pipe.read_exact(&mut signal).ok()?;
+
+    if !bugsnag.flush(timeout) {
+        warn!("queued notifications were not flushed before termination");
+    }
+    Some(libc::c_int::from(signal[0]))
+}
+
+/// Installs handlers for `SIGTERM` and `SIGINT` that flush the delivery queue of
+/// `bugsnag` for at most `timeout` and then raise the signal again with the disposition
+/// installed before, which terminates the process unless the application handles or
+/// ignores the signal.
+///
+/// Must be called after `Bugsnag::use_background_delivery`, as the queue of `bugsnag` is
+/// captured when the handlers are installed. Fails if the handlers are already installed.
+/// If installing fails, the dispositions from before are restored.
+pub fn flush_on_termination(bugsnag: &Bugsnag, timeout: Duration) -> io::Result<()> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` is valid for the two fds written by `pipe`.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fds[0]` is the read end of the new pipe, owned by nothing else.
+    let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+
+    if PIPE
+        .compare_exchange(-1, fds[1], Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        // SAFETY: `fds[1]` is the write end of the new pipe, owned by nothing else.
+        unsafe { libc::close(fds[1]) };
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "termination handlers are already installed",
+        ));
+    }
+
+    let bugsnag = bugsnag.clone();
+    // on failure, the reader is dropped with the closure, which closes the read end
+    let result = std::thread::Builder::new()
+        .name("bugsnag-signal".to_owned())
+        .spawn(move || {
+            if let Some(signal) = flush_on_signal(&mut reader, &bugsnag, timeout) {
+                raise_previous(signal);
+            }
+        })
+        .and_then(|_| install());
+
+    if result.is_err() {
+        restore_previous();
+        PIPE.store(-1, Ordering::SeqCst);
+        // SAFETY: `fds[1]` is the write end of the new pipe, which is no longer used by
+        // the handlers. Closing it ends the flushing thread, which closes the read end.
+        unsafe { libc::close(fds[1]) };
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flush_on_signal;
+    use crate::Bugsnag;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_flush_on_signal() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+        api.use_background_delivery(4, Duration::from_secs(60));
+        let timeout = Duration::from_secs(5);
+        let _ = api.notify("Error", "during shutdown").send();
+
+        let mut pipe: &[u8] = &[libc::SIGTERM as u8, libc::SIGINT as u8];
+        assert_eq!(
+            flush_on_signal(&mut pipe, &api, timeout),
+            Some(libc::SIGTERM)
+        );
+        assert_eq!(captured.lock().unwrap().len(), 1);
+        assert_eq!(
+            flush_on_signal(&mut pipe, &api, timeout),
+            Some(libc::SIGINT)
+        );
+        // a closed pipe does not terminate the process
+        assert_eq!(flush_on_signal(&mut pipe, &api, timeout), None);
+    }
+}
@@ -1,29 +1,184 @@
 //! Module for creating a stacktrace in the Bugsnag format.
 
-use backtrace::{self, Symbol};
+use super::buildid;
+use super::hashing::StableHasher;
+use backtrace::{self, Backtrace, BacktraceFrame, Symbol};
 use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::path::Path;
 
+/// Number of lines of source code attached before and after the line of a frame.
+const SNIPPET_CONTEXT: u32 = 3;
+/// Maximum length in bytes of an attached line of source code, as Bugsnag truncates
+/// longer lines anyway.
+const MAX_SNIPPET_LINE_LEN: usize = 200;
+
 /// Struct for storing the one frame of the stacktrace.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Frame {
     file: String,
     line_number: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column_number: Option<u32>,
     method: String,
+    /// The module of the method, e.g. `app::handlers` for `app::handlers::checkout`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module_path: Option<String>,
     in_project: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crate_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crate_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_identifier: Option<String>,
+    /// Lines of source code around the line of the frame, by line number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<BTreeMap<u32, String>>,
 }
 
 impl Frame {
+    /// Creates a frame, e.g. of a stack reported by another process or language for
+    /// `NotifyBuilder::stacktrace`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The source file of the frame.
+    /// * `linenumber` - The line in the source file, or 0 if unknown.
+    /// * `method` - The function of the frame.
+    /// * `in_proj` - Whether the frame belongs to the project, Bugsnag highlights these
+    ///   frames and hides the others by default.
     pub fn new(file: &str, linenumber: u32, method: &str, in_proj: bool) -> Frame {
         Frame {
             file: file.to_owned(),
             line_number: linenumber,
+            column_number: None,
             method: method.to_owned(),
+            module_path: None,
             in_project: in_proj,
+            crate_name: None,
+            crate_version: None,
+            frame_address: None,
+            load_address: None,
+            code_identifier: None,
+            code: None,
         }
     }
 
+    /// Creates a frame for an address without symbol information, e.g. of a stripped
+    /// binary or one built with split debuginfo. The method is the address relative to
+    /// the module that contains it, so it can be symbolicated later with the debug info
+    /// that matches the build id of the module.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The instruction pointer of the frame.
+    /// * `module` - The path and load address of the module, if known.
+    fn unsymbolicated(ip: usize, module: Option<(String, usize)>) -> Frame {
+        let (file, method, load_address, code_identifier) = match module {
+            Some((path, base)) => (
+                path.clone(),
+                format!("0x{:x}", ip.wrapping_sub(base)),
+                Some(format!("0x{base:x}")),
+                buildid::of_file(&path),
+            ),
+            None => (String::new(), format!("0x{ip:x}"), None, None),
+        };
+
+        let mut frame = Frame::new(&file, 0, &method, false);
+        frame.frame_address = Some(format!("0x{ip:x}"));
+        frame.load_address = load_address;
+        frame.code_identifier = code_identifier;
+        frame
+    }
+
+    /// Returns the source file of the frame.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Sets the source file of the frame, e.g. to strip a build directory.
+    pub fn set_file(&mut self, file: &str) {
+        self.file = file.to_owned();
+    }
+
+    /// Returns the line in the source file, or 0 if unknown.
+    pub fn line_number(&self) -> u32 {
+        self.line_number
+    }
+
+    /// Sets the line in the source file.
+    pub fn set_line_number(&mut self, line_number: u32) {
+        self.line_number = line_number;
+    }
+
+    /// Returns the column in the source line, if known.
+    pub fn column_number(&self) -> Option<u32> {
+        self.column_number
+    }
+
+    /// Sets the column in the source line.
+    pub fn set_column_number(&mut self, column_number: u32) {
+        self.column_number = Some(column_number);
+    }
+
+    /// Returns the module of the method, e.g. `app::handlers`.
+    pub fn module_path(&self) -> Option<&str> {
+        self.module_path.as_deref()
+    }
+
+    /// Sets the module of the method.
+    pub fn set_module_path(&mut self, module_path: &str) {
+        self.module_path = Some(module_path.to_owned());
+    }
+
+    /// Sets the module path from the method.
+    fn with_module_path(mut self) -> Frame {
+        self.module_path = module_path(&self.method).map(str::to_owned);
+        self
+    }
+
+    /// Returns the function of the frame.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Sets the function of the frame.
+    pub fn set_method(&mut self, method: &str) {
+        self.method = method.to_owned();
+    }
+
+    /// Returns whether the frame belongs to the project, see `Frame::new`.
+    pub fn in_project(&self) -> bool {
+        self.in_project
+    }
+
+    /// Sets whether the frame belongs to the project, see `Frame::new`.
+    pub fn set_in_project(&mut self, in_project: bool) {
+        self.in_project = in_project;
+    }
+
+    /// Returns the address of the frame, if the frame has no symbol information.
+    pub fn frame_address(&self) -> Option<&str> {
+        self.frame_address.as_deref()
+    }
+
+    /// Annotates the frame with the crate that owns its file, if the file lies in the
+    /// cargo registry.
+    fn with_crate_from_path(mut self) -> Frame {
+        if let Some((name, version)) = crate_from_path(&self.file) {
+            self.crate_name = Some(name.to_owned());
+            self.crate_version = Some(version.to_owned());
+        }
+        self
+    }
+
     /// Converts from a backtrace::Symbol into a Frame
     ///
     /// # Arguments
@@ -45,58 +200,432 @@ impl Frame {
             None => "unknown".to_string(),
         };
 
-        Frame::new(
-            file,
-            linenumber,
-            method.as_str(),
-            in_project(file, method.as_str()),
-        )
+        let in_proj = in_project(file, method.as_str());
+        let mut frame = Frame::new(file, linenumber, method.as_str(), in_proj).with_module_path();
+        frame.column_number = trace.colno();
+
+        if in_proj {
+            frame
+        } else {
+            frame.with_crate_from_path()
+        }
+    }
+}
+
+/// Attaches the lines of source code around the line of each frame in project, if its
+/// file can be read. Relative paths are resolved against `project_dir`. Each file is read
+/// only once.
+pub fn attach_source_snippets(frames: &mut [Frame], project_dir: &str) {
+    let mut files: HashMap<String, Option<Vec<String>>> = HashMap::new();
+
+    for frame in frames
+        .iter_mut()
+        .filter(|f| f.in_project && f.line_number > 0)
+    {
+        let lines = files.entry(frame.file.clone()).or_insert_with(|| {
+            let path = Path::new(project_dir).join(&frame.file);
+            let source = std::fs::read_to_string(path).ok()?;
+            Some(source.lines().map(truncate_line).collect())
+        });
+        let Some(lines) = lines else {
+            continue;
+        };
+
+        let first = frame.line_number.saturating_sub(SNIPPET_CONTEXT).max(1);
+        let last = frame.line_number.saturating_add(SNIPPET_CONTEXT);
+        let code = (first..=last)
+            .filter_map(|n| Some((n, lines.get(n as usize - 1)?.clone())))
+            .collect::<BTreeMap<_, _>>();
+        if !code.is_empty() {
+            frame.code = Some(code);
+        }
+    }
+}
+
+fn truncate_line(line: &str) -> String {
+    let mut end = line.len().min(MAX_SNIPPET_LINE_LEN);
+    while !line.is_char_boundary(end) {
+        end -= 1;
     }
+    line[..end].to_owned()
 }
 
-/// Create a stacktrace and returns this stacktrace as vector of Frames
+/// Returns the name and version of the crate a file belongs to, if the file lies in the
+/// cargo registry, e.g. `("serde", "1.0.200")` for
+/// `~/.cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.200/src/de.rs`.
+pub fn crate_from_path(file: &str) -> Option<(&str, &str)> {
+    let mut components = file.split(['/', '\\']);
+    components.by_ref().find(|c| *c == "registry")?;
+    if components.next()? != "src" {
+        return None;
+    }
+    // skip the directory of the index
+    components.next()?;
+    let dir = components.next()?;
+
+    dir.char_indices()
+        .filter(|&(i, c)| c == '-' && dir[i + 1..].starts_with(|c: char| c.is_ascii_digit()))
+        .map(|(i, _)| (&dir[..i], &dir[i + 1..]))
+        .next()
+        .filter(|(name, _)| !name.is_empty())
+}
+
+/// Returns the name of the crate a demangled method belongs to, e.g. `my_app` for
+/// `<my_app::Foo as core::fmt::Debug>::fmt`.
+pub fn crate_name(method: &str) -> Option<&str> {
+    let path = method.trim_start_matches(['<', '&', '*']);
+    let path = path.strip_prefix("mut ").unwrap_or(path);
+    let (name, _) = path.split_once("::")?;
+
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Returns the module a demangled method belongs to, e.g. `app::handlers` for
+/// `app::handlers::checkout::{{closure}}::h0123456789abcdef`. Methods of trait
+/// implementations, e.g. `<app::Foo as core::fmt::Debug>::fmt`, have no module path.
+pub fn module_path(method: &str) -> Option<&str> {
+    let mut path = strip_symbol_hash(method);
+    if path.starts_with('<') {
+        return None;
+    }
+    // generic arguments of the v0 symbol mangling, e.g. `app::parse::<u32>`
+    if let Some(i) = path.find("::<") {
+        path = &path[..i];
+    }
+    // and of the legacy symbol mangling, e.g. `core::ptr::drop_in_place<app::Foo>`
+    if path.ends_with('>') {
+        if let Some(i) = path.find('<') {
+            path = &path[..i];
+        }
+    }
+    while let Some(outer) = path.strip_suffix("::{{closure}}") {
+        path = outer;
+    }
+    let (module, _) = path.rsplit_once("::")?;
+    Some(module)
+}
+
+/// Removes the hash that rustc appends to symbol names, e.g. `::h0123456789abcdef`.
+pub fn strip_symbol_hash(method: &str) -> &str {
+    match method.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            path
+        }
+        _ => method,
+    }
+}
+
+/// Computes a grouping hash that does not change between builds of the same code.
+///
+/// Only the error class, the symbol names without their hash and the file paths
+/// relative to `project_dir` of the frames in project are used, line numbers and
+/// addresses are ignored. Consecutive frames of the same function, as produced by
+/// inlining, are merged into one.
+pub fn grouping_hash(error_class: &str, frames: &[Frame], project_dir: &str) -> String {
+    let mut hasher = StableHasher::default();
+    hasher.write(error_class.as_bytes());
+
+    let in_project = frames.iter().any(|f| f.in_project);
+    let mut last = None;
+    for frame in frames.iter().filter(|f| f.in_project || !in_project) {
+        let method = strip_symbol_hash(&frame.method);
+        let file = frame
+            .file
+            .strip_prefix(project_dir)
+            .unwrap_or(&frame.file)
+            .trim_start_matches(['/', '\\']);
+
+        if last == Some((method, file)) {
+            continue;
+        }
+        hasher.write(method.as_bytes());
+        hasher.write(file.as_bytes());
+        last = Some((method, file));
+    }
+
+    hasher.finish_hex()
+}
+
+/// Maximum number of frames recorded by `RawFrames::capture`.
+pub const MAX_RAW_FRAMES: usize = 64;
+
+/// The frames of a stack, recorded into a fixed-size array without allocating. Only the
+/// instruction pointers are kept, symbols are resolved when the frames are attached to a
+/// notification with `NotifyBuilder::raw_frames`. Use this in code that must not allocate
+/// when an error occurs, e.g. real-time audio or game loops. Recording is not
+/// async-signal-safe, see `RawFrames::capture`.
+#[derive(Clone)]
+pub struct RawFrames {
+    frames: [Option<backtrace::Frame>; MAX_RAW_FRAMES],
+    len: usize,
+}
+
+impl fmt::Debug for RawFrames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.frames().map(|frame| frame.ip()))
+            .finish()
+    }
+}
+
+impl RawFrames {
+    /// Records the innermost `MAX_RAW_FRAMES` frames of the current stack. This does not
+    /// allocate, but takes the global lock the `backtrace` crate uses while unwinding, so
+    /// it may block while another thread captures a backtrace. It must not be called from
+    /// a signal handler, which may deadlock if the interrupted code holds the lock.
+    #[inline(never)]
+    pub fn capture() -> RawFrames {
+        const NONE: Option<backtrace::Frame> = None;
+        let mut raw = RawFrames {
+            frames: [NONE; MAX_RAW_FRAMES],
+            len: 0,
+        };
+        backtrace::trace(|frame| {
+            // cloning only copies the addresses of the frame
+            raw.frames[raw.len] = Some(frame.clone());
+            raw.len += 1;
+            raw.len < MAX_RAW_FRAMES
+        });
+        raw
+    }
+
+    /// Returns the number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn frames(&self) -> impl Iterator<Item = &backtrace::Frame> {
+        self.frames[..self.len].iter().flatten()
+    }
+
+    /// Converts the frames into an unresolved backtrace.
+    pub(crate) fn to_backtrace(&self) -> Backtrace {
+        self.frames()
+            .cloned()
+            .map(BacktraceFrame::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Captures the frames of the current stack without resolving their symbols, which is
+/// cheap enough to do whenever a notification is created.
+pub fn capture() -> Backtrace {
+    Backtrace::new_unresolved()
+}
+
+/// Resolves the symbols of a captured stack and returns the stacktrace as vector of Frames
 ///
 /// # Arguments
 ///
+/// * `backtrace` - The stack captured with `capture`.
 /// * `in_project` - A function that gets the following arguments (file, method). The function is
-///                  used to determine if a file and method belongs to the project.
+///   used to determine if a file and method belongs to the project.
 ///
 /// # Remarks
 ///
 /// Bugsnag will use the information about a frame belonging to a project to hide
 /// unnecessary information in the web interface.
-pub fn create_stacktrace<F>(in_project: &F) -> Vec<Frame>
+pub fn create_stacktrace<F>(backtrace: &Backtrace, in_project: &F) -> Vec<Frame>
 where
     F: Fn(&str, &str) -> bool,
 {
     let mut result: Vec<Frame> = Vec::new();
 
-    backtrace::trace(|frame| {
+    for frame in backtrace.frames() {
+        let mut resolved = false;
         backtrace::resolve(frame.ip(), |symbol| {
-            result.push(Frame::from_symbol(symbol, in_project))
+            if symbol.name().is_some() || symbol.filename().is_some() {
+                resolved = true;
+                result.push(Frame::from_symbol(symbol, in_project))
+            }
+        });
+
+        if !resolved {
+            result.push(Frame::unsymbolicated(frame.ip() as usize, module_of(frame)));
+        }
+    }
+
+    result
+}
+
+/// Resolves the symbols of instruction pointers, e.g. of the stack of another thread, and
+/// returns the stacktrace as vector of Frames. See `create_stacktrace`.
+pub fn create_stacktrace_from_ips<F>(ips: &[usize], in_project: &F) -> Vec<Frame>
+where
+    F: Fn(&str, &str) -> bool,
+{
+    let mut result: Vec<Frame> = Vec::new();
+
+    for &ip in ips {
+        let mut resolved = false;
+        backtrace::resolve(ip as *mut std::ffi::c_void, |symbol| {
+            if symbol.name().is_some() || symbol.filename().is_some() {
+                resolved = true;
+                result.push(Frame::from_symbol(symbol, in_project))
+            }
         });
-        true
-    });
+
+        if !resolved {
+            result.push(Frame::unsymbolicated(ip, module_of_ip(ip)));
+        }
+    }
 
     result
 }
 
+/// Parses the `Display` output of a `std::backtrace::Backtrace` into frames, as the
+/// standard library has no API to access the frames of a backtrace. Each symbol is
+/// followed by an indented line with its location:
+///
+/// ```text
+///    0: app::main
+///              at ./src/main.rs:2:28
+/// ```
+pub fn parse_std_backtrace<F>(text: &str, in_project: &F) -> Vec<Frame>
+where
+    F: Fn(&str, &str) -> bool,
+{
+    type Location<'t> = (&'t str, u32, Option<u32>);
+    let mut symbols: Vec<(&str, Option<Location>)> = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if let Some(location) = line.strip_prefix("at ") {
+            let mut parts = location.rsplitn(3, ':');
+            let (column, line, file) = (parts.next(), parts.next(), parts.next());
+            let location = match (file, line.and_then(|l| l.parse().ok()), column) {
+                (Some(file), Some(line), Some(column)) => (file, line, column.parse().ok()),
+                _ => (location, 0, None),
+            };
+            if let Some(last) = symbols.last_mut() {
+                last.1 = Some(location);
+            }
+        } else if !line.is_empty() {
+            // symbols inlined into the same frame are listed without an index
+            let method = match line.split_once(": ") {
+                Some((index, method)) if index.chars().all(|c| c.is_ascii_digit()) => method,
+                _ => line,
+            };
+            symbols.push((method, None));
+        }
+    }
+
+    symbols
+        .into_iter()
+        .map(|(method, location)| {
+            let (file, line, column) = location.unwrap_or(("", 0, None));
+            let in_proj = in_project(file, method);
+            let mut frame = Frame::new(file, line, method, in_proj).with_module_path();
+            frame.column_number = column;
+            if in_proj {
+                frame
+            } else {
+                frame.with_crate_from_path()
+            }
+        })
+        .collect()
+}
+
+/// Returns the raw addresses of a captured stack, with the load address and path of the
+/// module of each frame, for symbolication by an external service.
+pub fn raw_addresses(backtrace: &Backtrace) -> Vec<serde_json::Value> {
+    backtrace
+        .frames()
+        .iter()
+        .map(|frame| {
+            let ip = frame.ip() as usize;
+            match module_of(frame) {
+                Some((path, base)) => json!({
+                    "frameAddress": format!("0x{ip:x}"),
+                    "loadAddress": format!("0x{base:x}"),
+                    "module": path,
+                }),
+                None => json!({"frameAddress": format!("0x{ip:x}")}),
+            }
+        })
+        .collect()
+}
+
+/// Returns the path and the load address of the module that contains a frame.
+#[cfg(unix)]
+fn module_of(frame: &BacktraceFrame) -> Option<(String, usize)> {
+    module_of_ip(frame.ip() as usize)
+}
+
+/// Returns the path and the load address of the module that contains an address.
+#[cfg(unix)]
+fn module_of_ip(ip: usize) -> Option<(String, usize)> {
+    // SAFETY: `Dl_info` is a plain C struct, for which all zeros is a valid value.
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    // SAFETY: dladdr only looks up the address in the list of loaded modules, it does not
+    // dereference `ip`, and writes to `info`, which is valid for writes.
+    if unsafe { libc::dladdr(ip as *const std::ffi::c_void, &mut info) } == 0
+        || info.dli_fname.is_null()
+    {
+        return None;
+    }
+
+    // SAFETY: `dli_fname` is not null and points to the nul-terminated path of the module,
+    // which stays valid while the module is loaded. It is copied right away.
+    let path = unsafe { std::ffi::CStr::from_ptr(info.dli_fname) }
+        .to_string_lossy()
+        .into_owned();
+    // the main executable may be reported by the name it was started with
+    let path = if Path::new(&path).is_absolute() {
+        path
+    } else {
+        std::env::current_exe()
+            .map(|exe| exe.to_string_lossy().into_owned())
+            .unwrap_or(path)
+    };
+
+    Some((path, info.dli_fbase as usize))
+}
+
+/// Returns the path and the load address of the module that contains a frame.
+#[cfg(not(unix))]
+fn module_of(frame: &BacktraceFrame) -> Option<(String, usize)> {
+    frame
+        .module_base_address()
+        .map(|base| (String::new(), base as usize))
+}
+
+/// The modules of bare addresses are only looked up on unix.
+#[cfg(not(unix))]
+fn module_of_ip(_ip: usize) -> Option<(String, usize)> {
+    None
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{create_stacktrace, Frame};
+    use super::{
+        attach_source_snippets, capture, crate_from_path, crate_name, create_stacktrace,
+        grouping_hash, module_path, parse_std_backtrace, strip_symbol_hash, Frame, RawFrames,
+    };
     use serde_json::json;
 
     #[test]
     #[ignore]
+    #[allow(clippy::collapsible_if)]
     fn test_create_stacktrace() {
         let file = file!();
-        let frames = create_stacktrace(&|f, _| f.ends_with(&file));
+        let frames = create_stacktrace(&capture(), &|f, _| f.ends_with(&file));
         let mut found_frame = false;
 
         for frame in frames {
             if frame.method == "bugsnag::stacktrace::tests::test_create_stacktrace" {
                 if frame.file.ends_with(file) {
                     if frame.in_project {
+                        assert_eq!(frame.module_path(), Some("bugsnag::stacktrace::tests"));
+                        assert!(frame.column_number().is_some());
                         found_frame = true;
                         break;
                     }
@@ -107,6 +636,142 @@ mod tests {
         assert!(found_frame);
     }
 
+    #[test]
+    fn test_crate_name() {
+        assert_eq!(crate_name("my_app::main::h1234"), Some("my_app"));
+        assert_eq!(
+            crate_name("<my_app::Foo as core::fmt::Debug>::fmt"),
+            Some("my_app")
+        );
+        assert_eq!(
+            crate_name("<&mut serde_json::Value as x::Y>::z"),
+            Some("serde_json")
+        );
+        assert_eq!(crate_name("main"), None);
+        assert_eq!(crate_name("<[T] as x::Y>::z"), None);
+    }
+
+    #[test]
+    fn test_crate_from_path() {
+        assert_eq!(
+            crate_from_path(
+                "/home/me/.cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.200/src/de.rs"
+            ),
+            Some(("serde", "1.0.200"))
+        );
+        assert_eq!(
+            crate_from_path(
+                "C:\\Users\\me\\.cargo\\registry\\src\\index.crates.io-1949cf8c6b5b557f\\tokio-util-0.7.0-alpha.1\\src\\lib.rs"
+            ),
+            Some(("tokio-util", "0.7.0-alpha.1"))
+        );
+        assert_eq!(crate_from_path("/build/my-app/src/main.rs"), None);
+        assert_eq!(
+            crate_from_path("/rustc/90b35a6/library/std/src/panicking.rs"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_strip_symbol_hash() {
+        assert_eq!(
+            strip_symbol_hash("my_app::main::h0123456789abcdef"),
+            "my_app::main"
+        );
+        assert_eq!(strip_symbol_hash("my_app::main"), "my_app::main");
+        assert_eq!(strip_symbol_hash("my_app::hello"), "my_app::hello");
+    }
+
+    #[test]
+    fn test_module_path() {
+        assert_eq!(
+            module_path("app::handlers::checkout::{{closure}}::{{closure}}::h0123456789abcdef"),
+            Some("app::handlers")
+        );
+        assert_eq!(module_path("app::parse::<u32>"), Some("app"));
+        assert_eq!(
+            module_path("core::ptr::drop_in_place<app::Foo>"),
+            Some("core::ptr")
+        );
+        assert_eq!(module_path("<app::Foo as core::fmt::Debug>::fmt"), None);
+        assert_eq!(module_path("main"), None);
+    }
+
+    #[test]
+    fn test_grouping_hash() {
+        let build_a = vec![
+            Frame::new(
+                "/ci/1/src/lib.rs",
+                10,
+                "app::parse::h0123456789abcdef",
+                true,
+            ),
+            Frame::new(
+                "/ci/1/src/lib.rs",
+                12,
+                "app::parse::h0123456789abcdef",
+                true,
+            ),
+            Frame::new("/ci/1/src/main.rs", 3, "app::main::h0123456789abcdef", true),
+            Frame::new(
+                "/rustc/library/std/src/rt.rs",
+                1,
+                "std::rt::lang_start",
+                false,
+            ),
+        ];
+        let build_b = vec![
+            Frame::new(
+                "/ci/2/src/lib.rs",
+                11,
+                "app::parse::hfedcba9876543210",
+                true,
+            ),
+            Frame::new("/ci/2/src/main.rs", 4, "app::main::hfedcba9876543210", true),
+        ];
+
+        assert_eq!(
+            grouping_hash("Error", &build_a, "/ci/1"),
+            grouping_hash("Error", &build_b, "/ci/2")
+        );
+        assert_ne!(
+            grouping_hash("Error", &build_a, "/ci/1"),
+            grouping_hash("OtherError", &build_a, "/ci/1")
+        );
+    }
+
+    #[test]
+    fn test_raw_addresses() {
+        let backtrace = capture();
+        let addresses = super::raw_addresses(&backtrace);
+
+        assert_eq!(addresses.len(), backtrace.frames().len());
+        assert!(addresses[0]["frameAddress"]
+            .as_str()
+            .unwrap()
+            .starts_with("0x"));
+    }
+
+    #[test]
+    fn test_unsymbolicated_frame_to_json() {
+        let frame = Frame::unsymbolicated(
+            0x5555_0000_1234,
+            Some(("/app".to_owned(), 0x5555_0000_0000)),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&frame).unwrap(),
+            json!({
+                "file": "/app",
+                "lineNumber": 0,
+                "method": "0x1234",
+                "inProject": false,
+                "frameAddress": "0x555500001234",
+                "loadAddress": "0x555500000000"
+            })
+        );
+    }
+
     #[test]
     fn test_frame_to_json() {
         let frame = Frame::new("test.rs", 500, "test_json", false);
@@ -120,13 +785,67 @@ mod tests {
                 "inProject": false
             })
         );
+
+        let frame = Frame::new(
+            "/.cargo/registry/src/index.crates.io-6f17d22bba15001f/log-0.4.21/src/lib.rs",
+            10,
+            "log::log",
+            false,
+        )
+        .with_crate_from_path();
+
+        assert_eq!(
+            serde_json::to_value(&frame).unwrap(),
+            json!({
+                "file": "/.cargo/registry/src/index.crates.io-6f17d22bba15001f/log-0.4.21/src/lib.rs",
+                "lineNumber": 10,
+                "method": "log::log",
+                "inProject": false,
+                "crateName": "log",
+                "crateVersion": "0.4.21"
+            })
+        );
+    }
+
+    #[test]
+    fn test_attach_source_snippets() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_snippets_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let source = (1..=10)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(dir.join("src/main.rs"), source + &"x".repeat(300)).unwrap();
+
+        let mut frames = vec![
+            Frame::new("src/main.rs", 2, "app::main", true),
+            Frame::new("src/main.rs", 10, "app::run", true),
+            Frame::new("src/main.rs", 2, "std::rt::lang_start", false),
+            Frame::new("src/missing.rs", 2, "app::missing", true),
+        ];
+        attach_source_snippets(&mut frames, dir.to_str().unwrap());
+
+        let json = serde_json::to_value(&frames).unwrap();
+        assert_eq!(
+            json[0]["code"],
+            json!({"1": "line 1", "2": "line 2", "3": "line 3", "4": "line 4", "5": "line 5"})
+        );
+        let code = json[1]["code"].as_object().unwrap();
+        assert_eq!(code.keys().collect::<Vec<_>>(), ["10", "7", "8", "9"]);
+        assert_eq!(code["10"].as_str().unwrap().len(), 200);
+        assert!(json[2].get("code").is_none());
+        assert!(json[3].get("code").is_none());
+
+        std::fs::remove_dir_all(dir).ok();
     }
 
     #[test]
     #[ignore]
+    #[allow(clippy::collapsible_if, clippy::bool_comparison)]
     fn test_create_stacktrace_with_ignore() {
-        let frames =
-            create_stacktrace(&|_, method| !method.contains("test_create_stacktrace_with_ignore"));
+        let frames = create_stacktrace(&capture(), &|_, method| {
+            !method.contains("test_create_stacktrace_with_ignore")
+        });
         let mut found_frame = false;
         let file = file!();
 
@@ -143,4 +862,53 @@ mod tests {
 
         assert!(found_frame);
     }
+
+    #[test]
+    fn test_raw_frames() {
+        let raw = RawFrames::capture();
+        assert!(!raw.is_empty());
+
+        let backtrace = raw.to_backtrace();
+        assert_eq!(backtrace.frames().len(), raw.len());
+        let frames = create_stacktrace(&backtrace, &|_, _| false);
+        assert!(frames
+            .iter()
+            .any(|frame| frame.method.contains("test_raw_frames")));
+    }
+
+    #[test]
+    fn test_parse_std_backtrace() {
+        let text = "   0: app::config::load
+             at ./src/config.rs:12:5
+      app::config::read
+             at ./src/config.rs:30:9
+   1: std::rt::lang_start
+             at /rustc/abc/library/std/src/rt.rs:206:18
+   2: __libc_start_main
+";
+        let frames = parse_std_backtrace(text, &|file, _| file.starts_with("./src"));
+        assert_eq!(frames[0].column_number(), Some(5));
+        assert_eq!(frames[0].module_path(), Some("app::config"));
+        assert_eq!(frames[3].column_number(), None);
+        assert_eq!(frames[3].module_path(), None);
+
+        let frames: Vec<_> = frames
+            .iter()
+            .map(|f| (f.method(), f.file(), f.line_number(), f.in_project()))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                ("app::config::load", "./src/config.rs", 12, true),
+                ("app::config::read", "./src/config.rs", 30, true),
+                (
+                    "std::rt::lang_start",
+                    "/rustc/abc/library/std/src/rt.rs",
+                    206,
+                    false
+                ),
+                ("__libc_start_main", "", 0, false),
+            ]
+        );
+    }
 }
@@ -0,0 +1,381 @@
+//! Module with helpers for testing the integration of Bugsnag in an application.
+//!
+//! Enabled by the `testing` feature, which should only be used in `dev-dependencies`.
+
+use super::DEFAULT_INTERNAL_TAB;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[doc(hidden)]
+pub use serde_json;
+
+type PayloadMatcher = dyn Fn(&str) -> bool + Send + Sync;
+
+/// Makes deliveries fail as if the endpoint was unreachable, to test the offline storage
+/// and retry behavior of an application deterministically.
+///
+/// # Example
+///
+/// ```
+/// use bugsnag::testing::FailureInjection;
+/// use bugsnag::transport::TransportOptions;
+///
+/// let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+/// api.set_transport_options(
+///     TransportOptions::new().failure_injection(FailureInjection::new().fail_first(2)),
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct FailureInjection {
+    remaining: Arc<AtomicUsize>,
+    matcher: Option<Arc<PayloadMatcher>>,
+}
+
+impl fmt::Debug for FailureInjection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FailureInjection")
+            .field("remaining", &self.remaining.load(Ordering::Relaxed))
+            .field("matcher", &self.matcher.is_some())
+            .finish()
+    }
+}
+
+impl FailureInjection {
+    /// Creates a failure injection that lets all deliveries pass.
+    pub fn new() -> FailureInjection {
+        FailureInjection::default()
+    }
+
+    /// Fails the next `n` deliveries.
+    pub fn fail_first(self, n: usize) -> Self {
+        self.remaining.store(n, Ordering::Relaxed);
+        self
+    }
+
+    /// Fails all deliveries whose json payload matches `matcher`.
+    pub fn fail_matching<F>(mut self, matcher: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.matcher = Some(Arc::new(matcher));
+        self
+    }
+
+    /// Checks if the delivery of `json` should fail.
+    pub(crate) fn should_fail(&self, json: &str) -> bool {
+        let counted = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+
+        counted || self.matcher.as_ref().is_some_and(|m| m(json))
+    }
+}
+
+/// Number of live `Suppressed` guards.
+static SUPPRESSED: AtomicUsize = AtomicUsize::new(0);
+
+/// Payloads delivered to the memory transport.
+static DELIVERED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Guard returned by `suppress`. Deliveries go to memory until all guards are dropped.
+#[must_use = "deliveries are no longer suppressed when the guard is dropped"]
+#[derive(Debug)]
+pub struct Suppressed {
+    first: usize,
+}
+
+impl Suppressed {
+    /// Returns the json payloads delivered since the guard was created. Tests running
+    /// in parallel share the memory transport, so filter for the expected payloads.
+    pub fn deliveries(&self) -> Vec<String> {
+        DELIVERED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(self.first..)
+            .map(|d| d.to_vec())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Suppressed {
+    fn drop(&mut self) {
+        if SUPPRESSED.fetch_sub(1, Ordering::SeqCst) == 1 {
+            DELIVERED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+        }
+    }
+}
+
+/// Routes the deliveries of all `Bugsnag` instances of the process to memory while the
+/// returned guard lives, so tests of code that notifies never reach the network, even
+/// if it constructs a real client. The deliveries are reported as `Outcome::Captured`.
+/// As the guard affects the whole process, tests that check real deliveries should not
+/// run in the same process.
+///
+/// # Example
+///
+/// ```
+/// let suppressed = bugsnag::testing::suppress();
+///
+/// let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+/// api.notify("Error", "never sent").send().unwrap();
+///
+/// assert!(suppressed.deliveries().iter().any(|json| json.contains("never sent")));
+/// ```
+pub fn suppress() -> Suppressed {
+    let delivered = DELIVERED.lock().unwrap_or_else(PoisonError::into_inner);
+    SUPPRESSED.fetch_add(1, Ordering::SeqCst);
+    Suppressed {
+        first: delivered.len(),
+    }
+}
+
+/// Delivers `json` to memory if deliveries are suppressed. Returns `false` otherwise.
+pub(crate) fn deliver_to_memory(json: &str) -> bool {
+    let mut delivered = DELIVERED.lock().unwrap_or_else(PoisonError::into_inner);
+    if SUPPRESSED.load(Ordering::SeqCst) == 0 {
+        return false;
+    }
+    delivered.push(json.to_owned());
+    true
+}
+
+/// Fields that differ between runs and are ignored by `compare_event`, besides the
+/// internal tab, see `Bugsnag::set_internal_tab`.
+pub const VOLATILE_FIELDS: [&str; 3] =
+    ["device.time", "device.hostname", "exceptions.*.stacktrace"];
+
+/// Names of the internal tabs set with `Bugsnag::set_internal_tab` in this process, which
+/// are ignored by `compare_event` like the default name.
+static INTERNAL_TABS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+pub(crate) fn register_internal_tab(name: &str) {
+    INTERNAL_TABS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(name.to_owned());
+}
+
+/// Returns the paths of the internal tabs, whose content differs between runs.
+fn internal_tab_paths() -> Vec<String> {
+    let tabs = INTERNAL_TABS.lock().unwrap_or_else(PoisonError::into_inner);
+    std::iter::once(DEFAULT_INTERNAL_TAB)
+        .chain(tabs.iter().map(String::as_str))
+        .map(|tab| format!("metaData.{tab}"))
+        .collect()
+}
+
+/// Removes the field at `path` from `value`. `*` matches every key or index.
+fn remove_path(value: &mut Value, path: &[&str]) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let matches = |key: &str| *first == "*" || *first == key;
+
+    match value {
+        Value::Object(map) if rest.is_empty() => map.retain(|key, _| !matches(key)),
+        Value::Object(map) => map
+            .iter_mut()
+            .filter(|(key, _)| matches(key))
+            .for_each(|(_, child)| remove_path(child, rest)),
+        Value::Array(items) if rest.is_empty() => {
+            let mut i = 0;
+            items.retain(|_| {
+                i += 1;
+                !matches(&(i - 1).to_string())
+            });
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, _)| matches(&i.to_string()))
+            .for_each(|(_, child)| remove_path(child, rest)),
+        _ => {}
+    }
+}
+
+/// Checks that `actual` contains `expected`. Objects may have more keys than expected,
+/// arrays must have the same length.
+fn contains(actual: &Value, expected: &Value, path: &str) -> Result<(), String> {
+    let mismatch = || {
+        Err(format!(
+            "mismatch at `{}`: expected {expected}, got {actual}",
+            if path.is_empty() { "." } else { path }
+        ))
+    };
+    let child = |key: &dyn fmt::Display| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => {
+            for (key, value) in expected {
+                contains(actual.get(key).unwrap_or(&Value::Null), value, &child(key))?;
+            }
+            Ok(())
+        }
+        (Value::Array(items), Value::Array(expected_items)) => {
+            if items.len() != expected_items.len() {
+                return mismatch();
+            }
+            for (i, (item, value)) in items.iter().zip(expected_items).enumerate() {
+                contains(item, value, &child(&i))?;
+            }
+            Ok(())
+        }
+        _ if actual == expected => Ok(()),
+        _ => mismatch(),
+    }
+}
+
+/// Compares a captured event with the expected json. `actual` is either a delivered
+/// payload, whose first event is compared, or a single event. Only the keys of the
+/// expected objects are compared. The `VOLATILE_FIELDS`, the internal tab and the
+/// `ignored` fields, given as dot separated paths in which `*` matches every key or index,
+/// are removed from both sides before comparing. Returns a description of the first
+/// mismatch.
+pub fn compare_event(actual: &str, expected: &Value, ignored: &[&str]) -> Result<(), String> {
+    let actual: Value =
+        serde_json::from_str(actual).map_err(|e| format!("invalid event json: {e}"))?;
+    let mut actual = match actual.get("events") {
+        Some(events) => events
+            .get(0)
+            .cloned()
+            .ok_or_else(|| "payload contains no events".to_owned())?,
+        None => actual,
+    };
+    let mut expected = expected.clone();
+
+    let internal_tabs = internal_tab_paths();
+    let internal_tabs = internal_tabs.iter().map(String::as_str);
+    for path in VOLATILE_FIELDS
+        .iter()
+        .copied()
+        .chain(internal_tabs)
+        .chain(ignored.iter().copied())
+    {
+        let path = path.split('.').collect::<Vec<_>>();
+        remove_path(&mut actual, &path);
+        remove_path(&mut expected, &path);
+    }
+    contains(&actual, &expected, "")
+}
+
+/// Asserts that a captured event matches the expected json, see `compare_event`.
+///
+/// # Example
+///
+/// ```
+/// let suppressed = bugsnag::testing::suppress();
+///
+/// let mut api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+/// api.set_context("checkout");
+/// api.notify("Payment::Declined", "card expired").send().unwrap();
+///
+/// let delivery = suppressed
+///     .deliveries()
+///     .into_iter()
+///     .find(|json| json.contains("card expired"))
+///     .unwrap();
+/// bugsnag::testing::assert_event!(delivery, {
+///     "exceptions": [{"errorClass": "Payment::Declined", "message": "card expired"}],
+///     "context": "checkout",
+/// });
+/// bugsnag::testing::assert_event!(delivery, {"context": "other"}, ignore = ["context"]);
+/// ```
+#[macro_export]
+macro_rules! assert_event {
+    ($actual:expr, $expected:tt $(,)?) => {
+        $crate::assert_event!($actual, $expected, ignore = [])
+    };
+    ($actual:expr, $expected:tt, ignore = [$($path:expr),* $(,)?] $(,)?) => {
+        if let Err(mismatch) = $crate::testing::compare_event(
+            ::std::convert::AsRef::<str>::as_ref(&$actual),
+            &$crate::testing::serde_json::json!($expected),
+            &[$($path),*],
+        ) {
+            panic!("event does not match: {}", mismatch);
+        }
+    };
+}
+
+pub use crate::assert_event;
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_event, FailureInjection};
+    use serde_json::json;
+
+    #[test]
+    fn test_fail_first() {
+        let injection = FailureInjection::new().fail_first(2);
+        assert!(injection.should_fail("{}"));
+        assert!(injection.clone().should_fail("{}"));
+        assert!(!injection.should_fail("{}"));
+    }
+
+    #[test]
+    fn test_fail_matching() {
+        let injection = FailureInjection::new().fail_matching(|json| json.contains("Timeout"));
+        assert!(injection.should_fail(r#"{"errorClass":"Timeout"}"#));
+        assert!(!injection.should_fail(r#"{"errorClass":"Other"}"#));
+    }
+
+    #[test]
+    fn test_compare_event() {
+        let payload = json!({"events": [{
+            "exceptions": [{"errorClass": "Error", "stacktrace": [{"file": "a.rs"}]}],
+            "device": {"hostname": "host-1", "time": "2024-01-01T00:00:00Z"},
+            "context": "checkout",
+        }]})
+        .to_string();
+
+        let expected = json!({
+            "exceptions": [{"errorClass": "Error", "stacktrace": []}],
+            "device": {"hostname": "other"},
+            "context": "checkout",
+        });
+        assert_eq!(compare_event(&payload, &expected, &[]), Ok(()));
+
+        assert_eq!(
+            compare_event(&payload, &json!({"context": "cart"}), &[]),
+            Err("mismatch at `context`: expected \"cart\", got \"checkout\"".to_owned())
+        );
+        assert_eq!(
+            compare_event(&payload, &json!({"context": "cart"}), &["context"]),
+            Ok(())
+        );
+        assert!(compare_event(&payload, &json!({"exceptions": []}), &[]).is_err());
+    }
+
+    #[test]
+    fn test_compare_event_ignores_internal_tab() {
+        let mut api = crate::Bugsnag::new("api-key", "my-dir");
+        api.set_internal_tab(Some("notifier"));
+        let payload = json!({"events": [{
+            "metaData": {
+                "bugsnag-rs": {"eventId": "a"},
+                "notifier": {"eventId": "b"},
+                "order": {"id": 1},
+            },
+        }]})
+        .to_string();
+
+        let expected = json!({"metaData": {
+            "bugsnag-rs": {"eventId": "c"},
+            "notifier": {"eventId": "d"},
+            "order": {"id": 1},
+        }});
+        assert_eq!(compare_event(&payload, &expected, &[]), Ok(()));
+    }
+}
@@ -0,0 +1,348 @@
+//! Module for capturing the threads of the process, for
+//! `NotifyBuilder::capture_all_threads` and `NotifyBuilder::capture_all_thread_stacks`.
+//!
+//! On Linux, the threads are listed in `/proc/self/task`. If their stacks are captured,
+//! every thread is interrupted with `SIGURG` and records the instruction pointers of its
+//! stack in the signal handler, the symbols are resolved later by the reporting thread.
+//! The handler installed before is restored afterwards, and signals that are not meant
+//! for capturing are passed on to it in the meantime. `SIGURG` is ignored by default, so
+//! a signal that arrives after the handler was restored does no harm. Threads that block
+//! the signal, or do not handle it in time, are reported without stacktrace.
+//!
+//! Unwinding is not async-signal-safe. A handler may never finish, e.g. if the
+//! interrupted thread holds a lock of the unwinder or of the allocator, and then that
+//! thread stays blocked forever. Capturing is given up after a deadline, but this does
+//! not free the blocked thread, which is why capturing the stacks is an explicit opt-in.
+//! On other platforms only the reporting thread is reported.
+
+use super::stacktrace::Frame;
+use serde::Serialize;
+
+/// A thread with the unresolved frames of its stack.
+#[derive(Debug, Clone)]
+pub struct RawThread {
+    pub id: String,
+    pub name: String,
+    /// Whether this is the thread that created the notification. Its stacktrace is the
+    /// one of the notification, so `ips` is empty.
+    pub reporting: bool,
+    pub ips: Vec<usize>,
+}
+
+/// A thread in the `threads` array of an event.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thread {
+    id: String,
+    name: String,
+    error_reporting_thread: bool,
+    stacktrace: Vec<Frame>,
+}
+
+impl Thread {
+    pub fn new(raw: &RawThread, stacktrace: Vec<Frame>) -> Thread {
+        Thread {
+            id: raw.id.clone(),
+            name: raw.name.clone(),
+            error_reporting_thread: raw.reporting,
+            stacktrace,
+        }
+    }
+}
+
+/// Returns the current thread, which is the only thread that can be captured on
+/// platforms without support.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn current_only() -> Vec<RawThread> {
+    let current = std::thread::current();
+    let id = format!("{:?}", current.id());
+    vec![RawThread {
+        id: id
+            .trim_start_matches("ThreadId(")
+            .trim_end_matches(')')
+            .to_owned(),
+        name: current.name().unwrap_or_default().to_owned(),
+        reporting: true,
+        ips: Vec::new(),
+    }]
+}
+
+/// Captures all threads of the process, ordered by thread id. Only if `stacks` is set,
+/// the other threads are interrupted to capture their stacks.
+#[cfg(target_os = "linux")]
+pub fn capture(stacks: bool) -> Vec<RawThread> {
+    linux::capture(stacks).unwrap_or_else(current_only)
+}
+
+/// Captures all threads of the process.
+#[cfg(not(target_os = "linux"))]
+pub fn capture(_stacks: bool) -> Vec<RawThread> {
+    current_only()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::RawThread;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+    use std::sync::{Mutex, PoisonError};
+    use std::time::{Duration, Instant};
+
+    const MAX_FRAMES: usize = 128;
+    /// How long a thread may take to start handling the signal.
+    const TIMEOUT: Duration = Duration::from_millis(100);
+    /// How long capturing the stacks of all threads may take.
+    const DEADLINE: Duration = Duration::from_secs(1);
+    /// No thread is asked for its stack. Linux never uses 0 as thread id.
+    const IDLE: i32 = 0;
+    /// A handler records the stack of its thread.
+    const RECORDING: i32 = -1;
+
+    /// The thread that is asked for its stack. The handler sets it to `RECORDING` when it
+    /// starts, so the stack is only recorded if the capturing thread still waits for it,
+    /// and back to `IDLE` when it is done. A handler that did not finish in time keeps
+    /// `FRAMES` until it is done, no further stacks are recorded in the meantime.
+    static TARGET: AtomicI32 = AtomicI32::new(IDLE);
+    /// The thread whose stack was recorded last.
+    static DONE: AtomicI32 = AtomicI32::new(IDLE);
+    static FRAMES: [AtomicUsize; MAX_FRAMES] = [const { AtomicUsize::new(0) }; MAX_FRAMES];
+    static LEN: AtomicUsize = AtomicUsize::new(0);
+    /// Only one thread captures the stacks at a time, as they share the statics above.
+    static CAPTURE: Mutex<()> = Mutex::new(());
+    /// The `SIGURG` handler installed before capturing and its flags, signals that are
+    /// not meant for capturing are passed on to it. Set before `on_signal` is installed.
+    static PREVIOUS_HANDLER: AtomicUsize = AtomicUsize::new(libc::SIG_DFL);
+    static PREVIOUS_FLAGS: AtomicI32 = AtomicI32::new(0);
+
+    fn gettid() -> i32 {
+        // SAFETY: `gettid` takes no arguments and cannot fail.
+        unsafe { libc::syscall(libc::SYS_gettid) as i32 }
+    }
+
+    extern "C" fn on_signal(
+        signal: libc::c_int,
+        info: *mut libc::siginfo_t,
+        context: *mut libc::c_void,
+    ) {
+        // only async-signal-safe functions may be called here, and errno must be kept
+        // SAFETY: `__errno_location` returns a valid pointer to the errno of this thread.
+        let errno = unsafe { *libc::__errno_location() };
+        let tid = gettid();
+        if TARGET
+            .compare_exchange(tid, RECORDING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            let mut len = 0;
+            // SAFETY: not async-signal-safe, the unwinder may deadlock if the interrupted
+            // code holds one of its locks, which is the documented risk of the opt-in.
+            // The global lock of `backtrace::trace` is skipped, as the interrupted code
+            // may hold it. The closure only stores into atomics.
+            unsafe {
+                backtrace::trace_unsynchronized(|frame| {
+                    FRAMES[len].store(frame.ip() as usize, Ordering::Relaxed);
+                    len += 1;
+                    len < MAX_FRAMES
+                });
+            }
+            LEN.store(len, Ordering::Relaxed);
+            DONE.store(tid, Ordering::SeqCst);
+            TARGET.store(IDLE, Ordering::SeqCst);
+        } else {
+            chain(signal, info, context);
+        }
+        // SAFETY: see above.
+        unsafe { *libc::__errno_location() = errno };
+    }
+
+    /// Passes a signal that is not meant for capturing on to the previous handler.
+    fn chain(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+        let handler = PREVIOUS_HANDLER.load(Ordering::SeqCst);
+        // the default action of `SIGURG` is to ignore it
+        if handler == libc::SIG_DFL || handler == libc::SIG_IGN {
+            return;
+        }
+        if PREVIOUS_FLAGS.load(Ordering::SeqCst) & libc::SA_SIGINFO != 0 {
+            // SAFETY: the previous handler was installed with `SA_SIGINFO`, so it has
+            // this signature, and gets the arguments this handler got from the kernel.
+            let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                unsafe { std::mem::transmute(handler) };
+            handler(signal, info, context);
+        } else {
+            // SAFETY: the previous handler was installed without `SA_SIGINFO`, so it has
+            // this signature.
+            let handler: extern "C" fn(libc::c_int) = unsafe { std::mem::transmute(handler) };
+            handler(signal);
+        }
+    }
+
+    /// Asks a thread for its stack and waits for it. Returns no frames if the thread does
+    /// not start handling the signal within `TIMEOUT`, if the handler is not done by
+    /// `deadline` or if the handler of an earlier thread is still not done.
+    fn stack_of(pid: i32, tid: i32, deadline: Instant) -> Vec<usize> {
+        if TARGET
+            .compare_exchange(IDLE, tid, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Vec::new();
+        }
+        DONE.store(IDLE, Ordering::SeqCst);
+        // SAFETY: `tgkill` only sends a signal, `on_signal` handles it.
+        if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, libc::SIGURG) } != 0 {
+            // e.g. the thread exited in the meantime
+            TARGET.store(IDLE, Ordering::SeqCst);
+            return Vec::new();
+        }
+
+        let start_deadline = deadline.min(Instant::now() + TIMEOUT);
+        while DONE.load(Ordering::SeqCst) != tid {
+            let now = Instant::now();
+            if now >= start_deadline
+                && TARGET
+                    .compare_exchange(tid, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                return Vec::new();
+            }
+            // the handler started, but may never finish
+            if now >= deadline {
+                return Vec::new();
+            }
+            std::thread::yield_now();
+        }
+
+        let len = LEN.load(Ordering::Relaxed);
+        FRAMES[..len]
+            .iter()
+            .map(|ip| ip.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    fn name_of(tid: i32) -> String {
+        std::fs::read_to_string(format!("/proc/self/task/{tid}/comm"))
+            .map(|name| name.trim_end().to_owned())
+            .unwrap_or_default()
+    }
+
+    /// Installs `on_signal` as the `SIGURG` handler, returns the previous handler.
+    fn install() -> Option<libc::sigaction> {
+        // SAFETY: `sigaction` is a plain C struct, for which all zeros is a valid value.
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal
+            as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+            as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART | libc::SA_SIGINFO;
+        // SAFETY: see above.
+        let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+        // SAFETY: all pointers are valid. The previous handler is read and copied for
+        // `chain` before `on_signal` is installed, so no signal is lost in between.
+        // `on_signal` only runs async-signal-safe code, except for the unwinding of the
+        // opt-in.
+        unsafe {
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(libc::SIGURG, std::ptr::null(), &mut previous) != 0 {
+                return None;
+            }
+            PREVIOUS_HANDLER.store(previous.sa_sigaction, Ordering::SeqCst);
+            PREVIOUS_FLAGS.store(previous.sa_flags, Ordering::SeqCst);
+            if libc::sigaction(libc::SIGURG, &action, std::ptr::null_mut()) != 0 {
+                return None;
+            }
+        }
+        Some(previous)
+    }
+
+    pub fn capture(stacks: bool) -> Option<Vec<RawThread>> {
+        let _capture = CAPTURE.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut tids = std::fs::read_dir("/proc/self/task")
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+            .collect::<Vec<_>>();
+        tids.sort_unstable();
+
+        let previous = match stacks {
+            true => install(),
+            false => None,
+        };
+        let pid = std::process::id() as i32;
+        let current = gettid();
+        let deadline = Instant::now() + DEADLINE;
+        let threads = tids
+            .into_iter()
+            .map(|tid| RawThread {
+                id: tid.to_string(),
+                name: name_of(tid),
+                reporting: tid == current,
+                ips: match previous.is_some() && tid != current {
+                    true => stack_of(pid, tid, deadline),
+                    false => Vec::new(),
+                },
+            })
+            .collect();
+
+        if let Some(previous) = previous {
+            // SAFETY: `previous` was filled by `sigaction` in `install`.
+            unsafe { libc::sigaction(libc::SIGURG, &previous, std::ptr::null_mut()) };
+        }
+        Some(threads)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{stack_of, CAPTURE, IDLE, RECORDING, TARGET};
+        use std::sync::atomic::Ordering;
+        use std::sync::PoisonError;
+        use std::time::{Duration, Instant};
+
+        #[test]
+        fn test_unfinished_handler_keeps_frames() {
+            let _capture = CAPTURE.lock().unwrap_or_else(PoisonError::into_inner);
+            // the handler of an earlier thread that never finished
+            TARGET.store(RECORDING, Ordering::SeqCst);
+            let start = Instant::now();
+            let pid = std::process::id() as i32;
+            let tid = super::gettid();
+            assert!(stack_of(pid, tid, start + Duration::from_secs(5)).is_empty());
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert_eq!(TARGET.load(Ordering::SeqCst), RECORDING);
+            TARGET.store(IDLE, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::capture;
+
+    #[test]
+    fn test_capture() {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("worker".to_owned())
+            .spawn(move || {
+                ready_tx.send(()).unwrap();
+                rx.recv()
+            })
+            .unwrap();
+        ready_rx.recv().unwrap();
+
+        let threads = capture(false);
+        assert_eq!(threads.iter().filter(|t| t.reporting).count(), 1);
+        if cfg!(target_os = "linux") {
+            let worker = threads.iter().find(|t| t.name == "worker").unwrap();
+            assert!(worker.ips.is_empty());
+        }
+
+        let threads = capture(true);
+        assert_eq!(threads.iter().filter(|t| t.reporting).count(), 1);
+        assert!(threads.iter().find(|t| t.reporting).unwrap().ips.is_empty());
+        if cfg!(target_os = "linux") {
+            let worker = threads.iter().find(|t| t.name == "worker").unwrap();
+            assert!(!worker.ips.is_empty());
+        }
+
+        tx.send(()).unwrap();
+        worker.join().unwrap().unwrap();
+    }
+}
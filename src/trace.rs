@@ -0,0 +1,101 @@
+//! Module for tracing the decisions on the notify path, to find out why a notification
+//! never reached the dashboard. Setting the `BUGSNAG_DEBUG` environment variable logs
+//! every decision, e.g. that a notification was sampled, queued or stored offline,
+//! through the `log` crate with the target `bugsnag::trace` and the id of the event.
+//!
+//! The id of the event is kept in a thread local while the notification is processed,
+//! and handed to the worker thread with queued notifications.
+
+use log::info;
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The environment variable that enables the tracing.
+pub const ENV_VAR: &str = "BUGSNAG_DEBUG";
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+thread_local! {
+    /// The id of the event processed by the current thread.
+    static EVENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Logs a decision on the notify path if the tracing is enabled. The arguments are only
+/// formatted if it is.
+macro_rules! decision {
+    ($($arg:tt)+) => {
+        if $crate::trace::enabled() {
+            $crate::trace::log(format_args!($($arg)+));
+        }
+    };
+}
+pub(crate) use decision;
+
+/// Returns whether the tracing is enabled. The environment is read only once.
+pub fn enabled() -> bool {
+    *ENABLED.get_or_init(|| is_enabled_by(std::env::var_os(ENV_VAR).as_deref()))
+}
+
+/// Any value but an empty one, `0` and `false` enables the tracing.
+fn is_enabled_by(value: Option<&OsStr>) -> bool {
+    value.is_some_and(|v| !v.is_empty() && v != "0" && !v.eq_ignore_ascii_case("false"))
+}
+
+pub fn log(args: fmt::Arguments) {
+    EVENT.with(|event| match &*event.borrow() {
+        Some(id) => info!(target: "bugsnag::trace", "event {id}: {args}"),
+        None => info!(target: "bugsnag::trace", "{args}"),
+    });
+}
+
+/// Restores the previous event of the thread when dropped.
+pub struct EventScope {
+    previous: Option<Option<String>>,
+}
+
+impl Drop for EventScope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            EVENT.with(|event| *event.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Attributes the decisions of the current thread to `event_id` until the returned scope
+/// is dropped.
+pub fn enter(event_id: &str) -> EventScope {
+    if !enabled() {
+        return EventScope { previous: None };
+    }
+    let previous = EVENT.with(|event| event.replace(Some(event_id.to_owned())));
+    EventScope {
+        previous: Some(previous),
+    }
+}
+
+/// Returns the id of the event processed by the current thread, if the tracing is
+/// enabled.
+pub fn current_event() -> Option<String> {
+    if !enabled() {
+        return None;
+    }
+    EVENT.with(|event| event.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_enabled_by;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_is_enabled_by() {
+        assert!(!is_enabled_by(None));
+        assert!(!is_enabled_by(Some(OsStr::new(""))));
+        assert!(!is_enabled_by(Some(OsStr::new("0"))));
+        assert!(!is_enabled_by(Some(OsStr::new("FALSE"))));
+        assert!(is_enabled_by(Some(OsStr::new("1"))));
+        assert!(is_enabled_by(Some(OsStr::new("notify"))));
+    }
+}
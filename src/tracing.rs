@@ -0,0 +1,246 @@
+//! Module for reporting the events of the `tracing` crate, e.g. of every `tracing::error!`.
+//!
+//! `BugsnagLayer` is a `tracing_subscriber` layer that reports the events up to its level.
+//! The spans the event occurred in become the context, e.g. `request > checkout`, and are
+//! listed in the `tracing` tab, optionally with their fields. Like `BugsnagLogger`, the
+//! layer reports the target as class, at most 10 events per minute by default, and skips
+//! the events emitted by the reports themselves.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tracing_subscriber::layer::SubscriberExt;
+//!
+//! let api = bugsnag::Bugsnag::new("api-key", env!("CARGO_MANIFEST_DIR"));
+//! let subscriber = tracing_subscriber::registry()
+//!     .with(bugsnag::tracing::BugsnagLayer::new(&api).capture_span_fields(true));
+//! tracing::subscriber::set_global_default(subscriber).unwrap();
+//!
+//! let _span = tracing::error_span!("checkout", order = 42).entered();
+//! tracing::error!(amount = 10, "payment failed");
+//! ```
+
+use super::reentrancy::{self, Limiter, ReportingGuard};
+use super::{Bugsnag, Severity};
+use ::tracing::field::{Field, Visit};
+use ::tracing::span::{Attributes, Id, Record};
+use ::tracing::{Event, Level, Subscriber};
+use serde_json::{json, Map, Value};
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Duration;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Collects the fields of an event or span as json values. Stored in the extensions of
+/// spans if their fields are captured.
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn StdError + 'static)) {
+        self.0
+            .insert(field.name().to_owned(), json!(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), json!(format!("{value:?}")));
+    }
+}
+
+/// Layer that reports tracing events to Bugsnag.
+pub struct BugsnagLayer {
+    bugsnag: Bugsnag,
+    level: Level,
+    limiter: Limiter,
+    span_fields: bool,
+}
+
+impl BugsnagLayer {
+    /// Creates a layer that reports errors with a clone of `bugsnag`, at most 10 per
+    /// minute.
+    pub fn new(bugsnag: &Bugsnag) -> BugsnagLayer {
+        BugsnagLayer {
+            bugsnag: bugsnag.clone(),
+            level: Level::ERROR,
+            limiter: Limiter::new(10, Duration::from_secs(60)),
+            span_fields: false,
+        }
+    }
+
+    /// Sets the most verbose level that is reported.
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Reports at most `max` events per `window`, further events are dropped.
+    pub fn rate_limit(mut self, max: u32, window: Duration) -> Self {
+        self.limiter.max = max;
+        self.limiter.window = window;
+        self
+    }
+
+    /// Adds the fields of the spans to the `tracing` tab. The fields are only recorded for
+    /// spans created after the layer is installed.
+    pub fn capture_span_fields(mut self, capture: bool) -> Self {
+        self.span_fields = capture;
+        self
+    }
+}
+
+impl<S> Layer<S> for BugsnagLayer
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !self.span_fields {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Fields(Map::new());
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+                values.record(fields);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // events emitted while reporting, e.g. by the HTTP client, are not reported
+        if *metadata.level() > self.level || reentrancy::is_own_target(metadata.target()) {
+            return;
+        }
+        let Some(_guard) = ReportingGuard::enter() else {
+            return;
+        };
+        if !self.limiter.allow() {
+            return;
+        }
+
+        let mut fields = Fields(Map::new());
+        event.record(&mut fields);
+        let message = match fields.0.remove("message") {
+            Some(Value::String(message)) => message,
+            Some(message) => message.to_string(),
+            None => metadata.name().to_owned(),
+        };
+
+        let mut names = Vec::new();
+        let mut spans = Vec::new();
+        for span in ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(|s| s.from_root())
+        {
+            let mut tab = json!({"name": span.name(), "target": span.metadata().target()});
+            if let Some(fields) = span.extensions().get::<Fields>() {
+                tab["fields"] = Value::Object(fields.0.clone());
+            }
+            names.push(span.name());
+            spans.push(tab);
+        }
+        let context = match names.is_empty() {
+            true => metadata.target().to_owned(),
+            false => names.join(" > "),
+        };
+
+        let mut tab = json!({
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": fields.0,
+            "spans": spans,
+        });
+        if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
+            tab["location"] = json!(format!("{file}:{line}"));
+        }
+        let severity = match *metadata.level() {
+            Level::ERROR => Severity::Error,
+            Level::WARN => Severity::Warning,
+            _ => Severity::Info,
+        };
+
+        let mut bugsnag = self.bugsnag.clone();
+        let _ = bugsnag
+            .notify(metadata.target(), message)
+            .severity(severity)
+            .context(context)
+            .tab("tracing", tab)
+            .send();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BugsnagLayer;
+    use crate::Bugsnag;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_layer() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut api = Bugsnag::new("api-key", "my-dir");
+        let sink = captured.clone();
+        api.set_capture_callback(move |json| sink.lock().unwrap().push(json.to_owned()));
+
+        let layer = BugsnagLayer::new(&api)
+            .capture_span_fields(true)
+            .rate_limit(2, Duration::from_secs(60));
+        let subscriber = tracing_subscriber::registry().with(layer);
+        ::tracing::subscriber::with_default(subscriber, || {
+            let request = ::tracing::info_span!("request", id = ::tracing::field::Empty);
+            let _request = request.enter();
+            request.record("id", "abc");
+            let _checkout = ::tracing::info_span!("checkout", order = 42).entered();
+
+            // the default target of events in this module starts with "bugsnag"
+            ::tracing::warn!(target: "shop", "not reported");
+            ::tracing::error!(target: "bugsnag::transport", "not reported");
+            ::tracing::error!(target: "shop", amount = 10, "payment failed");
+            ::tracing::error!(target: "shop", "reported");
+            ::tracing::error!(target: "shop", "rate limited");
+        });
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 2);
+        let json: Value = serde_json::from_str(&captured[0]).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["exceptions"][0]["errorClass"], "shop");
+        assert_eq!(event["exceptions"][0]["message"], "payment failed");
+        assert_eq!(event["context"], "request > checkout");
+        let tab = &event["metaData"]["tracing"];
+        assert_eq!(tab["fields"], json!({"amount": 10}));
+        assert_eq!(tab["spans"][0]["fields"], json!({"id": "abc"}));
+        assert_eq!(tab["spans"][1]["fields"], json!({"order": 42}));
+    }
+}
@@ -0,0 +1,412 @@
+//! Module for configuring the HTTP transport used to deliver notifications.
+
+#[cfg(feature = "http")]
+use log::warn;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Function that resolves a host name to the addresses to connect to.
+pub type ResolverFn = dyn Fn(&str) -> std::io::Result<Vec<SocketAddr>> + Send + Sync;
+
+/// Function called by `TransportOptions::on_request` before each delivery attempt.
+pub type RequestHookFn = dyn Fn(&RequestInfo) + Send + Sync;
+
+/// Function called by `TransportOptions::on_response` after each delivery attempt.
+pub type ResponseHookFn = dyn Fn(&ResponseInfo) + Send + Sync;
+
+/// A delivery attempt that is about to be sent.
+#[derive(Debug, Clone)]
+pub struct RequestInfo<'a> {
+    pub endpoint: &'a str,
+    /// The number of the attempt, starting at 1 and counting the retries.
+    pub attempt: u32,
+    /// The size of the body in bytes.
+    pub body_len: usize,
+}
+
+/// The result of a delivery attempt.
+#[derive(Debug, Clone)]
+pub struct ResponseInfo<'a> {
+    pub endpoint: &'a str,
+    pub attempt: u32,
+    /// The HTTP status, or `None` if no response was received, e.g. because connecting
+    /// failed or the attempt timed out.
+    pub status: Option<u16>,
+    /// How long it took from sending the request until the response was received or the
+    /// attempt failed.
+    pub latency: Duration,
+}
+
+/// Adapter that makes a `ResolverFn` usable as a reqwest resolver.
+#[cfg(feature = "http")]
+struct Resolver(Arc<ResolverFn>);
+
+#[cfg(feature = "http")]
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let addrs = resolver(name.as_str())?;
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Builds a reqwest client of type `$client` with the transport options `$options`.
+/// The blocking and the async client builders have the same methods, but no common trait.
+#[cfg(feature = "http")]
+macro_rules! build_with_options {
+    ($options:expr, $client:ty) => {{
+        let options = $options;
+        let mut builder = <$client>::builder()
+            .tcp_keepalive(options.tcp_keepalive)
+            .pool_idle_timeout(options.pool_idle_timeout);
+
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        if options.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        if let Some(max) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+
+        for (domain, addrs) in &options.resolve_overrides {
+            builder = builder.resolve_to_addrs(domain, addrs);
+        }
+
+        if let Some(resolver) = &options.resolver {
+            builder = builder.dns_resolver(Arc::new(Resolver(resolver.clone())));
+        }
+
+        builder.build().unwrap_or_else(|e| {
+            warn!("failed to build client from transport options, using defaults: {e}");
+            <$client>::new()
+        })
+    }};
+}
+
+/// Policy for retrying deliveries that failed with a connection error or a server error,
+/// with exponential backoff.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub struct RetryPolicy {
+    attempts: u32,
+    base_delay: Duration,
+    jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Makes up to `attempts` attempts in total, waiting `base_delay` before the second
+    /// attempt and doubling the delay before each further attempt.
+    pub fn new(attempts: u32, base_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            attempts: attempts.max(1),
+            base_delay,
+            jitter: 0.0,
+        }
+    }
+
+    /// Randomizes each delay by up to the fraction `val` of its length, between `0.0` and
+    /// `1.0`, so instances that failed at the same time do not retry at the same time.
+    pub fn jitter(mut self, val: f64) -> Self {
+        self.jitter = val.clamp(0.0, 1.0);
+        self
+    }
+
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns the delay before the attempt following the `attempt`th failed one.
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        if self.jitter > 0.0 {
+            delay.mul_f64(1.0 + self.jitter * (rand::random::<f64>() * 2.0 - 1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+/// Options for the HTTP client that delivers notifications to Bugsnag.
+///
+/// The client is created once per `Bugsnag` instance (and shared with its clones), so
+/// connections to the notify endpoint are kept alive between notifications.
+#[derive(Clone)]
+pub struct TransportOptions {
+    http2_prior_knowledge: bool,
+    tcp_keepalive: Option<Duration>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
+    resolve_overrides: Vec<(String, Vec<SocketAddr>)>,
+    resolver: Option<Arc<ResolverFn>>,
+    request_hook: Option<Arc<RequestHookFn>>,
+    response_hook: Option<Arc<ResponseHookFn>>,
+    #[cfg(feature = "testing")]
+    failure_injection: Option<crate::testing::FailureInjection>,
+}
+
+impl fmt::Debug for TransportOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransportOptions")
+            .field("http2_prior_knowledge", &self.http2_prior_knowledge)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("timeout", &self.timeout)
+            .field("retry", &self.retry)
+            .field("resolve_overrides", &self.resolve_overrides)
+            .field("resolver", &self.resolver.is_some())
+            .field("request_hook", &self.request_hook.is_some())
+            .field("response_hook", &self.response_hook.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for TransportOptions {
+    fn default() -> TransportOptions {
+        TransportOptions {
+            http2_prior_knowledge: false,
+            tcp_keepalive: None,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            pool_max_idle_per_host: None,
+            connect_timeout: None,
+            timeout: None,
+            retry: None,
+            resolve_overrides: Vec::new(),
+            resolver: None,
+            request_hook: None,
+            response_hook: None,
+            #[cfg(feature = "testing")]
+            failure_injection: None,
+        }
+    }
+}
+
+impl TransportOptions {
+    /// Creates the default transport options.
+    pub fn new() -> TransportOptions {
+        TransportOptions::default()
+    }
+
+    /// Fails deliveries as configured by `injection`, for testing.
+    #[cfg(feature = "testing")]
+    pub fn failure_injection(mut self, injection: crate::testing::FailureInjection) -> Self {
+        self.failure_injection = Some(injection);
+        self
+    }
+
+    /// Checks if the delivery of `json` should fail because of a failure injection.
+    #[cfg(feature = "testing")]
+    pub(crate) fn injects_failure(&self, json: &str) -> bool {
+        self.failure_injection
+            .as_ref()
+            .is_some_and(|f| f.should_fail(json))
+    }
+
+    /// Only speak HTTP/2 to the endpoint, without negotiating the protocol first.
+    /// Without this option HTTP/2 is still used when the endpoint offers it via ALPN.
+    pub fn http2_prior_knowledge(mut self, val: bool) -> Self {
+        self.http2_prior_knowledge = val;
+        self
+    }
+
+    /// Sets `SO_KEEPALIVE` with the given interval on all sockets.
+    pub fn tcp_keepalive(mut self, val: Option<Duration>) -> Self {
+        self.tcp_keepalive = val;
+        self
+    }
+
+    /// Sets how long an idle connection is kept in the pool. `None` keeps them forever.
+    pub fn pool_idle_timeout(mut self, val: Option<Duration>) -> Self {
+        self.pool_idle_timeout = val;
+        self
+    }
+
+    /// Sets the maximum number of idle connections kept per host.
+    pub fn pool_max_idle_per_host(mut self, val: usize) -> Self {
+        self.pool_max_idle_per_host = Some(val);
+        self
+    }
+
+    /// Sets how long connecting to the endpoint may take.
+    pub fn connect_timeout(mut self, val: Option<Duration>) -> Self {
+        self.connect_timeout = val;
+        self
+    }
+
+    /// Sets how long a delivery may take in total, from connecting until the response
+    /// was read. `None` uses the default of reqwest.
+    pub fn timeout(mut self, val: Option<Duration>) -> Self {
+        self.timeout = val;
+        self
+    }
+
+    /// Retries failed deliveries as configured by `val`. `None` stores a notification
+    /// offline after the first failed attempt.
+    pub fn retry(mut self, val: Option<RetryPolicy>) -> Self {
+        self.retry = val;
+        self
+    }
+
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+
+    /// Resolves `domain` to the given addresses instead of asking DNS.
+    /// A port of `0` is replaced by the port of the endpoint URL.
+    pub fn resolve(mut self, domain: &str, addrs: &[SocketAddr]) -> Self {
+        self.resolve_overrides
+            .push((domain.to_owned(), addrs.to_vec()));
+        self
+    }
+
+    /// Uses the given function to resolve host names instead of the system resolver.
+    /// Addresses set with `resolve` still take precedence. The function is called from
+    /// the transport's worker thread and should not block for long.
+    pub fn dns_resolver<F>(mut self, resolver: F) -> Self
+    where
+        F: Fn(&str) -> std::io::Result<Vec<SocketAddr>> + Send + Sync + 'static,
+    {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Calls `hook` before each attempt to deliver a notification, e.g. to start a span
+    /// of the application's tracing. The hook runs on the delivering thread and should
+    /// not block.
+    pub fn on_request<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&RequestInfo) + Send + Sync + 'static,
+    {
+        self.request_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Calls `hook` after each attempt to deliver a notification, with the status and the
+    /// latency, e.g. to feed the application's own metrics. The hook runs on the
+    /// delivering thread and should not block.
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ResponseInfo) + Send + Sync + 'static,
+    {
+        self.response_hook = Some(Arc::new(hook));
+        self
+    }
+
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn request_sent(&self, info: &RequestInfo) {
+        if let Some(hook) = &self.request_hook {
+            hook(info);
+        }
+    }
+
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn response_received(&self, info: &ResponseInfo) {
+        if let Some(hook) = &self.response_hook {
+            hook(info);
+        }
+    }
+
+    /// Builds the blocking HTTP client for these options.
+    #[cfg(feature = "http")]
+    pub(crate) fn build_client(&self) -> reqwest::blocking::Client {
+        build_with_options!(self, reqwest::blocking::Client)
+    }
+
+    /// Builds the async HTTP client for these options.
+    #[cfg(all(feature = "http", feature = "tokio"))]
+    pub(crate) fn build_async_client(&self) -> reqwest::Client {
+        build_with_options!(self, reqwest::Client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RetryPolicy, TransportOptions};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_default_transport_options() {
+        let opts = TransportOptions::new();
+
+        assert!(!opts.http2_prior_knowledge);
+        assert_eq!(opts.tcp_keepalive, None);
+        assert_eq!(opts.pool_idle_timeout, Some(Duration::from_secs(90)));
+        assert_eq!(opts.pool_max_idle_per_host, None);
+    }
+
+    #[test]
+    fn test_transport_options_builder() {
+        let opts = TransportOptions::new()
+            .http2_prior_knowledge(true)
+            .tcp_keepalive(Some(Duration::from_secs(30)))
+            .pool_idle_timeout(None)
+            .pool_max_idle_per_host(2)
+            .connect_timeout(Some(Duration::from_secs(2)))
+            .timeout(Some(Duration::from_secs(5)));
+
+        assert!(opts.http2_prior_knowledge);
+        assert_eq!(opts.tcp_keepalive, Some(Duration::from_secs(30)));
+        assert_eq!(opts.pool_idle_timeout, None);
+        assert_eq!(opts.pool_max_idle_per_host, Some(2));
+        assert_eq!(opts.connect_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(opts.timeout, Some(Duration::from_secs(5)));
+
+        #[cfg(feature = "http")]
+        opts.build_client();
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(100));
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(RetryPolicy::new(0, Duration::ZERO).attempts(), 1);
+
+        let delay = policy.jitter(0.5).delay(2);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_transport_options_resolve() {
+        let addr: SocketAddr = "10.0.0.1:0".parse().unwrap();
+        let opts = TransportOptions::new()
+            .resolve("notify.bugsnag.com", &[addr])
+            .dns_resolver(|_| Ok(vec!["10.0.0.2:443".parse().unwrap()]));
+
+        assert_eq!(
+            opts.resolve_overrides,
+            vec![("notify.bugsnag.com".to_owned(), vec![addr])]
+        );
+        assert_eq!(
+            (opts.resolver.as_ref().unwrap())("relay.local").unwrap(),
+            vec!["10.0.0.2:443".parse::<SocketAddr>().unwrap()]
+        );
+
+        #[cfg(feature = "http")]
+        opts.build_client();
+    }
+}
@@ -0,0 +1,145 @@
+//! Module for uploading reports from the offline storage from a separate process.
+
+use super::{envelope, notification, DeliveryStatus, Error};
+use log::warn;
+use std::path::Path;
+
+/// Transmits the reports stored in `dir` to `endpoint`, e.g. by an uploader process for
+/// reports written by capture-only builds or by processes that crashed before sending.
+/// The reports are sent with their stored headers, `api_key` is used for reports that
+/// were stored without one. Uploaded reports and reports rejected as invalid are removed.
+/// The upload stops at the first report that Bugsnag did not accept for now, e.g. because
+/// of its rate limit, which is kept. Reports stored several times are sent once, with the
+/// number of copies in the `offline` tab. The time of events recorded since the last boot
+/// is corrected by their monotonic clock reading, for devices with a wrong real time clock.
+/// Returns the number of uploaded reports.
+///
+/// # Example
+///
+/// ```no_run
+/// let uploaded = bugsnag::upload_stored_reports(
+///     "/var/spool/bugsnag",
+///     "api-key",
+///     "https://notify.bugsnag.com",
+/// );
+/// ```
+pub fn upload_stored_reports(dir: &str, api_key: &str, endpoint: &str) -> Result<usize, Error> {
+    let reports =
+        envelope::read_stored_reports(Path::new(dir)).map_err(|_| Error::OfflineStorageError)?;
+    let client = reqwest::blocking::Client::new();
+    let mut uploaded = 0;
+
+    for report in reports {
+        let (headers, json) = report.split();
+        let headers = envelope::complete_headers(
+            headers,
+            &[
+                ("Bugsnag-Api-Key", api_key),
+                ("Bugsnag-Payload-Version", notification::PAYLOAD_VERSION),
+            ],
+        );
+
+        let mut request = client
+            .post(endpoint)
+            .body(json.into_owned())
+            .header("Content-Type", "application/json");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().map_err(|_| Error::JsonTransferFailed)?;
+        let retry_after = response.headers().get("Retry-After");
+        match DeliveryStatus::from_response(
+            response.status().as_u16(),
+            retry_after.and_then(|v| v.to_str().ok()),
+        ) {
+            DeliveryStatus::Delivered => uploaded += report.paths.len(),
+            DeliveryStatus::RejectedBadRequest => {
+                warn!("Removing stored report that was rejected by Bugsnag.");
+            }
+            status => return Err(Error::Rejected(status)),
+        }
+        for path in &report.paths {
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    Ok(uploaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::upload_stored_reports;
+    use crate::{envelope, DeliveryStatus, Error};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_upload_stored_reports() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_upload_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join(format!("{}_1", envelope::REPORT_PREFIX)),
+            envelope::wrap(&[("Bugsnag-Api-Key", "stored-key")], "{}"),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !request.ends_with(b"{}") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap().to_lowercase()
+        });
+
+        assert_eq!(
+            upload_stored_reports(dir.to_str().unwrap(), "api-key", &url),
+            Ok(1)
+        );
+        let request = server.join().unwrap();
+        assert!(request.contains("bugsnag-api-key: stored-key"));
+        assert!(request.contains("bugsnag-payload-version: 5"));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_upload_keeps_reports_on_server_error() {
+        let dir = std::env::temp_dir().join(format!("bugsnag_upload_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join(format!("{}_1", envelope::REPORT_PREFIX)),
+            envelope::wrap(&[("Bugsnag-Api-Key", "stored-key")], "{}"),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 65536];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                .unwrap();
+        });
+
+        assert_eq!(
+            upload_stored_reports(dir.to_str().unwrap(), "api-key", &url),
+            Err(Error::Rejected(DeliveryStatus::ServerError))
+        );
+        server.join().unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
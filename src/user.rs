@@ -1,5 +1,9 @@
+use super::Error;
 use serde::Serialize;
 
+/// Maximum length in bytes of the fields of a user, longer values are truncated.
+pub const MAX_FIELD_LEN: usize = 1024;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -10,18 +14,46 @@ pub struct User {
     email: Option<String>,
 }
 
+/// Trims a field and caps its length. Empty fields are dropped.
+fn sanitize(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    let mut end = value.len().min(MAX_FIELD_LEN);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(value[..end].trim_end().to_string())
+}
+
+/// Checks for the `local@domain.tld` form, without trying to implement the full RFC.
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !domain.contains('@')
+        && !email.contains(char::is_whitespace)
+        && domain
+            .split_once('.')
+            .is_some_and(|(host, tld)| !host.is_empty() && !tld.is_empty() && !tld.ends_with('.'))
+}
+
 impl User {
     pub fn new(id: &str, name: &str, email: &str) -> Self {
         Self {
-            id: Some(id.to_string()),
-            name: Some(name.to_string()),
-            email: Some(email.to_string()),
+            id: sanitize(id),
+            name: sanitize(name),
+            email: sanitize(email),
         }
     }
 
     pub fn new_id(id: &str) -> Self {
         Self {
-            id: Some(id.to_string()),
+            id: sanitize(id),
             name: None,
             email: None,
         }
@@ -30,7 +62,7 @@ impl User {
     pub fn new_name(name: &str) -> Self {
         Self {
             id: None,
-            name: Some(name.to_string()),
+            name: sanitize(name),
             email: None,
         }
     }
@@ -39,19 +71,71 @@ impl User {
         Self {
             id: None,
             name: None,
-            email: Some(email.to_string()),
+            email: sanitize(email),
         }
     }
 
     pub fn id(&mut self, id: &str) {
-        self.id = Some(id.to_string());
+        self.id = sanitize(id);
     }
 
     pub fn name(&mut self, name: &str) {
-        self.name = Some(name.to_string());
+        self.name = sanitize(name);
     }
 
     pub fn email(&mut self, email: &str) {
-        self.email = Some(email.to_string());
+        self.email = sanitize(email);
+    }
+
+    /// Replaces every field that is set by the result of `f`.
+    pub(crate) fn map_fields(&mut self, f: impl Fn(&str) -> String) {
+        let mut fields = [self.id.as_mut(), self.name.as_mut(), self.email.as_mut()];
+        for value in fields.iter_mut().flatten() {
+            **value = f(value);
+        }
+    }
+
+    /// Checks that the email address, if set, is well-formed.
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.email {
+            Some(email) if !is_valid_email(email) => Err(Error::InvalidUserEmail),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{User, MAX_FIELD_LEN};
+    use crate::Error;
+    use serde_json::json;
+
+    #[test]
+    fn test_user_fields_are_sanitized() {
+        let user = User::new("  42 ", "", "  ");
+        assert_eq!(serde_json::to_value(&user).unwrap(), json!({"id": "42"}));
+
+        let user = User::new_name(&"ä".repeat(MAX_FIELD_LEN));
+        assert_eq!(user.name.unwrap().len(), MAX_FIELD_LEN);
+    }
+
+    #[test]
+    fn test_validate_email() {
+        assert!(User::new_email("jane@example.com").validate().is_ok());
+        assert!(User::new_id("42").validate().is_ok());
+
+        for email in [
+            "jane",
+            "jane@",
+            "@example.com",
+            "jane@example",
+            "a b@c.d",
+            "a@b@c.d",
+        ] {
+            assert_eq!(
+                User::new_email(email).validate(),
+                Err(Error::InvalidUserEmail)
+            );
+        }
     }
 }